@@ -31,15 +31,48 @@ window.onFileLoad = (event) => {
         const input = event.target.files[0];
         const fileReader = new FileReader();
         fileReader.onloadend = e => {
-                const jsValue = Array.from(new Uint8Array(fileReader.result));
-                gameboy.load(jsValue);
+                try {
+                        gameboy.load(new Uint8Array(fileReader.result));
+                } catch (e) {
+                        alert(e.message);
+                        return;
+                }
+                const header = gameboy.get_header();
+                document.title = header.title ? "RustyBoy - " + header.title : "RustyBoy";
+                if (!header.logo_valid || !header.header_checksum_valid) {
+                        console.warn("The cartridge header is invalid, this is likely a bad dump", header);
+                }
                 gameboy.reset();
+                loadBatterySave();
+                window.runningFlag = true;
                 window.runRustyBoy();
         };
         fileReader.readAsArrayBuffer(input);
         setupSound();
 }
 
+// Battery backed ram is kept in local storage, keyed by the rom checksum
+const saveKey = () => "rustyboy-save-" + gameboy.rom_checksum().toString(16);
+
+const loadBatterySave = () => {
+        if (!gameboy.has_battery()) return;
+        const save = localStorage.getItem(saveKey());
+        if (save === null) return;
+        try {
+                gameboy.import_save(Uint8Array.from(atob(save), c => c.charCodeAt(0)));
+        } catch (e) {
+                console.error(e);
+        }
+}
+
+const flushBatterySave = () => {
+        if (!gameboy.is_save_dirty()) return;
+        const save = gameboy.export_save();
+        let binary = "";
+        for (let i = 0; i < save.length; i++) binary += String.fromCharCode(save[i]);
+        localStorage.setItem(saveKey(), btoa(binary));
+}
+
 let canvas = document.getElementById('screen');
 window.runningFlag = true;
 
@@ -48,10 +81,18 @@ window.screen = new Screen(canvas);
 window.runRustyBoy = () => {
         setTimeout(function() {
                 if (runningFlag) requestAnimationFrame(window.runRustyBoy);
-                window.gameboy.execute_ticks(27756);
+                try {
+                        window.gameboy.execute_ticks(27756);
+                } catch (e) {
+                        window.runningFlag = false;
+                        console.error(e);
+                        return;
+                }
                 let frame = window.gameboy.get_frame();
                 let buffer = screen.createBuffer();
                 buffer.data.set(frame);
                 screen.render(buffer);
+                window.playSamples(window.gameboy.get_sound());
+                flushBatterySave();
         }, 1000 / 60);
 }
@@ -35,3 +35,28 @@ window.playTone = () => {
 window.volume = (val) => {
     masterGainNode.gain.setValueAtTime(val, audioCtx.currentTime);
 }
+
+// Samples from the emulator are interleaved stereo (left, right) at 44.1kHz
+const SAMPLE_RATE = 44100;
+let nextPlayTime = 0;
+
+window.playSamples = (samples) => {
+    if (!window.audioCtx || !window.masterGainNode || samples.length === 0) return;
+
+    let length = samples.length / 2;
+    let buffer = audioCtx.createBuffer(2, length, SAMPLE_RATE);
+    let left = buffer.getChannelData(0);
+    let right = buffer.getChannelData(1);
+    for (let i = 0; i < length; i++) {
+        left[i] = samples[i * 2];
+        right[i] = samples[i * 2 + 1];
+    }
+
+    let source = audioCtx.createBufferSource();
+    source.buffer = buffer;
+    source.connect(window.masterGainNode);
+
+    nextPlayTime = Math.max(nextPlayTime, audioCtx.currentTime);
+    source.start(nextPlayTime);
+    nextPlayTime += buffer.duration;
+}
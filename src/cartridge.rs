@@ -1,55 +1,39 @@
-extern crate serde_json;
-extern crate wasm_bindgen;
-
-use std::fs;
-use std::convert::TryInto;
-use std::fmt;
 use crate::console::GameboyType;
-use wasm_bindgen::prelude::*;
-
-pub const HEADER_INDEX_FOR_CARTRIDGE_TYPE: usize = 0x0147;
-
-// Logging
-#[wasm_bindgen]
-extern "C" {
-    #[wasm_bindgen(js_namespace = console)]
-    fn log(s: &str);
-
-    #[wasm_bindgen(js_namespace = console, js_name = log)]
-    fn log_u32(a: u32);
+use crate::error::EmulatorError;
+use crate::state::{StateWriter, StateReader, crc32};
+use crate::rtc::{Rtc, unix_time};
+use crate::header::CartridgeHeader;
 
-    #[wasm_bindgen(js_namespace = console, js_name = log)]
-    fn log_u8(a: u8);
-
-    #[wasm_bindgen(js_namespace = console, js_name = log)]
-    fn log_usize(a: usize);
-
-    #[wasm_bindgen(js_namespace = console, js_name = log)]
-    fn log_many(a: &str, b: &str);
-}
+// MBC2 has 512 x 4 bits of ram built in, the header declares no ram
+const MBC2_RAM_SIZE: usize = 0x200;
 
-
-#[wasm_bindgen]
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum CartridgeType {
     None,
     MBC0,
     MBC1,
-    //MBC2,
+    MBC2,
     MBC3,
     //MBC4,
     MBC5,
 }
 
-#[wasm_bindgen]
 pub struct Cartridge {
     pub cartridge_type: CartridgeType,
+    header: Option<CartridgeHeader>,
     rom: Vec<u8>,
     rom_bank: usize,
     ram: Vec<u8>,
     ram_on: bool,
     ram_mode: bool,
     ram_bank: usize,
+    // MBC1M, the upper bank bits are wired one bit lower
+    multicart: bool,
+    checksum: u32,
+    battery: bool,
+    rtc: Option<Rtc>,
+    // Set when battery backed ram is written, cleared when the save is exported
+    ram_dirty: bool,
 }
 
 
@@ -57,34 +41,220 @@ impl Cartridge {
 
     pub fn new() -> Self {
         Self {
+            header: None,
             rom: vec![0; 1],
             rom_bank: 1,
-            ram: vec![0; 0x20000],
+            ram: Vec::new(),
             ram_on: false,
             ram_mode: false,
             ram_bank: 0,
+            multicart: false,
+            checksum: 0,
+            battery: false,
+            rtc: None,
+            ram_dirty: false,
             cartridge_type: CartridgeType::None
         }
     }
 
-    pub fn set_rom(&mut self, rom: Vec<u8>) {
+    pub fn set_rom(&mut self, rom: Vec<u8>, header: CartridgeHeader) {
+        self.checksum = crc32(&rom);
+        self.battery = has_battery(header.cartridge_type);
+        self.ram = match self.cartridge_type {
+            CartridgeType::MBC2 => vec![0; MBC2_RAM_SIZE],
+            _ => vec![0; header.ram_size.unwrap_or(0)],
+        };
+        self.rtc = if has_rtc(header.cartridge_type) { Some(Rtc::new()) } else { None };
+        self.multicart = self.cartridge_type == CartridgeType::MBC1 && is_multicart(&rom);
+        self.ram_dirty = false;
+        self.header = Some(header);
         self.rom = rom;
     }
 
-    pub fn rom_dump(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:x?}", self.rom)
+    pub fn get_header(&self) -> Option<&CartridgeHeader> {
+        return self.header.as_ref();
+    }
+
+    pub fn has_battery(&self) -> bool {
+        return self.battery;
+    }
+
+    pub fn is_ram_dirty(&self) -> bool {
+        return self.ram_dirty;
+    }
+
+    // Contents of the battery backed ram, empty when the cartridge has no battery.
+    // Cartridges with a clock get the rtc trailer appended, stamped with the current time.
+    pub fn export_save(&mut self) -> Vec<u8> {
+        self.ram_dirty = false;
+        if !self.battery {
+            return Vec::new();
+        }
+        let mut save = self.ram.clone();
+        if let Some(rtc) = &self.rtc {
+            save.extend(rtc.export_trailer(unix_time()));
+        }
+        return save;
+    }
+
+    // The rtc trailer is optional, without it the clock keeps its current time. Cartridges
+    // without a battery only take the empty save export_save gives for them.
+    pub fn import_save(&mut self, data: &[u8]) -> Result<(), EmulatorError> {
+        if !self.battery {
+            if !data.is_empty() {
+                return Err(EmulatorError::InvalidSave { expected: 0, actual: data.len() });
+            }
+            return Ok(());
+        }
+        let expected = self.ram.len();
+        let has_trailer = self.rtc.is_some() && data.len() > expected && Rtc::is_trailer_size(data.len() - expected);
+        if data.len() != expected && !has_trailer {
+            return Err(EmulatorError::InvalidSave { expected, actual: data.len() });
+        }
+
+        self.ram.copy_from_slice(&data[.. expected]);
+        if let (true, Some(rtc)) = (has_trailer, &mut self.rtc) {
+            rtc.import_trailer(&data[expected ..], unix_time());
+        }
+        self.ram_dirty = false;
+        return Ok(());
+    }
+
+    // Ticks are in normal speed clock cycles
+    pub fn execute_ticks(&mut self, ticks: u32) {
+        if let Some(rtc) = &mut self.rtc {
+            rtc.execute_ticks(ticks);
+        }
+    }
+
+    // Banks wrap around when the cartridge has less ram than the mapper can address
+    fn ram_index(&self, ram_bank: usize, addr: u16) -> Option<usize> {
+        if self.ram.is_empty() {
+            return None;
+        }
+        return Some(((ram_bank * 0x2000) | ((addr as usize) & 0x1FFF)) % self.ram.len());
+    }
+
+    fn read_ram(&self, ram_bank: usize, addr: u16) -> u8 {
+        return match self.ram_index(ram_bank, addr) {
+            Some(index) => self.ram[index],
+            None => 0xFF,
+        };
+    }
+
+    fn write_ram(&mut self, ram_bank: usize, addr: u16, value: u8) {
+        if let Some(index) = self.ram_index(ram_bank, addr) {
+            self.ram[index] = value;
+            self.ram_dirty |= self.battery;
+        }
+    }
+
+    // CRC32 of the whole rom
+    pub fn get_checksum(&self) -> u32 {
+        return self.checksum;
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u32(self.rom_bank as u32);
+        writer.write_bytes(&self.ram);
+        writer.write_bool(self.ram_on);
+        writer.write_bool(self.ram_mode);
+        writer.write_u32(self.ram_bank as u32);
+
+        // Since 1.1
+        writer.write_bool(self.rtc.is_some());
+        if let Some(rtc) = &self.rtc {
+            rtc.save_state(writer);
+        }
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<(), EmulatorError> {
+        self.rom_bank = reader.read_u32()? as usize;
+        reader.read_bytes_into(&mut self.ram)?;
+        self.ram_on = reader.read_bool()?;
+        self.ram_mode = reader.read_bool()?;
+        self.ram_bank = reader.read_u32()? as usize;
+        if reader.minor() >= 1 && reader.read_bool()? {
+            match &mut self.rtc {
+                Some(rtc) => rtc.load_state(reader)?,
+                None => return Err(reader.invalid("rtc")),
+            }
+        }
+        // Before 1.2 the MBC1 upper bank bits were merged into rom_bank
+        if reader.minor() < 2 && self.cartridge_type == CartridgeType::MBC1 {
+            self.ram_bank = (self.rom_bank >> 5) & 0x03;
+            self.rom_bank = match self.rom_bank & 0x1F { 0 => 1, n => n };
+        }
+        // The ram no longer matches what the frontend last persisted
+        self.ram_dirty = self.battery;
+        return Ok(());
+    }
+
+    // Bank numbers larger than the rom wrap around, roms are a power of two in size
+    fn read_rom(&self, rom_bank: usize, addr: u16) -> u8 {
+        let rom_banks = (self.rom.len() / 0x4000).max(1);
+        let index = (rom_bank % rom_banks) * 0x4000 | ((addr as usize) & 0x3FFF);
+        return *self.rom.get(index).unwrap_or(&0);
+    }
+
+    // https://gbdev.io/pandocs/#mbc1
+    // BANK2 is used for 0x0000 - 0x3FFF only in mode 1, and always for 0x4000 - 0x7FFF
+    fn mbc1_rom_bank(&self, addr: u16) -> usize {
+        let (shift, mask) = if self.multicart { (4, 0x0F) } else { (5, 0x1F) };
+        return match addr {
+            0x0000 ..= 0x3FFF => if self.ram_mode { self.ram_bank << shift } else { 0 },
+            _ => (self.ram_bank << shift) | (self.rom_bank & mask),
+        };
+    }
+
+    // Rom bank mapped at the address, as used by read_byte
+    pub fn get_rom_bank(&self, addr: u16) -> usize {
+        return match self.cartridge_type {
+            CartridgeType::MBC1 => self.mbc1_rom_bank(addr) % (self.rom.len() / 0x4000).max(1),
+            _ if addr < 0x4000 => 0,
+            CartridgeType::None | CartridgeType::MBC0 => 1,
+            _ => self.rom_bank,
+        };
+    }
+
+    // Offset in the rom of the byte read_byte returns at the address, None outside of the rom
+    pub fn rom_offset(&self, addr: u16) -> Option<usize> {
+        if addr >= 0x8000 || self.cartridge_type == CartridgeType::None {
+            return None;
+        }
+        let offset = self.get_rom_bank(addr) * 0x4000 | ((addr as usize) & 0x3FFF);
+        return if offset < self.rom.len() { Some(offset) } else { None };
+    }
+
+    pub fn rom_size(&self) -> usize {
+        return self.rom.len();
     }
 
     pub fn read_byte(&self, addr: u16) -> u8 {
         match self.cartridge_type {
             CartridgeType::None => 0,
-            CartridgeType::MBC0 => { self.rom[addr as usize] },
-            CartridgeType::MBC1 | CartridgeType::MBC5 => {
+            CartridgeType::MBC0 => {
+                match addr {
+                    0xA000 ..= 0xBFFF => { self.read_ram(0, addr) },
+                    // Roms with an unknown size code can be shorter than the 32KB they map
+                    _ => { self.rom.get(addr as usize).copied().unwrap_or(0xFF) },
+                }
+            },
+            CartridgeType::MBC1 => {
                 match addr {
                     0xA000 ..= 0xBFFF => {
                         if !self.ram_on { return 0 }
                         let ram_bank = if self.ram_mode { self.ram_bank } else { 0 };
-                        self.ram[(ram_bank * 0x2000) | ((addr & 0x1FFF) as usize)]
+                        self.read_ram(ram_bank, addr)
+                    },
+                    _ => { self.read_rom(self.mbc1_rom_bank(addr), addr) }
+                }
+            },
+            CartridgeType::MBC5 => {
+                match addr {
+                    0xA000 ..= 0xBFFF => {
+                        if !self.ram_on { return 0 }
+                        self.read_ram(self.ram_bank, addr)
                     }
                     _ => {
                         let index = if addr < 0x4000 { addr as usize }
@@ -94,14 +264,29 @@ impl Cartridge {
                     }
                 }
             },
+            CartridgeType::MBC2 => {
+                match addr {
+                    // Only the lower nibble is stored, 0xA200 - 0xBFFF echo the 512 bytes
+                    0xA000 ..= 0xBFFF => {
+                        if !self.ram_on { return 0xFF }
+                        self.ram[(addr as usize) & 0x1FF] | 0xF0
+                    },
+                    _ => {
+                        let index = if addr < 0x4000 { addr as usize }
+                        else  { self.rom_bank  * 0x4000 | ((addr as usize) & 0x3FFF) };
+
+                        return *self.rom.get(index).unwrap_or(&0);
+                    }
+                }
+            },
             CartridgeType::MBC3 => {
                 match addr {
                     0xA000 ..= 0xBFFF => {
                         if !self.ram_on { return 0 }
-                        if self.ram_bank <= 3 {
-                            self.ram[self.ram_bank * 0x2000 | ((addr as usize) & 0x1FFF)]
-                        } else {
-                            0
+                        match (self.ram_bank, &self.rtc) {
+                            (0x00 ..= 0x03, _) => self.read_ram(self.ram_bank, addr),
+                            (0x08 ..= 0x0C, Some(rtc)) => rtc.read_byte(self.ram_bank),
+                            _ => 0,
                         }
                     },
                     _ => { // Rom
@@ -118,29 +303,45 @@ impl Cartridge {
     pub fn write_byte(&mut self, addr: u16, value: u8) {
         match self.cartridge_type {
             CartridgeType::None => {},
-            CartridgeType::MBC0 => {},
+            CartridgeType::MBC0 => {
+                if let 0xA000 ..= 0xBFFF = addr {
+                    self.write_ram(0, addr, value);
+                }
+            },
             CartridgeType::MBC1 => {
                 match addr {
                     0x0000 ..= 0x1FFF => { self.ram_on = value == 0x0A; },
-                    0x2000 ..= 0x3FFF => {
-                        self.rom_bank = (self.rom_bank & 0x60) | match (value as usize) & 0x1F { 0 => 1, n => n }
-                    },
-                    0x4000 ..= 0x5FFF => {
-                        if !self.ram_mode {
-                            self.rom_bank = self.rom_bank & 0x1F | (((value as usize) & 0x03) << 5)
-                        } else {
-                            self.rom_bank = (value as usize) & 0x03;
-                        }
-                    },
+                    // BANK1, zero is checked on all 5 bits even when fewer are used
+                    0x2000 ..= 0x3FFF => { self.rom_bank = match (value as usize) & 0x1F { 0 => 1, n => n }; },
+                    // BANK2, the upper rom bank bits and the ram bank
+                    0x4000 ..= 0x5FFF => { self.ram_bank = (value as usize) & 0x03; },
                     0x6000 ..= 0x7FFF => { self.ram_mode = (value & 0x01) == 0x01; },
                     0xA000 ..= 0xBFFF => {
                         if !self.ram_on { return }
                         let ram_bank = if self.ram_mode { self.ram_bank } else { 0 };
-                        self.ram[(ram_bank * 0x2000) | ((addr & 0x1FFF) as usize)] = value;
+                        self.write_ram(ram_bank, addr, value);
                     }
                     _ => panic!("error"),
                 }
             },
+            CartridgeType::MBC2 => {
+                match addr {
+                    // Bit 8 of the address selects between ram enable and rom bank
+                    0x0000 ..= 0x3FFF => {
+                        if addr & 0x0100 == 0 {
+                            self.ram_on = value & 0x0F == 0x0A;
+                        } else {
+                            self.rom_bank = match value & 0x0F { 0 => 1, n => n as usize };
+                        }
+                    },
+                    0x4000 ..= 0x7FFF => {},
+                    0xA000 ..= 0xBFFF => {
+                        if !self.ram_on { return }
+                        self.write_ram(0, addr & 0x01FF, value & 0x0F);
+                    },
+                    _ => panic!("error"),
+                }
+            },
             CartridgeType::MBC5 => {
                 match addr {
                     0x0000 ..= 0x1FFF => { self.ram_on = value == 0x0A; },
@@ -150,7 +351,7 @@ impl Cartridge {
                     0x6000 ..= 0x7FFF => {}
                     0xA000 ..= 0xBFFF => {
                         if self.ram_on == false { return }
-                        self.ram[self.ram_bank * 0x2000 | ((addr as usize) & 0x1FFF)] = value;
+                        self.write_ram(self.ram_bank, addr, value);
                     }
                     _ => panic!("error"),
                 }
@@ -160,10 +361,21 @@ impl Cartridge {
                     0x0000 ..= 0x1FFF => { self.ram_on = value == 0x0A; },
                     0x2000 ..= 0x3FFF => self.rom_bank = match value & 0x7F { 0 => 1, n => n as usize },
                     0x4000 ..= 0x5FFF => self.ram_bank = value as usize,
-                    0x6000 ..= 0x7FFF => { },
+                    0x6000 ..= 0x7FFF => {
+                        if let Some(rtc) = &mut self.rtc {
+                            rtc.write_latch(value);
+                        }
+                    },
                     0xA000 ..= 0xBFFF => {
                         if self.ram_on == false { return }
-                        self.ram[self.ram_bank * 0x2000 | ((addr as usize) & 0x1FFF)] = value;
+                        match (self.ram_bank, &mut self.rtc) {
+                            (0x00 ..= 0x03, _) => self.write_ram(self.ram_bank, addr, value),
+                            (0x08 ..= 0x0C, Some(rtc)) => {
+                                rtc.write_byte(self.ram_bank, value);
+                                self.ram_dirty |= self.battery;
+                            },
+                            _ => {},
+                        }
                     }
                     _ => panic!("error"),
                 }
@@ -176,3 +388,18 @@ impl Cartridge {
         return if mode_byte == 0x80 || mode_byte == 0xc0 { GameboyType::COLOR } else { GameboyType::CLASSIC };
     }
 }
+
+// MBC1M carts are 8Mbit and have a second game with its own header at bank 0x10
+fn is_multicart(rom: &[u8]) -> bool {
+    return rom.len() == 0x100000 && CartridgeHeader::parse(&rom[0x40000 ..]).is_ok_and(|header| header.logo_valid);
+}
+
+// MBC3+TIMER+BATTERY and MBC3+TIMER+RAM+BATTERY
+fn has_rtc(cartridge_type: u8) -> bool {
+    return matches!(cartridge_type, 0x0F | 0x10);
+}
+
+// https://gbdev.io/pandocs/#_0147-cartridge-type
+fn has_battery(cartridge_type: u8) -> bool {
+    return matches!(cartridge_type, 0x03 | 0x06 | 0x09 | 0x0D | 0x0F | 0x10 | 0x13 | 0x1B | 0x1E | 0x22 | 0xFF);
+}
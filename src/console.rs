@@ -1,38 +1,151 @@
-use crate::cpu::Cpu;
+use crate::cpu::{Cpu, CpuState, Registers};
 use crate::mmu::Mmu;
-use web_sys::CanvasRenderingContext2d;
+use crate::joypad::Button;
+use crate::error::EmulatorError;
+use crate::header::CartridgeHeader;
+use crate::serial::SerialDevice;
+use crate::disassembler::{self, Instruction};
+use crate::debugger::{PauseReason, Watchpoint};
+use crate::symbols::SymbolTable;
+use crate::logger::{TraceSink, TraceFilter, Tracer};
+use crate::profiler::Profiler;
+use crate::state::{StateWriter, SaveState, SECTION_CPU};
+#[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::*;
-use console_error_panic_hook;
-use js_sys;
-use crate::joypad::{Joypad, Button};
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum GameboyType {
     COLOR,
     CLASSIC
 }
 
-#[wasm_bindgen]
 pub struct Console {
     cpu: Cpu,
     mmu: Mmu,
+    // Labels of the loaded rom for debugging, not part of the state
+    symbols: SymbolTable,
 }
 
-#[wasm_bindgen]
 impl Console {
 
     pub fn new() -> Self {
-        console_error_panic_hook::set_once();
-
         return Console {
             mmu: Mmu::new(),
-            cpu: Cpu::new()
+            cpu: Cpu::new(),
+            symbols: SymbolTable::new(),
         }
     }
 
-    pub fn load(&mut self, result: &JsValue) {
-        self.mmu.load_cartridge_from_js_value(result);
+    // Symbols of a previous rom are dropped
+    pub fn load(&mut self, rom: &[u8]) -> Result<(), EmulatorError> {
+        self.symbols = SymbolTable::new();
+        return self.mmu.load_cartridge_from_bytes(rom.to_vec());
+    }
+
+    // Loads the .sym file written by rgblink for the loaded rom
+    pub fn load_symbols(&mut self, text: &str) -> Result<(), EmulatorError> {
+        self.symbols = SymbolTable::parse(text)?;
+        return Ok(());
+    }
+
+    pub fn symbols(&self) -> &SymbolTable {
+        return &self.symbols;
+    }
+
+    // Bank and address of a label, the bank is None outside of the switchable rom and wram
+    pub fn find_symbol(&self, name: &str) -> Result<(Option<usize>, u16), EmulatorError> {
+        return self.symbols.address(name).ok_or_else(|| EmulatorError::UnknownSymbol(name.to_string()));
+    }
+
+    // The address as the closest label and offset like "Main.loop+$03", or as "$01:4003"
+    pub fn describe_address(&self, address: u16) -> String {
+        return self.describe_location(self.mmu.get_bank(address), address);
+    }
+
+    // Like describe_address for an address in the given bank
+    pub fn describe_location(&self, bank: usize, address: u16) -> String {
+        return match self.symbols.locate(bank, address) {
+            Some((name, 0)) => name.to_string(),
+            Some((name, offset)) => format!("{}+${:02X}", name, offset),
+            None => format!("${:02X}:{:04X}", bank, address),
+        };
+    }
+
+    // The registers, where pc is and the instruction at it
+    pub fn trace_line(&self) -> String {
+        let pc = self.cpu.pc;
+        return format!("{} {} {}", self.cpu.to_string(), self.describe_address(pc), self.disassemble_at(pc).mnemonic);
+    }
+
+    // Logs each instruction in the gameboy-doctor format to the sink until stop_trace, only the
+    // ones the filter matches when given. Replaces the trace that was running.
+    pub fn start_trace(&mut self, sink: Box<dyn TraceSink>, filter: Option<TraceFilter>) {
+        self.mmu.trace = Some(Tracer { sink, filter });
+    }
+
+    pub fn stop_trace(&mut self) -> Result<(), EmulatorError> {
+        return match self.mmu.trace.take() {
+            Some(mut tracer) => tracer.sink.flush(),
+            None => Ok(()),
+        };
+    }
+
+    pub fn is_tracing(&self) -> bool {
+        return self.mmu.trace.is_some();
+    }
+
+    // Counts the instructions and cycles run at each address, and marks the rom bytes the cpu
+    // executed or read, until stop_profiling. Starting again clears the counts.
+    pub fn start_profiling(&mut self) {
+        self.mmu.profiler = Some(Profiler::new(self.mmu.get_rom_size()));
+    }
+
+    pub fn stop_profiling(&mut self) -> Option<Profiler> {
+        return self.mmu.profiler.take();
+    }
+
+    pub fn profiler(&self) -> Option<&Profiler> {
+        return self.mmu.profiler.as_ref();
+    }
+
+    // The hotspots with the most cycles, a "bank:address cycles instructions label" line each
+    pub fn profile_report(&self, limit: usize) -> String {
+        let profiler = match &self.mmu.profiler {
+            Some(profiler) => profiler,
+            None => return String::new(),
+        };
+        let mut report = format!("{:<7}  {:>12}  {:>12}  {}\n", "address", "cycles", "instructions", "label");
+        for hotspot in profiler.hotspots().iter().take(limit) {
+            let label = match self.symbols.locate(hotspot.bank, hotspot.address) {
+                Some((name, 0)) => name.to_string(),
+                Some((name, offset)) => format!("{}+${:02X}", name, offset),
+                None => String::new(),
+            };
+            let line = format!("{:02X}:{:04X}  {:>12}  {:>12}  {}", hotspot.bank, hotspot.address, hotspot.cycles,
+                               hotspot.instructions, label);
+            report += line.trim_end();
+            report += "\n";
+        }
+        return report;
+    }
+
+    // A DMG or CGB boot rom that runs on the next reset, it is kept when another rom is loaded
+    pub fn load_boot_rom(&mut self, data: &[u8]) -> Result<(), EmulatorError> {
+        return self.mmu.set_boot_rom(data);
+    }
+
+    pub fn clear_boot_rom(&mut self) {
+        self.mmu.clear_boot_rom();
+    }
+
+    pub fn has_boot_rom(&self) -> bool {
+        return self.mmu.has_boot_rom();
+    }
+
+    // True while the boot rom is mapped over the cartridge
+    pub fn is_booting(&self) -> bool {
+        return self.mmu.is_boot_rom_mapped();
     }
 
     pub fn press_button(&mut self, button: Button) {
@@ -43,32 +156,241 @@ impl Console {
         self.mmu.joypad.release(button);
     }
 
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        if pressed {
+            self.press_button(button);
+        } else {
+            self.release_button(button);
+        }
+    }
+
+    // Runs the boot rom from power on when one was loaded, otherwise starts at 0x0100 with the
+    // registers as the boot rom leaves them
     pub fn reset(&mut self) {
-        let model = self.mmu.model;
+        let model = self.mmu.power_on_model();
+        self.mmu.model = model;
         self.cpu.reset(model.clone());
+        if self.has_boot_rom() {
+            self.cpu.power_on();
+        }
         self.mmu.reset(model.clone());
         self.mmu.timer.reset(model.clone());
         self.mmu.ppu.reset(model.clone());
-        self.mmu.dma.reset(model);
+        self.mmu.dma.reset(model.clone());
+        self.mmu.psg.reset(model.clone());
+        self.mmu.serial.reset(model);
     }
 
-    pub fn execute_ticks(&mut self, ticks: u32) -> () {
+    pub fn execute_ticks(&mut self, ticks: u32) -> Result<(), EmulatorError> {
         for _i in 0 .. ticks {
-            self.execute_tick();
+            self.execute_tick()?;
+        }
+        return Ok(());
+    }
+
+    pub fn execute_tick(&mut self) -> Result<(), EmulatorError> {
+        self.step()?;
+        return Ok(());
+    }
+
+    // Runs one instruction, returns the elapsed time in normal speed clock cycles
+    pub(crate) fn step(&mut self) -> Result<u32, EmulatorError> {
+        let cpu_ticks = self.cpu.execute_tick(&mut self.mmu)? * 4;
+        let elapsed = match self.cpu.get_state() {
+            CpuState::STOPPED => self.mmu.finish_stopped_instruction(cpu_ticks),
+            _ => self.mmu.finish_instruction(cpu_ticks),
+        };
+
+        if let Some(error) = self.mmu.take_error() {
+            return Err(error);
         }
+        return Ok(elapsed);
+    }
+
+    // Runs until the ppu has finished drawing the next frame. An illegal opcode returns
+    // IllegalOpcode once, later calls run the rest of the console with the cpu locked up.
+    pub fn run_frame(&mut self) -> Result<(), EmulatorError> {
+        while !self.take_frame_ready() {
+            self.execute_tick()?;
+        }
+        return Ok(());
+    }
+
+    pub(crate) fn take_frame_ready(&mut self) -> bool {
+        return self.mmu.ppu.take_frame_ready();
+    }
+
+    pub(crate) fn set_watchpoints(&mut self, watchpoints: Vec<Watchpoint>) {
+        self.mmu.watchpoints = watchpoints;
+        self.mmu.watch_hit = None;
+    }
+
+    pub(crate) fn take_watch_hit(&mut self) -> Option<PauseReason> {
+        return self.mmu.watch_hit.take();
+    }
+
+    // On by default, memory accesses of the cpu happen at the M-cycle they do on hardware.
+    // Turning it off clocks the console once per instruction, which is faster but breaks
+    // timing sensitive games and raster effects.
+    pub fn set_cycle_accurate(&mut self, enabled: bool) {
+        self.mmu.cycle_accurate = enabled;
     }
 
-    pub fn execute_tick(&mut self) {
-        let cpu_ticks = self.cpu.execute_tick(&mut self.mmu) * 4;
-        self.mmu.execute_ticks(cpu_ticks);
+    pub fn is_cycle_accurate(&self) -> bool {
+        return self.mmu.cycle_accurate;
     }
 
-    pub fn get_frame(&self) -> js_sys::Array {
+    // Header of the loaded cartridge, None until a rom is loaded
+    pub fn get_header(&self) -> Option<&CartridgeHeader> {
+        return self.mmu.get_header();
+    }
+
+    pub fn has_battery(&mut self) -> bool {
+        return self.mmu.get_cartridge().has_battery();
+    }
+
+    // True when the battery backed ram changed since the last export_save
+    pub fn is_save_dirty(&mut self) -> bool {
+        return self.mmu.get_cartridge().is_ram_dirty();
+    }
+
+    // Battery backed ram of the cartridge, empty when it has no battery. Clears the dirty flag.
+    pub fn export_save(&mut self) -> Vec<u8> {
+        return self.mmu.get_cartridge().export_save();
+    }
+
+    // Restores battery backed ram, call after load and before running
+    pub fn import_save(&mut self, data: &[u8]) -> Result<(), EmulatorError> {
+        return self.mmu.get_cartridge().import_save(data);
+    }
+
+    // Snapshot of the whole console, see state.rs for the format. The rom itself is not included.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new(self.rom_checksum());
+        writer.begin_section(SECTION_CPU);
+        self.cpu.save_state(&mut writer);
+        writer.end_section();
+        self.mmu.save_state(&mut writer);
+        return writer.finish();
+    }
+
+    // Restores a snapshot made by save_state. States made against another rom are refused,
+    // and a corrupt state leaves the console as it was.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), EmulatorError> {
+        let state = SaveState::parse(data)?;
+        let checksum = self.rom_checksum();
+        if state.rom_checksum != checksum {
+            return Err(EmulatorError::RomMismatch { expected: checksum, actual: state.rom_checksum });
+        }
+
+        let backup = self.save_state();
+        if let Err(error) = self.apply_state(&state) {
+            let backup_state = SaveState::parse(&backup)?;
+            self.apply_state(&backup_state)?;
+            return Err(error);
+        }
+        return Ok(());
+    }
+
+    fn apply_state(&mut self, state: &SaveState) -> Result<(), EmulatorError> {
+        self.cpu.load_state(&mut state.section(SECTION_CPU)?)?;
+        return self.mmu.load_state(state);
+    }
+
+    // CRC32 of the loaded rom
+    pub fn rom_checksum(&self) -> u32 {
+        return self.mmu.get_rom_checksum();
+    }
+
+    // CRC32 of the rom a state was made against
+    pub fn state_rom_checksum(data: &[u8]) -> Result<u32, EmulatorError> {
+        return Ok(SaveState::parse(data)?.rom_checksum);
+    }
+
+    // Bytes sent over the serial port since the last call
+    pub fn take_serial_output(&mut self) -> Vec<u8> {
+        return self.mmu.serial.take_output();
+    }
+
+    // Plugs a device into the link port, returns the device that was plugged in before
+    pub fn attach_serial_device(&mut self, device: Box<dyn SerialDevice>) -> Option<Box<dyn SerialDevice>> {
+        return self.mmu.serial.attach(device);
+    }
+
+    pub fn detach_serial_device(&mut self) -> Option<Box<dyn SerialDevice>> {
+        return self.mmu.serial.detach();
+    }
+
+    // Clocks a byte in from a peer that drives the clock. Returns the byte shifted out, or None
+    // when the console is not waiting for an externally clocked transfer.
+    pub fn external_serial_transfer(&mut self, byte: u8, fast: bool) -> Option<u8> {
+        return self.mmu.serial.external_transfer(byte, fast);
+    }
+
+    // Fixes up an internally clocked transfer once the peer on the other end has answered
+    pub(crate) fn complete_serial_transfer(&mut self, byte: u8) {
+        self.mmu.serial.complete_transfer(byte);
+    }
+
+    pub(crate) fn is_serial_fast(&self) -> bool {
+        return self.mmu.serial.is_fast();
+    }
+
+    pub fn get_cpu_state(&self) -> CpuState {
+        return self.cpu.get_state();
+    }
+
+    pub fn get_registers(&self) -> Registers {
+        return self.cpu.get_registers();
+    }
+
+    pub fn set_registers(&mut self, registers: &Registers) {
+        self.cpu.set_registers(registers);
+    }
+
+    // Reads memory as the cpu would see it, without side effects or clocking the console
+    pub fn read_byte(&self, address: u16) -> u8 {
+        return self.mmu.read_byte(address);
+    }
+
+    // Writes memory as the cpu would, writes to registers and mapper control take effect
+    pub fn write_byte(&mut self, address: u16, value: u8) {
+        self.mmu.write_byte(address, value);
+    }
+
+    // Rom or wram bank mapped at the address, 0 outside of the switchable areas
+    pub fn get_bank(&self, address: u16) -> usize {
+        return self.mmu.get_bank(address);
+    }
+
+    // Decodes the instruction at the address in the currently mapped banks
+    pub fn disassemble_at(&self, address: u16) -> Instruction {
+        return disassembler::disassemble_at(&self.mmu, &self.symbols, address);
+    }
+
+    // Decodes the instructions from start up to end
+    pub fn disassemble(&self, start: u16, end: u16) -> Vec<Instruction> {
+        return disassembler::disassemble(&self.mmu, &self.symbols, start, end);
+    }
+
+    pub fn get_model(&self) -> GameboyType {
+        return self.mmu.model;
+    }
+
+    // RGBA pixels of the last completed frame, 160x144
+    pub fn get_frame(&self) -> &[u8] {
         return self.mmu.ppu.get_frame();
     }
 
-    pub fn get_sound(&self) -> js_sys::Int8Array {
-        return js_sys::Int8Array::new_with_length(0);
+    // Interleaved stereo samples at 44.1kHz produced since the last call
+    pub fn take_sound(&mut self) -> Vec<f32> {
+        return self.mmu.psg.take_samples();
     }
 
 }
+
+impl Default for Console {
+    fn default() -> Self {
+        return Console::new();
+    }
+}
@@ -1,10 +1,69 @@
 use crate::mmu::Mmu;
 use crate::operations::execute_operation;
-use crate::logger::log;
+use crate::logger;
 use crate::console::GameboyType;
+use crate::error::EmulatorError;
+use crate::state::{StateWriter, StateReader};
+#[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::*;
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CpuState {
+    RUNNING,
+    // HALT, until an interrupt is pending
+    HALTED,
+    // STOP, until a joypad line goes low
+    STOPPED,
+    // An undefined opcode froze the cpu until reset, pc stays on the opcode
+    LOCKED,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Register {
+    A, F, B, C, D, E, H, L, AF, BC, DE, HL, SP, PC
+}
+
+// Copy of the cpu registers taken between instructions
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Registers {
+    pub a: u8,
+    pub f: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    pub sp: u16,
+    pub pc: u16,
+    pub ime: bool,
+}
+
+impl Registers {
+
+    pub fn get(&self, register: Register) -> u16 {
+        let pair = |high: u8, low: u8| (high as u16) << 8 | low as u16;
+        return match register {
+            Register::A => self.a as u16,
+            Register::F => self.f as u16,
+            Register::B => self.b as u16,
+            Register::C => self.c as u16,
+            Register::D => self.d as u16,
+            Register::E => self.e as u16,
+            Register::H => self.h as u16,
+            Register::L => self.l as u16,
+            Register::AF => pair(self.a, self.f),
+            Register::BC => pair(self.b, self.c),
+            Register::DE => pair(self.d, self.e),
+            Register::HL => pair(self.h, self.l),
+            Register::SP => self.sp,
+            Register::PC => self.pc,
+        };
+    }
+}
+
 #[allow(unused)]
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Cpu {
@@ -20,9 +79,11 @@ pub struct Cpu {
     pub pc: u16,
 
     pub halted: bool,
+    pub stopped: bool,
+    pub locked: bool,
+    pub halt_bug: bool,
     pub interrupt_master_enable: bool,
-    pub disable_interrupt_counter: u8, // Schedules interrupt handling to be enabled after the next machine cycle
-    pub enable_interrupt_counter: u8,  // Schedules interrupt handling to be enabled after the next machine cycle
+    pub enable_interrupt_counter: u8,  // EI enables interrupt handling after the next instruction
 
     pub cycles: u32,
     pub ticks: u32,
@@ -45,8 +106,10 @@ impl Cpu {
             pc: 0x0000,
             sp: 0x0000,
             halted: false,
+            stopped: false,
+            locked: false,
+            halt_bug: false,
             interrupt_master_enable: true,
-            disable_interrupt_counter: 0,
             enable_interrupt_counter: 0,
             cycles: 0,
             opcode: 0,
@@ -66,8 +129,10 @@ impl Cpu {
         self.pc = 0x0100;
         self.sp = 0xFFFE;
         self.halted = false;
+        self.stopped = false;
+        self.locked = false;
+        self.halt_bug = false;
         self.interrupt_master_enable = true;
-        self.disable_interrupt_counter = 0;
         self.enable_interrupt_counter = 0;
         self.cycles = 0;
         self.opcode = 0;
@@ -78,44 +143,125 @@ impl Cpu {
         }
     }
 
-    pub fn execute_ticks(&mut self, mmu: &mut Mmu, ticks: u32) -> u32 {
+    // Registers are cleared at power on, the boot rom leaves them as reset sets them
+    pub fn power_on(&mut self) {
+        self.reset(GameboyType::CLASSIC);
+        self.a = 0;
+        self.f = 0;
+        self.b = 0;
+        self.c = 0;
+        self.d = 0;
+        self.e = 0;
+        self.h = 0;
+        self.l = 0;
+        self.pc = 0x0000;
+        self.sp = 0x0000;
+        self.interrupt_master_enable = false;
+    }
+
+    pub fn execute_ticks(&mut self, mmu: &mut Mmu, ticks: u32) -> Result<u32, EmulatorError> {
         let mut total = 0;
         for i in 0 .. ticks {
-            total += self.execute_tick(mmu);
+            total += self.execute_tick(mmu)?;
         }
-        return total;
+        return Ok(total);
     }
 
-    pub fn execute_tick(&mut self, mmu: &mut Mmu) -> u32 {
+    pub fn execute_tick(&mut self, mmu: &mut Mmu) -> Result<u32, EmulatorError> {
+        // Interrupts are not serviced either, only a reset gets out of a lockup
+        if self.locked {
+            return Ok(1);
+        }
+        if self.stopped {
+            if !mmu.joypad.is_line_low() {
+                return Ok(1);
+            }
+            self.stopped = false;
+        }
+
         let cycles = self.cycles;
         let pc = self.pc;
 
         self.update_interrupt_master_flag();
         if self.handle_interrupt(mmu) {
-            return (self.cycles - cycles) as u32;
+            return Ok((self.cycles - cycles) as u32);
         }
 
         if self.halted {
-            return 1;
+            return Ok(1);
         }
 
-        self.opcode = mmu.read_byte(pc) as u8 as u16;
-        //log(self.to_string());
-        execute_operation(self.opcode as u8, self, mmu);
+        if mmu.trace.is_some() {
+            logger::trace(self, mmu)?;
+        }
+        self.opcode = mmu.cpu_read_byte(pc) as u8 as u16;
+        if self.halt_bug {
+            // Operands are read starting at the opcode itself and pc ends up one byte short
+            self.halt_bug = false;
+            self.pc = pc.wrapping_sub(1);
+        }
+        execute_operation(self.opcode as u8, self, mmu)?;
+        if mmu.profiler.is_some() {
+            mmu.profile_instruction(pc, self.opcode as u8, (self.cycles - cycles) * 4);
+        }
 
         self.ticks += 1;
-        return (self.cycles - cycles) as u32;
+        return Ok((self.cycles - cycles) as u32);
     }
 
-    pub fn update_interrupt_master_flag(&mut self) {
-        // There are some operations where interrupts are triggered after the next machine cycle
-        // So to mimic it we will use a counter that will tick down.
-
-        self.disable_interrupt_counter = match self.disable_interrupt_counter {
-            2 => 1,
-            1 => { self.interrupt_master_enable = false; 0},
-            _ => 0,
+    pub fn get_state(&self) -> CpuState {
+        if self.locked {
+            return CpuState::LOCKED;
+        }
+        if self.stopped {
+            return CpuState::STOPPED;
+        }
+        if self.halted {
+            return CpuState::HALTED;
+        }
+        return CpuState::RUNNING;
+    }
+
+    pub fn get_registers(&self) -> Registers {
+        return Registers {
+            a: self.a,
+            f: self.f,
+            b: self.b,
+            c: self.c,
+            d: self.d,
+            e: self.e,
+            h: self.h,
+            l: self.l,
+            sp: self.sp,
+            pc: self.pc,
+            ime: self.interrupt_master_enable,
         };
+    }
+
+    pub fn set_registers(&mut self, registers: &Registers) {
+        self.a = registers.a;
+        // The lower nibble of f is always 0
+        self.f = registers.f & 0xF0;
+        self.b = registers.b;
+        self.c = registers.c;
+        self.d = registers.d;
+        self.e = registers.e;
+        self.h = registers.h;
+        self.l = registers.l;
+        self.sp = registers.sp;
+        self.pc = registers.pc;
+        self.interrupt_master_enable = registers.ime;
+    }
+
+    // Undefined opcodes hang the cpu, pc is left on the opcode
+    pub fn lock_up(&mut self, opcode: u8) -> Result<(), EmulatorError> {
+        self.locked = true;
+        return Err(EmulatorError::IllegalOpcode { opcode, address: self.pc });
+    }
+
+    pub fn update_interrupt_master_flag(&mut self) {
+        // EI takes effect after the instruction that follows it, so to mimic it we will use a
+        // counter that will tick down. DI and RETI take effect immediately.
         self.enable_interrupt_counter = match self.enable_interrupt_counter {
             2 => 1,
             1 => { self.interrupt_master_enable = true; 0},
@@ -129,45 +275,102 @@ impl Cpu {
             return false;
         }
 
-        // http://bgb.bircd.org/pandocs.htm#interrupts
-        let interrupt_mask = mmu.interrupt_enable & mmu.interrupt_flags;
+        // https://gbdev.io/pandocs/Interrupts.html
+        let interrupt_mask = mmu.interrupt_enable & mmu.interrupt_flags & 0x1F;
         if interrupt_mask == 0 { return false }
 
         self.halted = false;
         if self.interrupt_master_enable == false { return false; };
         self.interrupt_master_enable = false;
 
-        // http://bgb.bircd.org/pandocs.htm#interrupts
+        // https://gbdev.io/pandocs/Interrupts.html#interrupt-handling
+        // Two wait states, pc is pushed and then set to the vector, 5 M-cycles in total
+        mmu.cpu_internal_cycle();
+        mmu.cpu_internal_cycle();
+        self.sp = self.sp.wrapping_sub(1);
+        mmu.cpu_write_byte(self.sp, (self.pc >> 8) as u8);
+
+        // The vector is picked after the high byte is pushed. When that push overwrites IE and no
+        // enabled interrupt is left pending the dispatch is cancelled and jumps to 0x0000.
+        let interrupt_mask = mmu.interrupt_enable & mmu.interrupt_flags & 0x1F;
+        self.sp = self.sp.wrapping_sub(1);
+        mmu.cpu_write_byte(self.sp, (self.pc & 0xFF) as u8);
+
         // Bit 0: V-Blank  Interrupt Enable  (INT 40h)  (1=Enable)
         // Bit 1: LCD STAT Interrupt Enable  (INT 48h)  (1=Enable)
         // Bit 2: Timer    Interrupt Enable  (INT 50h)  (1=Enable)
         // Bit 3: Serial   Interrupt Enable  (INT 58h)  (1=Enable)
         // Bit 4: Joypad   Interrupt Enable  (INT 60h)  (1=Enable)
-
-        let interrupt_type = interrupt_mask.trailing_zeros();
-        if interrupt_type >= 5 { panic!("Invalid interrupt code") }
-
-        // clear flag
-        mmu.interrupt_flags &= !(1 << interrupt_type);
-
-        self.push_word(mmu, self.pc);
-
-        // go to the vector
-        self.pc = match interrupt_type {
-            0 => 0x40,
-            1 => 0x48,
-            2 => 0x50,
-            3 => 0x58,
-            4 => 0x60,
-            _ => { panic!("error") }
+        self.pc = match interrupt_mask.trailing_zeros() {
+            5 ..= 8 => 0x0000,
+            interrupt_type => {
+                mmu.interrupt_flags &= !(1 << interrupt_type);
+                0x40 + interrupt_type as u16 * 8
+            }
         };
 
-        self.cycles += 12;
+        self.cycles += 5;
 
         return true;
     }
 
-    pub fn to_string(&mut self) -> String {
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.a);
+        writer.write_u8(self.b);
+        writer.write_u8(self.c);
+        writer.write_u8(self.d);
+        writer.write_u8(self.e);
+        writer.write_u8(self.f);
+        writer.write_u8(self.h);
+        writer.write_u8(self.l);
+        writer.write_u16(self.sp);
+        writer.write_u16(self.pc);
+        writer.write_bool(self.halted);
+        writer.write_bool(self.interrupt_master_enable);
+        // Formerly a delay for DI, which takes effect immediately
+        writer.write_u8(0);
+        writer.write_u8(self.enable_interrupt_counter);
+        writer.write_u32(self.cycles);
+        writer.write_u32(self.ticks);
+        writer.write_u16(self.opcode);
+        writer.write_bool(self.stopped);
+        writer.write_bool(self.locked);
+        writer.write_bool(self.halt_bug);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<(), EmulatorError> {
+        self.a = reader.read_u8()?;
+        self.b = reader.read_u8()?;
+        self.c = reader.read_u8()?;
+        self.d = reader.read_u8()?;
+        self.e = reader.read_u8()?;
+        self.f = reader.read_u8()?;
+        self.h = reader.read_u8()?;
+        self.l = reader.read_u8()?;
+        self.sp = reader.read_u16()?;
+        self.pc = reader.read_u16()?;
+        self.halted = reader.read_bool()?;
+        self.interrupt_master_enable = reader.read_bool()?;
+        if reader.read_u8()? != 0 {
+            self.interrupt_master_enable = false;
+        }
+        self.enable_interrupt_counter = reader.read_u8()?;
+        self.cycles = reader.read_u32()?;
+        self.ticks = reader.read_u32()?;
+        self.opcode = reader.read_u16()?;
+        if reader.minor() >= 4 {
+            self.stopped = reader.read_bool()?;
+            self.locked = reader.read_bool()?;
+            self.halt_bug = reader.read_bool()?;
+        } else {
+            self.stopped = false;
+            self.locked = false;
+            self.halt_bug = false;
+        }
+        return Ok(());
+    }
+
+    pub fn to_string(&self) -> String {
         return format!("PC: {:#06X} OPCODE: {:#04X} A: {:#04X} B: {:#04X} C: {:#04X} D: {:#04X} E: {:#04X} F: {:#04X} H: {:#04X} L: {:#04X} SP: {:#06X}",
                        self.pc,
                        self.opcode,
@@ -183,7 +386,7 @@ impl Cpu {
         );
     }
 
-    pub fn print(&mut self) {
+    pub fn print(&self) {
         println!("{}", self.to_string());
     }
 
@@ -446,19 +649,21 @@ impl Cpu {
 
     pub fn push_byte(&mut self, mmu: &mut Mmu, value: u8) {
         self.sp = self.sp.wrapping_sub(1);
-        mmu.write_byte(self.sp, value);
+        mmu.cpu_write_byte(self.sp, value);
     }
 
+    // PUSH, CALL, RST and interrupts all spend an internal cycle before writing the stack
     pub fn push_word(&mut self, mmu: &mut Mmu, value: u16) {
         let low = (value & 0xFF) as u8;
         let high = (value >> 8) as u8;
 
+        mmu.cpu_internal_cycle();
         self.push_byte(mmu, high);
         self.push_byte(mmu, low);
     }
 
     pub fn pop_byte(&mut self, mmu: &mut Mmu) -> u8 {
-        let value = mmu.read_byte(self.sp);
+        let value = mmu.cpu_read_byte(self.sp);
         self.sp = self.sp.wrapping_add(1);
         return value;
     }
@@ -0,0 +1,418 @@
+use crate::console::Console;
+use crate::cpu::{CpuState, Register, Registers};
+use crate::error::EmulatorError;
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+// CALL, CALL cc and RST push the address of the next instruction
+const CALLS: [u8; 13] = [0xCD, 0xC4, 0xCC, 0xD4, 0xDC, 0xC7, 0xCF, 0xD7, 0xDF, 0xE7, 0xEF, 0xF7, 0xFF];
+// RET, RETI and RET cc
+const RETURNS: [u8; 6] = [0xC9, 0xD9, 0xC0, 0xC8, 0xD0, 0xD8];
+// Interrupt handlers, 0x0000 when the dispatch was cancelled
+const VECTORS: [u16; 6] = [0x40, 0x48, 0x50, 0x58, 0x60, 0x00];
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    // Any read by the cpu, including opcode and operand fetches
+    READ,
+    WRITE,
+    // The cpu is about to run the instruction at the address
+    EXECUTE,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+}
+
+// Compares a register with a value, for example "a == $10" or "hl >= $C000"
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Condition {
+    pub register: Register,
+    pub comparison: Comparison,
+    pub value: u16,
+}
+
+impl Condition {
+
+    // Registers are case insensitive, values are decimal or hexadecimal with $ or 0x
+    pub fn parse(text: &str) -> Result<Self, EmulatorError> {
+        let invalid = || EmulatorError::InvalidCondition(text.to_string());
+        let tokens: Vec<&str> = text.split_whitespace().collect();
+        if tokens.len() != 3 {
+            return Err(invalid());
+        }
+
+        let register = match tokens[0].to_ascii_uppercase().as_str() {
+            "A" => Register::A,
+            "F" => Register::F,
+            "B" => Register::B,
+            "C" => Register::C,
+            "D" => Register::D,
+            "E" => Register::E,
+            "H" => Register::H,
+            "L" => Register::L,
+            "AF" => Register::AF,
+            "BC" => Register::BC,
+            "DE" => Register::DE,
+            "HL" => Register::HL,
+            "SP" => Register::SP,
+            "PC" => Register::PC,
+            _ => return Err(invalid()),
+        };
+        let comparison = match tokens[1] {
+            "==" => Comparison::Equal,
+            "!=" => Comparison::NotEqual,
+            "<" => Comparison::Less,
+            "<=" => Comparison::LessEqual,
+            ">" => Comparison::Greater,
+            ">=" => Comparison::GreaterEqual,
+            _ => return Err(invalid()),
+        };
+        let value = match tokens[2] {
+            hex if hex.starts_with('$') => u16::from_str_radix(&hex[1 ..], 16),
+            hex if hex.starts_with("0x") => u16::from_str_radix(&hex[2 ..], 16),
+            decimal => decimal.parse(),
+        }.map_err(|_e| invalid())?;
+
+        return Ok(Condition { register, comparison, value });
+    }
+
+    pub fn matches(&self, registers: &Registers) -> bool {
+        let register = registers.get(self.register);
+        return match self.comparison {
+            Comparison::Equal => register == self.value,
+            Comparison::NotEqual => register != self.value,
+            Comparison::Less => register < self.value,
+            Comparison::LessEqual => register <= self.value,
+            Comparison::Greater => register > self.value,
+            Comparison::GreaterEqual => register >= self.value,
+        };
+    }
+}
+
+// Pauses before the instruction at the address runs. Without a bank it matches whatever bank
+// is mapped, with a condition it only pauses when the condition holds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub address: u16,
+    pub bank: Option<usize>,
+    pub condition: Option<Condition>,
+}
+
+impl Breakpoint {
+
+    pub fn new(address: u16) -> Self {
+        return Breakpoint { address, bank: None, condition: None };
+    }
+}
+
+// Pauses on accesses to the addresses from start to end inclusive. Reads and writes pause
+// after the instruction that made them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub start: u16,
+    pub end: u16,
+    pub access: Access,
+}
+
+impl Watchpoint {
+
+    pub fn new(address: u16, access: Access) -> Self {
+        return Watchpoint { start: address, end: address, access };
+    }
+
+    pub fn contains(&self, address: u16) -> bool {
+        return address >= self.start && address <= self.end;
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PauseReason {
+    // Before the instruction at the address
+    Breakpoint { address: u16, bank: usize },
+    // The value read or written, for execute watchpoints the opcode
+    Watchpoint { address: u16, access: Access, value: u8 },
+    // A step into, over or out of, or a run to, has finished
+    Step,
+    // The cpu locked up on an undefined opcode
+    LockedUp,
+}
+
+// A call, rst or interrupt that has not returned yet
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Frame {
+    // The call or rst, or the instruction that was interrupted, and its bank
+    pub caller: u16,
+    pub bank: usize,
+    pub target: u16,
+    // Where the return address was pushed, the frame is gone once sp is above it
+    pub sp: u16,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Target {
+    StepInto,
+    // Back at the instruction after the call, with the call frame popped
+    StepOver { address: u16, sp: u16 },
+    // After a return that popped the frame the step started in
+    StepOut { sp: u16 },
+    RunTo { address: u16, bank: Option<usize> },
+}
+
+// Runs a console instruction by instruction and pauses on breakpoints, watchpoints and steps.
+// Pausing returns from execute_ticks or run_frame before the frame is done, calling them again
+// resumes from where it paused.
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    watchpoints: Vec<Watchpoint>,
+    target: Option<Target>,
+    // The pc the console paused at, its breakpoints are skipped until the instruction there ran
+    paused_at: Option<u16>,
+    call_stack: Vec<Frame>,
+}
+
+impl Debugger {
+
+    pub fn new() -> Self {
+        return Debugger {
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            target: None,
+            paused_at: None,
+            call_stack: Vec::new(),
+        }
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        self.breakpoints.push(breakpoint);
+    }
+
+    // Removes the breakpoints at the address with the same bank, returns whether there were any
+    pub fn remove_breakpoint(&mut self, address: u16, bank: Option<usize>) -> bool {
+        let count = self.breakpoints.len();
+        self.breakpoints.retain(|breakpoint| breakpoint.address != address || breakpoint.bank != bank);
+        return self.breakpoints.len() != count;
+    }
+
+    // Breaks at a label of the symbols loaded into the console, like "Main.loop"
+    pub fn add_symbol_breakpoint(&mut self, console: &Console, name: &str, condition: Option<Condition>) -> Result<(), EmulatorError> {
+        let (bank, address) = console.find_symbol(name)?;
+        self.add_breakpoint(Breakpoint { address, bank, condition });
+        return Ok(());
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        return &self.breakpoints;
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.push(watchpoint);
+    }
+
+    pub fn remove_watchpoint(&mut self, watchpoint: &Watchpoint) -> bool {
+        let count = self.watchpoints.len();
+        self.watchpoints.retain(|other| other != watchpoint);
+        return self.watchpoints.len() != count;
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        return &self.watchpoints;
+    }
+
+    // Calls made while the debugger ran the console, the innermost last
+    pub fn call_stack(&self) -> &[Frame] {
+        return &self.call_stack;
+    }
+
+    // Where the console is, followed by the callers of the call stack, described with the
+    // symbols of the console
+    pub fn backtrace(&self, console: &Console) -> Vec<String> {
+        let mut lines = vec![format!("#0 {}", console.describe_address(console.get_registers().pc))];
+        for (index, frame) in self.call_stack.iter().rev().enumerate() {
+            lines.push(format!("#{} {}", index + 1, console.describe_location(frame.bank, frame.caller)));
+        }
+        return lines;
+    }
+
+    // Forgets the call stack, after the console was reset or a state was loaded
+    pub fn reset(&mut self) {
+        self.call_stack.clear();
+        self.paused_at = None;
+        self.target = None;
+    }
+
+    pub fn clear(&mut self) {
+        self.breakpoints.clear();
+        self.watchpoints.clear();
+        self.target = None;
+    }
+
+    // Steps, and the run to, take effect on the next execute_ticks or run_frame, which pause
+    // with PauseReason::Step when they finish. Any other pause cancels them.
+    pub fn step_into(&mut self) {
+        self.target = Some(Target::StepInto);
+    }
+
+    // Runs a call or rst until it returns, any other instruction is stepped into
+    pub fn step_over(&mut self, console: &Console) {
+        let registers = console.get_registers();
+        let instruction = console.disassemble_at(registers.pc);
+        self.target = Some(match CALLS.contains(&instruction.bytes[0]) {
+            true => Target::StepOver {
+                address: registers.pc.wrapping_add(instruction.bytes.len() as u16),
+                sp: registers.sp,
+            },
+            false => Target::StepInto,
+        });
+    }
+
+    pub fn step_out(&mut self, console: &Console) {
+        self.target = Some(Target::StepOut { sp: console.get_registers().sp });
+    }
+
+    // Runs until pc reaches the address, without a bank in any bank
+    pub fn run_to(&mut self, address: u16, bank: Option<usize>) {
+        self.target = Some(Target::RunTo { address, bank });
+    }
+
+    // Runs up to the given number of instructions, like Console::execute_ticks
+    pub fn execute_ticks(&mut self, console: &mut Console, ticks: u32) -> Result<Option<PauseReason>, EmulatorError> {
+        return self.run(console, |_console, executed| executed < ticks);
+    }
+
+    // Runs until the ppu has finished drawing the next frame, like Console::run_frame
+    pub fn run_frame(&mut self, console: &mut Console) -> Result<Option<PauseReason>, EmulatorError> {
+        return self.run(console, |console, _executed| !console.take_frame_ready());
+    }
+
+    fn run<F>(&mut self, console: &mut Console, mut running: F) -> Result<Option<PauseReason>, EmulatorError>
+        where F: FnMut(&mut Console, u32) -> bool {
+        // The mmu checks the reads and writes while the debugger runs it
+        console.set_watchpoints(self.watchpoints.clone());
+        let mut result = Ok(None);
+        let mut executed = 0;
+        while running(console, executed) {
+            result = self.step(console);
+            executed += 1;
+            if !matches!(result, Ok(None)) {
+                break;
+            }
+        }
+        console.set_watchpoints(Vec::new());
+
+        if let Ok(Some(_reason)) = result {
+            self.target = None;
+            self.paused_at = Some(console.get_registers().pc);
+        }
+        return result;
+    }
+
+    fn step(&mut self, console: &mut Console) -> Result<Option<PauseReason>, EmulatorError> {
+        let registers = console.get_registers();
+        let pc = registers.pc;
+        let state = console.get_cpu_state();
+
+        if self.paused_at != Some(pc) {
+            self.paused_at = None;
+            if let Some(reason) = self.check_before(console, &registers) {
+                return Ok(Some(reason));
+            }
+        }
+
+        let opcode = console.read_byte(pc);
+        match console.step() {
+            // Paused on below, the debugger reports the lockup as a pause
+            Err(EmulatorError::IllegalOpcode { .. }) => {},
+            result => { result?; },
+        }
+        // A halted or locked cpu is still at the instruction, a loop back to it pauses again
+        if console.get_cpu_state() == CpuState::RUNNING {
+            self.paused_at = None;
+        }
+        let after = console.get_registers();
+        self.update_call_stack(console, &registers, &after, opcode);
+
+        if let Some(hit) = console.take_watch_hit() {
+            return Ok(Some(hit));
+        }
+        if state != CpuState::LOCKED && console.get_cpu_state() == CpuState::LOCKED {
+            return Ok(Some(PauseReason::LockedUp));
+        }
+
+        let finished = match self.target {
+            Some(Target::StepInto) => true,
+            Some(Target::StepOver { address, sp }) => after.pc == address && after.sp >= sp,
+            Some(Target::StepOut { sp }) => state == CpuState::RUNNING && RETURNS.contains(&opcode) && after.sp > sp,
+            _ => false,
+        };
+        return Ok(if finished { Some(PauseReason::Step) } else { None });
+    }
+
+    fn update_call_stack(&mut self, console: &Console, before: &Registers, after: &Registers, opcode: u8) {
+        // Returns, and code unwinding the stack by hand, pop the frames
+        while self.call_stack.last().is_some_and(|frame| frame.sp < after.sp) {
+            self.call_stack.pop();
+        }
+        if after.sp != before.sp.wrapping_sub(2) {
+            return;
+        }
+        let pushed = console.read_byte(after.sp) as u16 | (console.read_byte(after.sp.wrapping_add(1)) as u16) << 8;
+        let call = CALLS.contains(&opcode);
+        let interrupt = VECTORS.contains(&after.pc) && pushed == before.pc;
+        if call || interrupt {
+            self.call_stack.push(Frame {
+                caller: before.pc,
+                bank: console.get_bank(before.pc),
+                target: after.pc,
+                sp: after.sp,
+            });
+        }
+    }
+
+    // Breakpoints, execute watchpoints and run to, checked before the instruction at pc runs
+    fn check_before(&self, console: &Console, registers: &Registers) -> Option<PauseReason> {
+        let pc = registers.pc;
+        let bank = console.get_bank(pc);
+
+        if let Some(Target::RunTo { address, bank: target_bank }) = self.target {
+            if address == pc && target_bank.unwrap_or(bank) == bank {
+                return Some(PauseReason::Step);
+            }
+        }
+
+        let hit = self.breakpoints.iter().any(|breakpoint| {
+            breakpoint.address == pc &&
+                breakpoint.bank.unwrap_or(bank) == bank &&
+                breakpoint.condition.iter().all(|condition| condition.matches(registers))
+        });
+        if hit {
+            return Some(PauseReason::Breakpoint { address: pc, bank });
+        }
+
+        let watched = self.watchpoints.iter().any(|watchpoint| watchpoint.access == Access::EXECUTE && watchpoint.contains(pc));
+        if watched {
+            return Some(PauseReason::Watchpoint { address: pc, access: Access::EXECUTE, value: console.read_byte(pc) });
+        }
+        return None;
+    }
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        return Debugger::new();
+    }
+}
+
+// Mmu side of the watchpoints, set for the duration of a debugger run
+pub(crate) fn check_access(watchpoints: &[Watchpoint], hit: &mut Option<PauseReason>, address: u16, access: Access, value: u8) {
+    if hit.is_none() && watchpoints.iter().any(|watchpoint| watchpoint.access == access && watchpoint.contains(address)) {
+        *hit = Some(PauseReason::Watchpoint { address, access, value });
+    }
+}
@@ -0,0 +1,176 @@
+use std::fmt;
+
+use crate::mmu::Mmu;
+use crate::symbols::SymbolTable;
+
+// https://gbdev.io/pandocs/CPU_Instruction_Set.html
+// Operands are decoded from the bits of the opcode, xxyyyzzz with yyy split into ppq
+const R8: [&str; 8] = ["b", "c", "d", "e", "h", "l", "[hl]", "a"];
+const R16: [&str; 4] = ["bc", "de", "hl", "sp"];
+const R16_STACK: [&str; 4] = ["bc", "de", "hl", "af"];
+const R16_MEMORY: [&str; 4] = ["[bc]", "[de]", "[hl+]", "[hl-]"];
+const CONDITIONS: [&str; 4] = ["nz", "z", "nc", "c"];
+const ALU: [&str; 8] = ["add", "adc", "sub", "sbc", "and", "xor", "or", "cp"];
+const ROTATIONS: [&str; 8] = ["rlc", "rrc", "rl", "rr", "sla", "sra", "swap", "srl"];
+const ACCUMULATOR: [&str; 8] = ["rlca", "rrca", "rla", "rra", "daa", "cpl", "scf", "ccf"];
+
+// A decoded instruction in RGBDS syntax
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Instruction {
+    pub address: u16,
+    // Rom or wram bank mapped at the address when it was decoded
+    pub bank: usize,
+    pub bytes: Vec<u8>,
+    // Jump targets and memory operands are replaced by their labels
+    pub mnemonic: String,
+    // The label at the address
+    pub label: Option<String>,
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bytes = self.bytes.iter().map(|byte| format!("{:02X}", byte)).collect::<Vec<String>>().join(" ");
+        return write!(f, "{:02X}:{:04X}  {:<8}  {}", self.bank, self.address, bytes, self.mnemonic);
+    }
+}
+
+// Decodes the instruction at the address as currently mapped, without side effects
+pub fn disassemble_at(mmu: &Mmu, symbols: &SymbolTable, address: u16) -> Instruction {
+    let read = |offset: u16| mmu.read_byte(address.wrapping_add(offset));
+    let symbol = |target: u16| symbols.label(mmu.get_bank(target), target).map(String::from);
+    let (mnemonic, length) = decode(&read, &symbol, address);
+    let bank = mmu.get_bank(address);
+    return Instruction {
+        address,
+        bank,
+        bytes: (0 .. length).map(read).collect(),
+        mnemonic,
+        label: symbols.label(bank, address).map(String::from),
+    };
+}
+
+// Decodes the instructions starting from start up to end, the last one may extend past end
+pub fn disassemble(mmu: &Mmu, symbols: &SymbolTable, start: u16, end: u16) -> Vec<Instruction> {
+    let mut instructions = Vec::new();
+    let mut address = start as u32;
+    while address < end as u32 {
+        let instruction = disassemble_at(mmu, symbols, address as u16);
+        address += instruction.bytes.len() as u32;
+        instructions.push(instruction);
+    }
+    return instructions;
+}
+
+// Length in bytes of the instruction starting with the opcode, like decode without the mnemonic
+pub fn instruction_length(opcode: u8) -> u16 {
+    return match opcode {
+        0x01 | 0x08 | 0x11 | 0x21 | 0x31 | 0xC2 | 0xC3 | 0xC4 | 0xCA | 0xCC | 0xCD | 0xD2 | 0xD4 |
+        0xDA | 0xDC | 0xEA | 0xFA => 3,
+        0x06 | 0x0E | 0x10 | 0x16 | 0x18 | 0x1E | 0x20 | 0x26 | 0x28 | 0x2E | 0x30 | 0x36 | 0x38 |
+        0x3E | 0xC6 | 0xCB | 0xCE | 0xD6 | 0xDE | 0xE0 | 0xE6 | 0xE8 | 0xEE | 0xF0 | 0xF6 | 0xF8 |
+        0xFE => 2,
+        _ => 1,
+    };
+}
+
+// Returns the mnemonic and the length in bytes of the instruction, read gives the bytes from
+// the start of the instruction and symbol the label of an address
+fn decode(read: &dyn Fn(u16) -> u8, symbol: &dyn Fn(u16) -> Option<String>, address: u16) -> (String, u16) {
+    let opcode = read(0);
+    let n8 = || format!("${:02X}", read(1));
+    let n16 = || format!("${:04X}", u16::from_le_bytes([read(1), read(2)]));
+    let a16 = || symbol(u16::from_le_bytes([read(1), read(2)])).unwrap_or_else(n16);
+    let a8 = || symbol(0xFF00 | read(1) as u16).unwrap_or_else(|| format!("$FF{:02X}", read(1)));
+    let e8 = || read(1) as i8;
+    let jump = || {
+        let target = address.wrapping_add(2).wrapping_add(e8() as u16);
+        symbol(target).unwrap_or_else(|| format!("${:04X}", target))
+    };
+
+    let x = opcode >> 6;
+    let y = ((opcode >> 3) & 0x07) as usize;
+    let z = (opcode & 0x07) as usize;
+    let p = y >> 1;
+    let q = y & 0x01;
+
+    return match (x, z) {
+        (0, 0) => match y {
+            0 => (String::from("nop"), 1),
+            1 => (format!("ld [{}], sp", a16()), 3),
+            // Followed by a byte that is skipped
+            2 => (String::from("stop"), 2),
+            3 => (format!("jr {}", jump()), 2),
+            _ => (format!("jr {}, {}", CONDITIONS[y - 4], jump()), 2),
+        },
+        (0, 1) if q == 0 => (format!("ld {}, {}", R16[p], n16()), 3),
+        (0, 1) => (format!("add hl, {}", R16[p]), 1),
+        (0, 2) if q == 0 => (format!("ld {}, a", R16_MEMORY[p]), 1),
+        (0, 2) => (format!("ld a, {}", R16_MEMORY[p]), 1),
+        (0, 3) if q == 0 => (format!("inc {}", R16[p]), 1),
+        (0, 3) => (format!("dec {}", R16[p]), 1),
+        (0, 4) => (format!("inc {}", R8[y]), 1),
+        (0, 5) => (format!("dec {}", R8[y]), 1),
+        (0, 6) => (format!("ld {}, {}", R8[y], n8()), 2),
+        (0, _) => (String::from(ACCUMULATOR[y]), 1),
+
+        (1, 6) if y == 6 => (String::from("halt"), 1),
+        (1, _) => (format!("ld {}, {}", R8[y], R8[z]), 1),
+
+        (2, _) => (format!("{} a, {}", ALU[y], R8[z]), 1),
+
+        (3, 0) => match y {
+            0 ..= 3 => (format!("ret {}", CONDITIONS[y]), 1),
+            4 => (format!("ldh [{}], a", a8()), 2),
+            5 => (format!("add sp, {}", offset(e8())), 2),
+            6 => (format!("ldh a, [{}]", a8()), 2),
+            _ => (format!("ld hl, sp{}", signed_offset(e8())), 2),
+        },
+        (3, 1) if q == 0 => (format!("pop {}", R16_STACK[p]), 1),
+        (3, 1) => (String::from(["ret", "reti", "jp hl", "ld sp, hl"][p]), 1),
+        (3, 2) => match y {
+            0 ..= 3 => (format!("jp {}, {}", CONDITIONS[y], a16()), 3),
+            4 => (String::from("ldh [c], a"), 1),
+            5 => (format!("ld [{}], a", a16()), 3),
+            6 => (String::from("ldh a, [c]"), 1),
+            _ => (format!("ld a, [{}]", a16()), 3),
+        },
+        (3, 3) => match y {
+            0 => (format!("jp {}", a16()), 3),
+            1 => (decode_prefixed(read(1)), 2),
+            6 => (String::from("di"), 1),
+            7 => (String::from("ei"), 1),
+            _ => (illegal(opcode), 1),
+        },
+        (3, 4) if y < 4 => (format!("call {}, {}", CONDITIONS[y], a16()), 3),
+        (3, 5) if q == 0 => (format!("push {}", R16_STACK[p]), 1),
+        (3, 5) if p == 0 => (format!("call {}", a16()), 3),
+        (3, 6) => (format!("{} a, {}", ALU[y], n8()), 2),
+        (3, 7) => (format!("rst ${:02X}", y * 8), 1),
+        _ => (illegal(opcode), 1),
+    };
+}
+
+// Opcodes following the 0xCB prefix
+fn decode_prefixed(opcode: u8) -> String {
+    let y = ((opcode >> 3) & 0x07) as usize;
+    let z = (opcode & 0x07) as usize;
+    return match opcode >> 6 {
+        0 => format!("{} {}", ROTATIONS[y], R8[z]),
+        1 => format!("bit {}, {}", y, R8[z]),
+        2 => format!("res {}, {}", y, R8[z]),
+        _ => format!("set {}, {}", y, R8[z]),
+    };
+}
+
+// The opcodes without an instruction lock up the cpu
+fn illegal(opcode: u8) -> String {
+    return format!("db ${:02X}", opcode);
+}
+
+fn offset(value: i8) -> String {
+    return if value < 0 { format!("-${:02X}", value.unsigned_abs()) } else { format!("${:02X}", value) };
+}
+
+fn signed_offset(value: i8) -> String {
+    return if value < 0 { offset(value) } else { format!("+{}", offset(value)) };
+}
@@ -1,7 +1,8 @@
 use crate::mmu::Mmu;
-use wasm_bindgen::prelude::*;
 use crate::console::GameboyType;
 use crate::ppu::Ppu;
+use crate::error::EmulatorError;
+use crate::state::{StateWriter, StateReader};
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 enum DMAType {
@@ -10,7 +11,6 @@ enum DMAType {
     HDMA,
 }
 
-#[wasm_bindgen]
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Dma {
     dma: [u8; 4],
@@ -39,6 +39,28 @@ impl Dma {
         self.dma_status = DMAType::NONE;
     }
 
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.dma);
+        writer.write_u8(match self.dma_status { DMAType::NONE => 0, DMAType::GDMA => 1, DMAType::HDMA => 2 });
+        writer.write_u16(self.dma_source);
+        writer.write_u16(self.dma_destination);
+        writer.write_u8(self.dma_length);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<(), EmulatorError> {
+        reader.read_bytes_into(&mut self.dma)?;
+        self.dma_status = match reader.read_u8()? {
+            0 => DMAType::NONE,
+            1 => DMAType::GDMA,
+            2 => DMAType::HDMA,
+            _ => return Err(reader.invalid("dma status")),
+        };
+        self.dma_source = reader.read_u16()?;
+        self.dma_destination = reader.read_u16()?;
+        self.dma_length = reader.read_u8()?;
+        return Ok(());
+    }
+
     pub fn read_byte(&self, address: u16) -> u8 {
         match address {
             0xFF51 ..= 0xFF54 => { self.dma[(address - 0xFF51) as usize] },
@@ -47,20 +69,23 @@ impl Dma {
         }
     }
 
-    pub fn write_byte(&mut self, address: u16, value: u8) {
+    pub fn write_byte(&mut self, address: u16, value: u8) -> Result<(), EmulatorError> {
         match address {
-            0xFF51 => self.dma[0] = value & 0x0F,
+            // https://gbdev.io/pandocs/#ff51-ff52-hdma1-hdma2-cgb-mode-only-new-dma-source-high-low-w
+            0xFF51 => self.dma[0] = value,
             0xFF52 => self.dma[1] = value & 0xF0,
-            0xFF53 => self.dma[2] = value & 0x0F,
+            0xFF53 => self.dma[2] = value & 0x1F,
             0xFF54 => self.dma[3] = value & 0xF0,
             0xFF55 => {
                 if self.dma_status == DMAType::HDMA {
                     if value & 0x80 == 0 { self.dma_status = DMAType::NONE; };
-                    return;
+                    return Ok(());
                 }
                 let src = ((self.dma[0] as u16) << 8) | (self.dma[1] as u16);
                 let dst = ((self.dma[2] as u16) << 8) | (self.dma[3] as u16) | 0x8000;
-                if !(src <= 0x7FF0 || (src >= 0xA000 && src <= 0xDFF0)) { panic!("HDMA transfer with illegal start address {:04X}", src); }
+                if !(src <= 0x7FF0 || (src >= 0xA000 && src <= 0xDFF0)) {
+                    return Err(EmulatorError::InvalidDma { source: src });
+                }
 
                 self.dma_source = src;
                 self.dma_destination = dst;
@@ -72,6 +97,8 @@ impl Dma {
             },
             _ => panic!("The address {:04X} should not be handled by hdma_write", address),
         };
+
+        return Ok(());
     }
 
 }
@@ -90,6 +117,8 @@ fn execute_hdma(mmu: &mut Mmu) -> u32 {
     if mmu.ppu.h_blank == false {
         return 0;
     }
+    // One block per H-Blank
+    mmu.ppu.h_blank = false;
     execute_transfer(mmu);
     if mmu.dma.dma_length == 0x7F { mmu.dma.dma_status = DMAType::NONE; }
 
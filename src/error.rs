@@ -0,0 +1,63 @@
+use std::error::Error;
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EmulatorError {
+    // The cartridge type at 0x147 of the header is not emulated
+    UnsupportedMapper(u8),
+    // The rom is smaller than its header or the size declared at 0x148
+    TruncatedRom { expected: usize, actual: usize },
+    // The cpu fetched one of the undefined opcodes (D3, DB, DD, E3, E4, EB, EC, ED, F4, FC, FD) and
+    // locked up, reported once, running on keeps the cpu locked until a reset
+    IllegalOpcode { opcode: u8, address: u16 },
+    // A HDMA transfer was started from a source outside of rom, sram or wram
+    InvalidDma { source: u16 },
+    // The save state is corrupt or from an incompatible version
+    InvalidState(String),
+    // The save state was made against a different rom, checksums are CRC32 of the rom
+    RomMismatch { expected: u32, actual: u32 },
+    // Imported battery ram does not match the ram size of the cartridge
+    InvalidSave { expected: usize, actual: usize },
+    // A breakpoint condition is not of the form "register comparison value"
+    InvalidCondition(String),
+    // A line of a symbol file is not of the form "bank:address name"
+    InvalidSymbols { line: usize },
+    // No label of that name was loaded
+    UnknownSymbol(String),
+    // A boot rom is 256 bytes for a DMG or 2304 bytes for a CGB
+    InvalidBootRom { size: usize },
+    Io(String),
+}
+
+impl fmt::Display for EmulatorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EmulatorError::UnsupportedMapper(cartridge_type) =>
+                write!(f, "cartridge type {:#04X} is not supported", cartridge_type),
+            EmulatorError::TruncatedRom { expected, actual } =>
+                write!(f, "rom is truncated, expected {} bytes but got {}", expected, actual),
+            EmulatorError::IllegalOpcode { opcode, address } =>
+                write!(f, "cpu locked up on illegal opcode {:#04X} at {:#06X}", opcode, address),
+            EmulatorError::InvalidDma { source } =>
+                write!(f, "HDMA transfer with illegal source address {:#06X}", source),
+            EmulatorError::InvalidState(message) =>
+                write!(f, "invalid save state: {}", message),
+            EmulatorError::RomMismatch { expected, actual } =>
+                write!(f, "save state was made for rom {:08X}, loaded rom is {:08X}", actual, expected),
+            EmulatorError::InvalidSave { expected, actual } =>
+                write!(f, "save is {} bytes, the cartridge has {} bytes of battery ram", actual, expected),
+            EmulatorError::InvalidCondition(condition) =>
+                write!(f, "invalid breakpoint condition \"{}\"", condition),
+            EmulatorError::InvalidSymbols { line } =>
+                write!(f, "invalid symbol file, line {} is not of the form bank:address name", line),
+            EmulatorError::UnknownSymbol(name) =>
+                write!(f, "unknown symbol {}", name),
+            EmulatorError::InvalidBootRom { size } =>
+                write!(f, "boot rom is {} bytes, expected 256 for a DMG or 2304 for a CGB", size),
+            EmulatorError::Io(message) =>
+                write!(f, "{}", message),
+        }
+    }
+}
+
+impl Error for EmulatorError {}
@@ -0,0 +1,365 @@
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+use crate::console::Console;
+use crate::cpu::Registers;
+use crate::debugger::{Debugger, Breakpoint, Watchpoint, Access, PauseReason};
+use crate::error::EmulatorError;
+
+// https://sourceware.org/gdb/current/onlinedocs/gdb.html/Remote-Protocol.html
+// Registers are the pairs af, bc, de, hl, sp and pc, 16 bits little endian in g and G packets.
+// gbz80 is the name binutils uses for the SM83.
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <architecture>gbz80</architecture>
+  <feature name="org.rustyboy.sm83.cpu">
+    <reg name="af" bitsize="16" type="int" regnum="0"/>
+    <reg name="bc" bitsize="16" type="int"/>
+    <reg name="de" bitsize="16" type="int"/>
+    <reg name="hl" bitsize="16" type="data_ptr"/>
+    <reg name="sp" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+  </feature>
+</target>
+"#;
+
+const REGISTER_COUNT: usize = 6;
+
+// Advertised in qSupported, memory reads are cut short to fit their hex in it
+const PACKET_SIZE: usize = 0x1000;
+const MAX_READ: u16 = (PACKET_SIZE / 2 - 1) as u16;
+
+// Sent by gdb outside of a packet to interrupt the target
+const INTERRUPT: u8 = 0x03;
+
+// Signals in stop replies
+const SIGINT: u8 = 0x02;
+const SIGILL: u8 = 0x04;
+const SIGTRAP: u8 = 0x05;
+
+enum Packet {
+    Command(String),
+    Interrupt,
+}
+
+// Serves one gdb session over TCP, for example after "target remote localhost:2345". The console
+// only runs while gdb continues or steps it, through a Debugger that holds the breakpoints and
+// watchpoints gdb inserts.
+pub struct GdbStub {
+    stream: TcpStream,
+    debugger: Debugger,
+}
+
+impl GdbStub {
+
+    // Waits for gdb to connect
+    pub fn accept(listener: &TcpListener) -> Result<GdbStub, EmulatorError> {
+        let (stream, _) = listener.accept().map_err(io_error)?;
+        stream.set_nodelay(true).map_err(io_error)?;
+        return Ok(GdbStub {
+            stream,
+            debugger: Debugger::new(),
+        });
+    }
+
+    // Answers packets until gdb detaches, kills the session or disconnects
+    pub fn serve(&mut self, console: &mut Console) -> Result<(), EmulatorError> {
+        loop {
+            let command = match self.read_packet()? {
+                Some(Packet::Command(command)) => command,
+                // Only meaningful while running, the console is already stopped
+                Some(Packet::Interrupt) => continue,
+                None => return Ok(()),
+            };
+            match command.chars().next() {
+                Some('D') => return self.write_packet("OK"),
+                Some('k') => return Ok(()),
+                _ => {},
+            }
+            let reply = self.execute(console, &command)?;
+            self.write_packet(&reply)?;
+        }
+    }
+
+    fn execute(&mut self, console: &mut Console, command: &str) -> Result<String, EmulatorError> {
+        let name = match command.chars().next() {
+            Some(name) => name,
+            None => return Ok(String::new()),
+        };
+        let arguments = &command[name.len_utf8() ..];
+        let reply = match name {
+            '?' => format!("S{:02x}", SIGTRAP),
+            'g' => read_registers(console),
+            'G' => ok_or_error(write_registers(console, arguments)),
+            'p' => match parse_hex(arguments).filter(|n| (*n as usize) < REGISTER_COUNT) {
+                Some(n) => to_hex(&register_pair(&console.get_registers(), n as usize).to_le_bytes()),
+                None => String::from("E01"),
+            },
+            'P' => ok_or_error(write_register(console, arguments)),
+            'm' => match parse_range(arguments) {
+                Some((address, length)) => {
+                    let bytes: Vec<u8> = (0 .. length.min(MAX_READ)).map(|i| console.read_byte(address.wrapping_add(i))).collect();
+                    to_hex(&bytes)
+                },
+                None => String::from("E01"),
+            },
+            'M' => ok_or_error(write_memory(console, arguments)),
+            'Z' | 'z' => self.update_breakpoint(name == 'Z', arguments),
+            'c' | 's' => {
+                // An optional address to resume from
+                if let Some(address) = parse_hex(arguments) {
+                    let mut registers = console.get_registers();
+                    registers.pc = address;
+                    console.set_registers(&registers);
+                }
+                if name == 'c' { self.resume(console)? } else { self.step(console)? }
+            },
+            'H' => String::from("OK"),
+            'q' => query(arguments),
+            _ => String::new(),
+        };
+        return Ok(reply);
+    }
+
+    // Z0 and Z1 are breakpoints, Z2 write, Z3 read and Z4 access watchpoints, of length bytes
+    fn update_breakpoint(&mut self, insert: bool, arguments: &str) -> String {
+        let mut fields = arguments.split(',');
+        let kind = fields.next();
+        let address = fields.next().and_then(parse_hex);
+        let length = fields.next().and_then(parse_hex).unwrap_or(1).max(1);
+        let address = match address {
+            Some(address) => address,
+            None => return String::from("E01"),
+        };
+        let watch = |access| Watchpoint { start: address, end: address.saturating_add(length - 1), access };
+
+        let watchpoints = match kind {
+            Some("0") | Some("1") => {
+                if insert {
+                    self.debugger.add_breakpoint(Breakpoint::new(address));
+                } else {
+                    self.debugger.remove_breakpoint(address, None);
+                }
+                return String::from("OK");
+            },
+            Some("2") => vec![watch(Access::WRITE)],
+            Some("3") => vec![watch(Access::READ)],
+            Some("4") => vec![watch(Access::READ), watch(Access::WRITE)],
+            _ => return String::new(),
+        };
+        for watchpoint in watchpoints {
+            if insert {
+                self.debugger.add_watchpoint(watchpoint);
+            } else {
+                self.debugger.remove_watchpoint(&watchpoint);
+            }
+        }
+        return String::from("OK");
+    }
+
+    // Runs frames until the debugger pauses or gdb interrupts
+    fn resume(&mut self, console: &mut Console) -> Result<String, EmulatorError> {
+        self.stream.set_nonblocking(true).map_err(io_error)?;
+        let result = self.run_until_stopped(console);
+        self.stream.set_nonblocking(false).map_err(io_error)?;
+        return result;
+    }
+
+    fn run_until_stopped(&mut self, console: &mut Console) -> Result<String, EmulatorError> {
+        loop {
+            if let Some(reason) = self.debugger.run_frame(console)? {
+                return Ok(stop_reply(reason));
+            }
+            let mut byte = [0];
+            match self.stream.read(&mut byte) {
+                Ok(0) => return Err(EmulatorError::Io(String::from("gdb: connection closed"))),
+                Ok(_) if byte[0] == INTERRUPT => return Ok(format!("S{:02x}", SIGINT)),
+                Ok(_) => {},
+                Err(error) if error.kind() == ErrorKind::WouldBlock => {},
+                Err(error) => return Err(io_error(error)),
+            }
+        }
+    }
+
+    fn step(&mut self, console: &mut Console) -> Result<String, EmulatorError> {
+        self.debugger.step_into();
+        let reason = self.debugger.execute_ticks(console, 1)?;
+        return Ok(reason.map_or(format!("S{:02x}", SIGTRAP), stop_reply));
+    }
+
+    // Acknowledges and returns the next packet, None when gdb disconnected
+    fn read_packet(&mut self) -> Result<Option<Packet>, EmulatorError> {
+        loop {
+            let byte = match self.read_byte()? {
+                Some(byte) => byte,
+                None => return Ok(None),
+            };
+            match byte {
+                INTERRUPT => return Ok(Some(Packet::Interrupt)),
+                b'$' => {},
+                // Acknowledgements of our replies, a resend request is not worth supporting
+                _ => continue,
+            }
+
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    Some(b'#') => break,
+                    Some(byte) => data.push(byte),
+                    None => return Ok(None),
+                }
+            }
+            let mut checksum = [0; 2];
+            self.stream.read_exact(&mut checksum).map_err(io_error)?;
+
+            let expected = std::str::from_utf8(&checksum).ok().and_then(|hex| u8::from_str_radix(hex, 16).ok());
+            if expected != Some(packet_checksum(&data)) {
+                self.stream.write_all(b"-").map_err(io_error)?;
+                continue;
+            }
+            self.stream.write_all(b"+").map_err(io_error)?;
+            return Ok(Some(Packet::Command(String::from_utf8_lossy(&data).into_owned())));
+        }
+    }
+
+    fn read_byte(&mut self) -> Result<Option<u8>, EmulatorError> {
+        let mut byte = [0];
+        return match self.stream.read(&mut byte) {
+            Ok(0) => Ok(None),
+            Ok(_) => Ok(Some(byte[0])),
+            Err(error) => Err(io_error(error)),
+        };
+    }
+
+    fn write_packet(&mut self, data: &str) -> Result<(), EmulatorError> {
+        let packet = format!("${}#{:02x}", data, packet_checksum(data.as_bytes()));
+        return self.stream.write_all(packet.as_bytes()).map_err(io_error);
+    }
+}
+
+fn query(arguments: &str) -> String {
+    if arguments.starts_with("Supported") {
+        return format!("PacketSize={:x};qXfer:features:read+;swbreak+;hwbreak+", PACKET_SIZE);
+    }
+    if arguments == "Attached" {
+        return String::from("1");
+    }
+    // Xfer:features:read:target.xml:offset,length
+    if let Some(range) = arguments.strip_prefix("Xfer:features:read:target.xml:") {
+        return match parse_range(range) {
+            Some((offset, length)) => {
+                let start = (offset as usize).min(TARGET_XML.len());
+                let end = (start + length as usize).min(TARGET_XML.len());
+                let prefix = if end == TARGET_XML.len() { "l" } else { "m" };
+                format!("{}{}", prefix, &TARGET_XML[start .. end])
+            },
+            None => String::from("E01"),
+        };
+    }
+    return String::new();
+}
+
+fn stop_reply(reason: PauseReason) -> String {
+    return match reason {
+        PauseReason::Breakpoint { .. } => format!("T{:02x}swbreak:;", SIGTRAP),
+        PauseReason::Watchpoint { address, access: Access::READ, .. } => format!("T{:02x}rwatch:{:04x};", SIGTRAP, address),
+        PauseReason::Watchpoint { address, access: Access::WRITE, .. } => format!("T{:02x}watch:{:04x};", SIGTRAP, address),
+        PauseReason::Watchpoint { access: Access::EXECUTE, .. } => format!("T{:02x}hwbreak:;", SIGTRAP),
+        PauseReason::Step => format!("S{:02x}", SIGTRAP),
+        PauseReason::LockedUp => format!("S{:02x}", SIGILL),
+    };
+}
+
+fn register_pair(registers: &Registers, index: usize) -> u16 {
+    let pair = |high: u8, low: u8| (high as u16) << 8 | low as u16;
+    return match index {
+        0 => pair(registers.a, registers.f),
+        1 => pair(registers.b, registers.c),
+        2 => pair(registers.d, registers.e),
+        3 => pair(registers.h, registers.l),
+        4 => registers.sp,
+        _ => registers.pc,
+    };
+}
+
+fn set_register_pair(registers: &mut Registers, index: usize, value: u16) {
+    let [low, high] = value.to_le_bytes();
+    match index {
+        0 => { registers.a = high; registers.f = low; },
+        1 => { registers.b = high; registers.c = low; },
+        2 => { registers.d = high; registers.e = low; },
+        3 => { registers.h = high; registers.l = low; },
+        4 => registers.sp = value,
+        _ => registers.pc = value,
+    }
+}
+
+fn read_registers(console: &Console) -> String {
+    let registers = console.get_registers();
+    let bytes: Vec<u8> = (0 .. REGISTER_COUNT).flat_map(|index| register_pair(&registers, index).to_le_bytes()).collect();
+    return to_hex(&bytes);
+}
+
+fn write_registers(console: &mut Console, arguments: &str) -> Option<()> {
+    let bytes = from_hex(arguments).filter(|bytes| bytes.len() == REGISTER_COUNT * 2)?;
+    let mut registers = console.get_registers();
+    for (index, pair) in bytes.chunks(2).enumerate() {
+        set_register_pair(&mut registers, index, u16::from_le_bytes([pair[0], pair[1]]));
+    }
+    console.set_registers(&registers);
+    return Some(());
+}
+
+// P n=value, the value is little endian like in the g packet
+fn write_register(console: &mut Console, arguments: &str) -> Option<()> {
+    let (index, value) = arguments.split_once('=')?;
+    let index = parse_hex(index).filter(|index| (*index as usize) < REGISTER_COUNT)? as usize;
+    let value = from_hex(value).filter(|bytes| bytes.len() == 2)?;
+    let mut registers = console.get_registers();
+    set_register_pair(&mut registers, index, u16::from_le_bytes([value[0], value[1]]));
+    console.set_registers(&registers);
+    return Some(());
+}
+
+// M address,length:bytes
+fn write_memory(console: &mut Console, arguments: &str) -> Option<()> {
+    let (range, data) = arguments.split_once(':')?;
+    let (address, length) = parse_range(range)?;
+    let bytes = from_hex(data).filter(|bytes| bytes.len() == length as usize)?;
+    for (i, byte) in bytes.iter().enumerate() {
+        console.write_byte(address.wrapping_add(i as u16), *byte);
+    }
+    return Some(());
+}
+
+fn ok_or_error(result: Option<()>) -> String {
+    return String::from(if result.is_some() { "OK" } else { "E01" });
+}
+
+// address,length in hex
+fn parse_range(text: &str) -> Option<(u16, u16)> {
+    let (address, length) = text.split_once(',')?;
+    return Some((parse_hex(address)?, parse_hex(length)?));
+}
+
+fn parse_hex(text: &str) -> Option<u16> {
+    return u16::from_str_radix(text, 16).ok();
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    return bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+}
+
+// None for odd lengths, the last pair is out of range
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    return (0 .. text.len()).step_by(2).map(|i| u8::from_str_radix(text.get(i .. i + 2)?, 16).ok()).collect();
+}
+
+fn packet_checksum(data: &[u8]) -> u8 {
+    return data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+}
+
+fn io_error(error: std::io::Error) -> EmulatorError {
+    return EmulatorError::Io(format!("gdb: {}", error));
+}
@@ -0,0 +1,121 @@
+use crate::error::EmulatorError;
+
+// https://gbdev.io/pandocs/#the-cartridge-header
+pub const HEADER_END: usize = 0x0150;
+
+const NINTENDO_LOGO: [u8; 0x30] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
+    0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
+    0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CartridgeHeader {
+    // 0134-0143, 0134-013E on cartridges with a manufacturer code
+    pub title: String,
+    // 013F-0142, empty on older cartridges
+    pub manufacturer_code: String,
+    // 0143, 80h supports CGB functions, C0h works on CGB only
+    pub cgb_flag: u8,
+    // 0146, 03h supports SGB functions
+    pub sgb_flag: u8,
+    // 014B, or the two characters at 0144-0145 when 014B is 33h
+    pub licensee_code: String,
+    // 0147
+    pub cartridge_type: u8,
+    // 0148 and 0149 in bytes, None for unknown size codes
+    pub rom_size: Option<usize>,
+    pub ram_size: Option<usize>,
+    // 014A, 00h Japanese, 01h Non-Japanese
+    pub destination_code: u8,
+    // 014C
+    pub version: u8,
+    // 014D, checked by the boot rom
+    pub header_checksum: u8,
+    pub header_checksum_valid: bool,
+    // 014E-014F big endian, not checked by the boot rom
+    pub global_checksum: u16,
+    pub global_checksum_valid: bool,
+    // 0104-0133, checked by the boot rom
+    pub logo_valid: bool,
+}
+
+impl CartridgeHeader {
+
+    pub fn parse(rom: &[u8]) -> Result<CartridgeHeader, EmulatorError> {
+        if rom.len() < HEADER_END {
+            return Err(EmulatorError::TruncatedRom { expected: HEADER_END, actual: rom.len() });
+        }
+
+        let cgb_flag = rom[0x143];
+        let manufacturer_code = &rom[0x13F ..= 0x142];
+        let has_manufacturer_code = cgb_flag & 0x80 == 0x80 &&
+            manufacturer_code.iter().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit());
+
+        let title_end = if cgb_flag & 0x80 == 0x80 { if has_manufacturer_code { 0x13E } else { 0x142 } } else { 0x143 };
+        let title = rom[0x134 ..= title_end].iter()
+            .take_while(|c| **c != 0)
+            .map(|c| if c.is_ascii_graphic() || *c == b' ' { *c as char } else { '?' })
+            .collect::<String>()
+            .trim_end()
+            .to_string();
+
+        let licensee_code = match rom[0x14B] {
+            0x33 => String::from_utf8_lossy(&rom[0x144 ..= 0x145]).into_owned(),
+            code => format!("{:02X}", code),
+        };
+
+        let header_checksum = rom[0x14D];
+        let computed_header_checksum = rom[0x134 ..= 0x14C].iter()
+            .fold(0u8, |checksum, byte| checksum.wrapping_sub(*byte).wrapping_sub(1));
+
+        let global_checksum = ((rom[0x14E] as u16) << 8) | rom[0x14F] as u16;
+        let computed_global_checksum = rom.iter().enumerate()
+            .filter(|(i, _)| *i != 0x14E && *i != 0x14F)
+            .fold(0u16, |checksum, (_, byte)| checksum.wrapping_add(*byte as u16));
+
+        return Ok(CartridgeHeader {
+            title,
+            manufacturer_code: if has_manufacturer_code { String::from_utf8_lossy(manufacturer_code).into_owned() } else { String::new() },
+            cgb_flag,
+            sgb_flag: rom[0x146],
+            licensee_code,
+            cartridge_type: rom[0x147],
+            rom_size: rom_size(rom[0x148]),
+            ram_size: ram_size(rom[0x149]),
+            destination_code: rom[0x14A],
+            version: rom[0x14C],
+            header_checksum,
+            header_checksum_valid: header_checksum == computed_header_checksum,
+            global_checksum,
+            global_checksum_valid: global_checksum == computed_global_checksum,
+            logo_valid: rom[0x104 ..= 0x133] == NINTENDO_LOGO,
+        });
+    }
+
+    // A real cartridge passes the checks done by the boot rom
+    pub fn is_valid(&self) -> bool {
+        return self.logo_valid && self.header_checksum_valid;
+    }
+}
+
+// https://gbdev.io/pandocs/#_0148-rom-size
+fn rom_size(code: u8) -> Option<usize> {
+    return match code {
+        0x00 ..= 0x08 => Some(0x8000 << code),
+        _ => None,
+    };
+}
+
+// https://gbdev.io/pandocs/#_0149-ram-size
+fn ram_size(code: u8) -> Option<usize> {
+    return match code {
+        0x00 => Some(0),
+        0x01 => Some(0x800),
+        0x02 => Some(0x2000),
+        0x03 => Some(0x8000),
+        0x04 => Some(0x20000),
+        0x05 => Some(0x10000),
+        _ => None,
+    };
+}
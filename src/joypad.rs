@@ -1,12 +1,14 @@
+use crate::error::EmulatorError;
+use crate::state::{StateWriter, StateReader};
+#[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::*;
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Button {
     RIGHT, LEFT, UP, DOWN, A, B, SELECT, START
 }
 
-#[wasm_bindgen]
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Joypad {
     row0: u8,
@@ -15,7 +17,6 @@ pub struct Joypad {
     pub interrupt: u8,
 }
 
-#[wasm_bindgen]
 impl Joypad {
 
     pub fn new() -> Joypad {
@@ -27,6 +28,21 @@ impl Joypad {
         }
     }
 
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.row0);
+        writer.write_u8(self.row1);
+        writer.write_u8(self.data);
+        writer.write_u8(self.interrupt);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<(), EmulatorError> {
+        self.row0 = reader.read_u8()?;
+        self.row1 = reader.read_u8()?;
+        self.data = reader.read_u8()?;
+        self.interrupt = reader.read_u8()?;
+        return Ok(());
+    }
+
     pub fn read_byte(&self, address: u16) -> u8 {
         return self.data
     }
@@ -36,6 +52,11 @@ impl Joypad {
         self.trigger_interrupt();
     }
 
+    // True while a pressed button is on a selected row, this wakes the cpu from STOP
+    pub fn is_line_low(&self) -> bool {
+        return self.data & 0x0F != 0x0F;
+    }
+
     pub fn trigger_interrupt(&mut self) {
         let old_values = self.data & 0xF;
         let mut new_values = 0xF;
@@ -10,17 +10,46 @@ mod timer;
 mod logger;
 mod joypad;
 mod psg;
-
-extern crate serde_json;
-extern crate wasm_bindgen;
-extern crate js_sys;
-
-#[macro_use]
-extern crate serde_derive;
-
-use wasm_bindgen::prelude::*;
-use wasm_bindgen::JsCast;
-use web_sys::{CanvasRenderingContext2d, HtmlCanvasElement};
+mod error;
+mod state;
+mod rtc;
+mod header;
+mod serial;
+mod link;
+mod printer;
+mod disassembler;
+mod debugger;
+mod symbols;
+mod profiler;
+#[cfg(not(target_arch = "wasm32"))]
+mod tcp_link;
+#[cfg(not(target_arch = "wasm32"))]
+mod gdb;
+
+#[cfg(feature = "wasm")]
+mod wasm;
+
+pub use crate::console::{Console, GameboyType};
+pub use crate::cpu::{CpuState, Register, Registers};
+pub use crate::joypad::Button;
+pub use crate::error::EmulatorError;
+pub use crate::header::CartridgeHeader;
+pub use crate::serial::SerialDevice;
+pub use crate::link::LinkedPair;
+pub use crate::disassembler::Instruction;
+pub use crate::symbols::SymbolTable;
+pub use crate::debugger::{Debugger, Breakpoint, Watchpoint, Access, Condition, Comparison, PauseReason, Frame};
+pub use crate::profiler::{Profiler, Hotspot, COVERAGE_CODE, COVERAGE_DATA};
+pub use crate::logger::{TraceSink, TraceFilter, TraceBuffer};
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::logger::FileTrace;
+pub use crate::printer::{Printer, PrintJob, PrintQueue, PRINT_WIDTH};
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::tcp_link::{TcpLink, DEFAULT_SYNC_WINDOW};
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::gdb::GdbStub;
+pub use crate::ppu::{SCREEN_W, SCREEN_H};
+pub use crate::psg::SAMPLE_RATE;
 
 // When the `wee_alloc` feature is enabled, this uses `wee_alloc` as the global
 // allocator.
@@ -28,26 +57,3 @@ use web_sys::{CanvasRenderingContext2d, HtmlCanvasElement};
 #[cfg(feature = "wee_alloc")]
 #[global_allocator]
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
-
-// Logging
-#[wasm_bindgen]
-extern "C" {
-    #[wasm_bindgen(js_namespace = console)]
-    fn log(s: &str);
-
-    #[wasm_bindgen(js_namespace = console, js_name = log)]
-    fn log_u32(a: u32);
-
-    #[wasm_bindgen(js_namespace = console, js_name = log)]
-    fn log_many(a: &str, b: &str);
-}
-
-//static mut CONSOLE: Console = Console::new();
-
-#[wasm_bindgen(start)]
-pub fn load_cartridge() -> Result<(), JsValue> {
-    //let bytes: Vec<u8> = value.into_serde().unwrap();
-    //CONSOLE.load(bytes);
-
-    Ok(())
-}
@@ -0,0 +1,104 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+use crate::console::Console;
+use crate::error::EmulatorError;
+use crate::serial::SerialDevice;
+
+// 154 lines of 456 cycles
+pub(crate) const CYCLES_PER_FRAME: u32 = 70224;
+
+// Plugged into each console of a pair. The peer is owned by the pair and can't be reached from
+// inside a transfer, so the byte is handed over after the instruction that started it and 1s
+// are shifted in until then.
+pub(crate) struct LinkPort {
+    pub(crate) sent: Rc<Cell<Option<u8>>>,
+}
+
+impl SerialDevice for LinkPort {
+    fn transfer(&mut self, byte: u8) -> u8 {
+        self.sent.set(Some(byte));
+        return 0xFF;
+    }
+}
+
+// Two consoles connected by a link cable. They run in lockstep, whichever console is behind
+// runs the next instruction, so they never drift apart by more than one instruction.
+pub struct LinkedPair {
+    // Boxed, a console is too large to move around on the stack
+    consoles: [Box<Console>; 2],
+    sent: [Rc<Cell<Option<u8>>>; 2],
+    // Normal speed clock cycles run by each console and by the pair
+    cycles: [u64; 2],
+    time: u64,
+}
+
+impl LinkedPair {
+
+    // Load a rom into each console and reset the pair before running
+    pub fn new() -> Self {
+        let mut pair = LinkedPair {
+            consoles: [Box::new(Console::new()), Box::new(Console::new())],
+            sent: [Rc::new(Cell::new(None)), Rc::new(Cell::new(None))],
+            cycles: [0, 0],
+            time: 0,
+        };
+        for index in 0 .. 2 {
+            let port = LinkPort { sent: pair.sent[index].clone() };
+            pair.consoles[index].attach_serial_device(Box::new(port));
+        }
+        return pair;
+    }
+
+    // Index 0 or 1
+    pub fn console(&self, index: usize) -> &Console {
+        return &self.consoles[index];
+    }
+
+    pub fn console_mut(&mut self, index: usize) -> &mut Console {
+        return &mut self.consoles[index];
+    }
+
+    pub fn reset(&mut self) {
+        for index in 0 .. 2 {
+            self.consoles[index].reset();
+            self.sent[index].set(None);
+        }
+        self.cycles = [0, 0];
+        self.time = 0;
+    }
+
+    // Runs both consoles for the given number of normal speed clock cycles
+    pub fn execute_cycles(&mut self, cycles: u32) -> Result<(), EmulatorError> {
+        self.time += cycles as u64;
+        while self.cycles[0] < self.time || self.cycles[1] < self.time {
+            let index = if self.cycles[0] <= self.cycles[1] { 0 } else { 1 };
+            self.step(index)?;
+        }
+        return Ok(());
+    }
+
+    // Runs both consoles for the duration of one frame, the screens are not synchronized to it
+    pub fn run_frame(&mut self) -> Result<(), EmulatorError> {
+        return self.execute_cycles(CYCLES_PER_FRAME);
+    }
+
+    fn step(&mut self, index: usize) -> Result<(), EmulatorError> {
+        self.cycles[index] += self.consoles[index].step()? as u64;
+
+        // A transfer on the internal clock clocks the peer, which answers only when it is
+        // waiting with the external clock
+        if let Some(byte) = self.sent[index].take() {
+            let fast = self.consoles[index].is_serial_fast();
+            let reply = self.consoles[1 - index].external_serial_transfer(byte, fast).unwrap_or(0xFF);
+            self.consoles[index].complete_serial_transfer(reply);
+        }
+        return Ok(());
+    }
+}
+
+impl Default for LinkedPair {
+    fn default() -> Self {
+        return LinkedPair::new();
+    }
+}
@@ -1,24 +1,147 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+#[cfg(not(target_arch = "wasm32"))]
+use std::fs::File;
+#[cfg(not(target_arch = "wasm32"))]
+use std::io::{BufWriter, Write};
+#[cfg(not(target_arch = "wasm32"))]
 use std::path::Path;
-use std::fs::{OpenOptions, File};
-use std::io::Write;
-use std::convert::TryInto;
-
-const FILE_PATH: &str = "cpu.log";
-
-static mut FILE: Option<File> = Option::None;
-
-pub fn log(value: String) {
-    println!("{}", value);
-    unsafe {
-        if FILE.is_none() {
-            FILE = Option::Some(OpenOptions::new()
-                .create(true)
-                .write(true)
-                .open(FILE_PATH)
-                .unwrap());
+
+use crate::cpu::Cpu;
+use crate::mmu::Mmu;
+use crate::error::EmulatorError;
+
+// Receives a line per traced instruction, see Console::start_trace
+pub trait TraceSink {
+    fn write_line(&mut self, line: &str) -> Result<(), EmulatorError>;
+
+    // Called when the trace is stopped
+    fn flush(&mut self) -> Result<(), EmulatorError> {
+        return Ok(());
+    }
+}
+
+// Only instructions with pc in start..=end, and in the bank when one is given, are traced
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TraceFilter {
+    pub start: u16,
+    pub end: u16,
+    pub bank: Option<usize>,
+}
+
+impl TraceFilter {
+
+    pub fn new(start: u16, end: u16) -> Self {
+        return TraceFilter { start, end, bank: None };
+    }
+
+    pub fn matches(&self, bank: usize, pc: u16) -> bool {
+        return (self.start ..= self.end).contains(&pc) && self.bank.unwrap_or(bank) == bank;
+    }
+}
+
+pub struct Tracer {
+    pub sink: Box<dyn TraceSink>,
+    pub filter: Option<TraceFilter>,
+}
+
+// The last lines of a trace kept in memory, shared between the console and the host
+#[derive(Clone)]
+pub struct TraceBuffer {
+    lines: Rc<RefCell<VecDeque<String>>>,
+    capacity: usize,
+}
+
+impl TraceBuffer {
+
+    // Older lines are dropped once capacity lines are buffered
+    pub fn new(capacity: usize) -> Self {
+        return TraceBuffer {
+            lines: Rc::new(RefCell::new(VecDeque::new())),
+            capacity,
         }
+    }
 
-        FILE.as_mut().unwrap().write_all((value + "\n").as_bytes());
+    pub fn take_lines(&self) -> Vec<String> {
+        return self.lines.borrow_mut().drain(..).collect();
+    }
+
+    pub fn len(&self) -> usize {
+        return self.lines.borrow().len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.lines.borrow().is_empty();
+    }
+}
+
+impl TraceSink for TraceBuffer {
+    fn write_line(&mut self, line: &str) -> Result<(), EmulatorError> {
+        let mut lines = self.lines.borrow_mut();
+        lines.push_back(line.to_string());
+        while lines.len() > self.capacity {
+            lines.pop_front();
+        }
+        return Ok(());
+    }
+}
+
+// Writes the trace to a file, one line per instruction
+#[cfg(not(target_arch = "wasm32"))]
+pub struct FileTrace {
+    writer: BufWriter<File>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl FileTrace {
+
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, EmulatorError> {
+        let file = File::create(path).map_err(io_error)?;
+        return Ok(FileTrace { writer: BufWriter::new(file) });
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl TraceSink for FileTrace {
+    fn write_line(&mut self, line: &str) -> Result<(), EmulatorError> {
+        return writeln!(self.writer, "{}", line).map_err(io_error);
+    }
+
+    fn flush(&mut self) -> Result<(), EmulatorError> {
+        return self.writer.flush().map_err(io_error);
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn io_error(error: std::io::Error) -> EmulatorError {
+    return EmulatorError::Io(format!("trace: {}", error));
+}
+
+// https://github.com/robert/gameboy-doctor
+// The registers before the instruction runs and the 4 bytes at pc, A:01 F:B0 B:00 C:13 D:00
+// E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,13,02
+pub fn format_line(cpu: &Cpu, mmu: &Mmu) -> String {
+    let r = cpu.get_registers();
+    let memory = (0 .. 4).map(|offset| format!("{:02X}", mmu.read_byte(r.pc.wrapping_add(offset))));
+    return format!("A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X} PCMEM:{}",
+                   r.a, r.f, r.b, r.c, r.d, r.e, r.h, r.l, r.sp, r.pc, memory.collect::<Vec<String>>().join(","));
+}
 
+// Called by the cpu before it fetches an instruction
+pub fn trace(cpu: &Cpu, mmu: &mut Mmu) -> Result<(), EmulatorError> {
+    let pc = cpu.get_registers().pc;
+    let matches = match mmu.trace.as_ref().map(|tracer| tracer.filter) {
+        Some(Some(filter)) => filter.matches(mmu.get_bank(pc), pc),
+        Some(None) => true,
+        None => false,
+    };
+    if !matches {
+        return Ok(());
     }
+    let line = format_line(cpu, mmu);
+    return match &mut mmu.trace {
+        Some(tracer) => tracer.sink.write_line(&line),
+        None => Ok(()),
+    };
 }
@@ -1,28 +1,100 @@
-mod cpu;
-mod mmu;
-mod console;
-mod cartridge;
-mod ppu;
-mod io;
-mod operations;
-mod dma;
-mod timer;
-mod logger;
-mod joypad;
-
-const WIDTH: usize = 640;
-const HEIGHT: usize = 360;
-
-use crate::console::{Console};
+use std::env;
+use std::fs;
+use std::net::TcpListener;
+use std::process;
 
+use rust_webpack_template::{Console, EmulatorError, FileTrace, GdbStub, TcpLink, DEFAULT_SYNC_WINDOW};
+
+const DEFAULT_CART_PATH: &str = "./roms/cpu_instrs.gb";
+const DEFAULT_FRAMES: u32 = 600;
+const PROFILE_HOTSPOTS: usize = 100;
+
+fn exit_on_error<T>(result: Result<T, EmulatorError>) -> T {
+    return match result {
+        Ok(value) => value,
+        Err(error) => {
+            eprintln!("{}", error);
+            process::exit(1);
+        }
+    };
+}
+
+// Usage: rustyboy [--listen ADDRESS | --connect ADDRESS] [--sync-window CYCLES] [--gdb ADDRESS] [--trace FILE]
+//                 [--profile FILE] [--coverage FILE] [--boot-rom FILE] [ROM] [FRAMES]
 fn main() {
-    let mut console: Console = Console::new();
-    let cart_path = "./roms/promo_demo.gbc";
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let mut take_option = |name: &str| -> Option<String> {
+        let index = args.iter().position(|arg| arg == name)?;
+        let value = args.get(index + 1).cloned();
+        args.drain(index .. (index + 2).min(args.len()));
+        return value;
+    };
+    let listen = take_option("--listen");
+    let connect = take_option("--connect");
+    let sync_window = take_option("--sync-window").and_then(|s| s.parse().ok()).unwrap_or(DEFAULT_SYNC_WINDOW);
+    let gdb = take_option("--gdb");
+    let trace = take_option("--trace");
+    let profile = take_option("--profile");
+    let coverage = take_option("--coverage");
+    let boot_rom = take_option("--boot-rom");
+
+    let cart_path = args.first().map(|s| s.as_str()).unwrap_or(DEFAULT_CART_PATH);
+    let frames = args.get(1).and_then(|s| s.parse().ok()).unwrap_or(DEFAULT_FRAMES);
+
+    let rom = fs::read(cart_path).expect("unable to read cartridge");
 
-    //console.load(cart_path);
+    let mut console: Console = Console::new();
+    exit_on_error(console.load(&rom));
+    if let Some(path) = boot_rom {
+        exit_on_error(console.load_boot_rom(&fs::read(path).expect("unable to read the boot rom")));
+    }
     console.reset();
-    console.execute_ticks(45165847);
 
-    console.execute_ticks(1);
-    print!("finished")
+    // Logs every instruction in the gameboy-doctor format
+    if let Some(path) = trace {
+        console.start_trace(Box::new(exit_on_error(FileTrace::create(path))), None);
+    }
+
+    // Writes the hotspot report and the code/data coverage of the rom when done
+    if profile.is_some() || coverage.is_some() {
+        console.start_profiling();
+    }
+
+    // Plays link games against another process over the link cable
+    let mut link = match (listen, connect) {
+        (Some(address), _) => {
+            let listener = TcpListener::bind(&address).expect("unable to listen for the link cable");
+            println!("waiting for a peer on {}", address);
+            Some(exit_on_error(TcpLink::accept(&listener, sync_window)))
+        },
+        (None, Some(address)) => Some(exit_on_error(TcpLink::connect(address.as_str(), sync_window))),
+        (None, None) => None,
+    };
+    if let Some(link) = &link {
+        link.attach(&mut console);
+    }
+
+    // Waits for gdb and hands the console over to it until it detaches
+    if let Some(address) = gdb {
+        let listener = TcpListener::bind(&address).expect("unable to listen for gdb");
+        println!("waiting for gdb on {}", address);
+        let mut stub = exit_on_error(GdbStub::accept(&listener));
+        exit_on_error(stub.serve(&mut console));
+    }
+
+    for _i in 0 .. frames {
+        exit_on_error(match &mut link {
+            Some(link) => link.run_frame(&mut console),
+            None => console.run_frame(),
+        });
+    }
+
+    exit_on_error(console.stop_trace());
+    if let Some(path) = profile {
+        fs::write(path, console.profile_report(PROFILE_HOTSPOTS)).expect("unable to write the profile");
+    }
+    if let (Some(path), Some(profiler)) = (coverage, console.profiler()) {
+        fs::write(path, profiler.coverage()).expect("unable to write the coverage");
+    }
+    println!("finished {} frames of {}", frames, cart_path);
 }
@@ -1,36 +1,71 @@
-use crate::cartridge::{Cartridge, CartridgeType, HEADER_INDEX_FOR_CARTRIDGE_TYPE};
+use crate::cartridge::{Cartridge, CartridgeType};
+use crate::header::CartridgeHeader;
 use crate::ppu::Ppu;
 use crate::psg::Psg;
+use crate::serial::Serial;
 use crate::dma::{Dma, execute_dma_tick, execute_odma};
 use crate::timer::Timer;
 use std::cell::RefCell;
 use std::rc::Rc;
 use crate::joypad::Joypad;
 use crate::console::GameboyType;
-use wasm_bindgen::prelude::*;
+use crate::error::EmulatorError;
+use crate::debugger::{self, Access, PauseReason, Watchpoint};
+use crate::logger::Tracer;
+use crate::profiler::Profiler;
+use crate::disassembler;
+use crate::state::{StateWriter, SaveState, SECTION_MMU, SECTION_PPU, SECTION_PSG, SECTION_TIMER,
+                   SECTION_DMA, SECTION_JOYPAD, SECTION_CARTRIDGE, SECTION_SERIAL};
 use std::path::Path;
 use std::fs;
 
-#[wasm_bindgen]
 pub struct Mmu {
     hram: [u8; 0x7F],
-    sram: [u8; 0x30], // sound
     wram: [u8; 0x8000],
     wram_bank: usize,
     switch_speed: bool,
     pub speed: Speed,
     pub interrupt_enable: u8,
     pub interrupt_flags: u8,
-    #[wasm_bindgen(skip)]
     pub ppu: Ppu,
     cartridge: Cartridge,
     pub dma: Dma,
     pub timer: Timer,
+    pub psg: Psg,
+    pub serial: Serial,
     pub joypad: Joypad,
     pub model: GameboyType,
+    // Supplied by the host and not part of the state like the rom, empty when the console starts
+    // at 0x0100 without one. It is mapped over the cartridge from reset until FF50 is written.
+    boot_rom: Vec<u8>,
+    boot_rom_mapped: bool,
+    // FF4C - KEY0, written by the CGB boot rom, bit 2 selects the DMG compatibility mode
+    key0: u8,
+    // Faults raised during a memory access, collected by the console after each instruction
+    error: Option<EmulatorError>,
+
+    // When set every cpu access takes one M-cycle and the rest of the console is clocked up to
+    // it, otherwise the console is clocked once after each instruction. Not part of the state.
+    pub cycle_accurate: bool,
+    // Cpu speed clock cycles clocked by accesses of the current instruction, and the normal
+    // speed clock cycles that took
+    instruction_ticks: u32,
+    instruction_elapsed: u32,
+
+    // Set by the debugger while it runs the console, the first access that hits one is kept
+    // until the debugger takes it
+    pub watchpoints: Vec<Watchpoint>,
+    pub watch_hit: Option<PauseReason>,
+
+    // Set while the console is traced, the cpu logs each instruction before fetching it
+    pub trace: Option<Tracer>,
+    // Set while the console is profiled, see profile_instruction
+    pub profiler: Option<Profiler>,
 }
 
-#[wasm_bindgen]
+pub const DMG_BOOT_ROM_SIZE: usize = 0x100;
+pub const CGB_BOOT_ROM_SIZE: usize = 0x900;
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Speed {
     FAST, SLOW
@@ -41,7 +76,6 @@ impl Mmu {
 
     pub fn new() -> Self {
         return Mmu {
-            sram: [0; 0x30],
             wram: [0; 0x8000],
             wram_bank: 1,
             hram: [0; 0x7F],
@@ -54,38 +88,225 @@ impl Mmu {
             ppu: Ppu::new(),
             dma: Dma::new(),
             timer: Timer::new(),
+            psg: Psg::new(),
+            serial: Serial::new(),
             joypad: Joypad::new(),
-            model: GameboyType::CLASSIC
+            model: GameboyType::CLASSIC,
+            boot_rom: Vec::new(),
+            boot_rom_mapped: false,
+            key0: 0,
+            error: None,
+            cycle_accurate: true,
+            instruction_ticks: 0,
+            instruction_elapsed: 0,
+            watchpoints: Vec::new(),
+            watch_hit: None,
+            trace: None,
+            profiler: None,
         };
     }
 
-    pub fn load_cartridge_from_js_value(&mut self, result: &JsValue) {
-        let bytes: Vec<u8> = result.into_serde().unwrap();
-        self.load_cartridge_from_bytes(bytes);
+    pub fn load_from_file_address(&mut self, file_path: &str) -> Result<(), EmulatorError> {
+        let path = Path::new(file_path);
+        let bytes : Vec<u8> = fs::read(path)
+            .map_err(|e| EmulatorError::Io(format!("unable to read {}: {}", file_path, e)))?;
+        return self.load_cartridge_from_bytes(bytes);
     }
 
-    pub fn load_from_file_address(&mut self, file_path: &str) {
-        let path = Path::new(file_path);
-        let bytes : Vec<u8> = fs::read(path).expect("yabe");
-        self.load_cartridge_from_bytes(bytes);
-    }
-
-    pub fn load_cartridge_from_bytes(&mut self, bytes: Vec<u8>) {
-        let cartridge_type = bytes[HEADER_INDEX_FOR_CARTRIDGE_TYPE];
-        match cartridge_type {
-            0x00 ..= 0x00 => { self.cartridge.cartridge_type = CartridgeType::MBC0 },
-            0x01 ..= 0x03 => { self.cartridge.cartridge_type = CartridgeType::MBC1 },
-            //0x05..=0x06 => { cartridge.cartridge_type = Some(CartridgeType::MBC2) },
-            0x0F ..= 0x13 => { self.cartridge.cartridge_type = CartridgeType::MBC3 },
-            0x19 ..= 0x1E => { self.cartridge.cartridge_type = CartridgeType::MBC5 },
-            _ => panic!("cartridge type not implemented")
+    pub fn load_cartridge_from_bytes(&mut self, bytes: Vec<u8>) -> Result<(), EmulatorError> {
+        let header = CartridgeHeader::parse(&bytes)?;
+        if let Some(expected) = header.rom_size {
+            if bytes.len() < expected {
+                return Err(EmulatorError::TruncatedRom { expected, actual: bytes.len() });
+            }
         }
-        self.cartridge.set_rom(bytes);
+
+        let cartridge_type = header.cartridge_type;
+        self.cartridge.cartridge_type = match cartridge_type {
+            0x00 ..= 0x00 | (0x08 ..= 0x09) => CartridgeType::MBC0,
+            0x01 ..= 0x03 => CartridgeType::MBC1,
+            0x05 ..= 0x06 => CartridgeType::MBC2,
+            0x0F ..= 0x13 => CartridgeType::MBC3,
+            0x19 ..= 0x1E => CartridgeType::MBC5,
+            _ => return Err(EmulatorError::UnsupportedMapper(cartridge_type)),
+        };
+        self.cartridge.set_rom(bytes, header);
         self.model = self.cartridge.get_gameboy_type().clone();
+        return Ok(());
+    }
+
+    // https://gbdev.io/pandocs/Power_Up_Sequence.html
+    // A DMG boot rom is 0x100 bytes, a CGB boot rom 0x900 bytes of which 0x100 - 0x1FF are not
+    // mapped so the cartridge header shows through
+    pub fn set_boot_rom(&mut self, data: &[u8]) -> Result<(), EmulatorError> {
+        if data.len() != DMG_BOOT_ROM_SIZE && data.len() != CGB_BOOT_ROM_SIZE {
+            return Err(EmulatorError::InvalidBootRom { size: data.len() });
+        }
+        self.boot_rom = data.to_vec();
+        return Ok(());
+    }
+
+    pub fn clear_boot_rom(&mut self) {
+        self.boot_rom = Vec::new();
+        self.boot_rom_mapped = false;
+    }
+
+    pub fn has_boot_rom(&self) -> bool {
+        return !self.boot_rom.is_empty();
+    }
+
+    pub fn is_boot_rom_mapped(&self) -> bool {
+        return self.boot_rom_mapped;
+    }
+
+    fn is_boot_rom_address(&self, address: u16) -> bool {
+        return self.boot_rom_mapped && match address {
+            0x0000 ..= 0x00FF => true,
+            0x0200 ..= 0x08FF => self.boot_rom.len() == CGB_BOOT_ROM_SIZE,
+            _ => false,
+        };
+    }
+
+    // The console a boot rom belongs to, otherwise the one the cartridge asks for
+    pub fn power_on_model(&self) -> GameboyType {
+        return match self.boot_rom.len() {
+            DMG_BOOT_ROM_SIZE => GameboyType::CLASSIC,
+            CGB_BOOT_ROM_SIZE => GameboyType::COLOR,
+            _ if self.cartridge.get_header().is_some() => self.cartridge.get_gameboy_type(),
+            _ => self.model,
+        };
+    }
+
+    // Writing FF50 maps the cartridge back for good. A CGB stays in the DMG compatibility mode
+    // when the boot rom selected it for a DMG cartridge.
+    fn unmap_boot_rom(&mut self) {
+        self.boot_rom_mapped = false;
+        if self.model == GameboyType::COLOR && self.key0 & 0x04 != 0 {
+            self.model = GameboyType::CLASSIC;
+            self.ppu.enter_compatibility_mode();
+        }
+    }
+
+    // Offset in the rom of a byte read from the cartridge, None for the boot rom
+    fn rom_offset(&self, address: u16) -> Option<usize> {
+        if self.is_boot_rom_address(address) {
+            return None;
+        }
+        return self.cartridge.rom_offset(address);
+    }
+
+    pub fn take_error(&mut self) -> Option<EmulatorError> {
+        return self.error.take();
+    }
+
+    pub fn get_cartridge(&mut self) -> &mut Cartridge {
+        return &mut self.cartridge;
+    }
+
+    pub fn get_header(&self) -> Option<&CartridgeHeader> {
+        return self.cartridge.get_header();
+    }
+
+    // Bank mapped at the address in the switchable areas of rom and wram, 0 elsewhere
+    pub fn get_bank(&self, address: u16) -> usize {
+        return match address {
+            0x0000 ..= 0x7FFF => self.cartridge.get_rom_bank(address),
+            0xD000 ..= 0xDFFF => self.wram_bank,
+            _ => 0,
+        };
+    }
+
+    pub fn get_rom_size(&self) -> usize {
+        return self.cartridge.rom_size();
+    }
+
+    pub fn get_rom_checksum(&self) -> u32 {
+        return self.cartridge.get_checksum();
+    }
+
+    // Writes the memory section followed by a section for each component on the bus
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.begin_section(SECTION_MMU);
+        writer.write_bytes(&self.hram);
+        writer.write_bytes(&self.wram);
+        writer.write_u8(self.wram_bank as u8);
+        writer.write_bool(self.switch_speed);
+        writer.write_bool(self.speed == Speed::FAST);
+        writer.write_u8(self.interrupt_enable);
+        writer.write_u8(self.interrupt_flags);
+        writer.write_model(self.model);
+        writer.write_bool(self.boot_rom_mapped);
+        writer.write_u8(self.key0);
+        writer.end_section();
+
+        writer.begin_section(SECTION_PPU);
+        self.ppu.save_state(writer);
+        writer.end_section();
+
+        writer.begin_section(SECTION_PSG);
+        self.psg.save_state(writer);
+        writer.end_section();
+
+        writer.begin_section(SECTION_TIMER);
+        self.timer.save_state(writer);
+        writer.end_section();
+
+        writer.begin_section(SECTION_DMA);
+        self.dma.save_state(writer);
+        writer.end_section();
+
+        writer.begin_section(SECTION_JOYPAD);
+        self.joypad.save_state(writer);
+        writer.end_section();
+
+        writer.begin_section(SECTION_CARTRIDGE);
+        self.cartridge.save_state(writer);
+        writer.end_section();
+
+        writer.begin_section(SECTION_SERIAL);
+        self.serial.save_state(writer);
+        writer.end_section();
+    }
+
+    pub fn load_state(&mut self, state: &SaveState) -> Result<(), EmulatorError> {
+        let mut reader = state.section(SECTION_MMU)?;
+        reader.read_bytes_into(&mut self.hram)?;
+        reader.read_bytes_into(&mut self.wram)?;
+        self.wram_bank = match reader.read_u8()? {
+            n @ 1 ..= 7 => n as usize,
+            _ => return Err(reader.invalid("wram bank")),
+        };
+        self.switch_speed = reader.read_bool()?;
+        self.speed = if reader.read_bool()? { Speed::FAST } else { Speed::SLOW };
+        self.interrupt_enable = reader.read_u8()?;
+        self.interrupt_flags = reader.read_u8()? & 0x1F;
+        self.model = reader.read_model()?;
+        if reader.minor() >= 5 {
+            self.boot_rom_mapped = reader.read_bool()? && !self.boot_rom.is_empty();
+            self.key0 = reader.read_u8()?;
+        } else {
+            self.boot_rom_mapped = false;
+            self.key0 = 0;
+        }
+        self.error = None;
+
+        self.ppu.load_state(&mut state.section(SECTION_PPU)?)?;
+        self.psg.load_state(&mut state.section(SECTION_PSG)?)?;
+        self.timer.load_state(&mut state.section(SECTION_TIMER)?)?;
+        self.dma.load_state(&mut state.section(SECTION_DMA)?)?;
+        self.joypad.load_state(&mut state.section(SECTION_JOYPAD)?)?;
+        self.cartridge.load_state(&mut state.section(SECTION_CARTRIDGE)?)?;
+        if state.minor() >= 3 {
+            self.serial.load_state(&mut state.section(SECTION_SERIAL)?)?;
+        } else {
+            self.serial.reset(self.model);
+        }
+        return Ok(());
     }
 
     pub fn read_byte(&self, address: u16) -> u8 {
         match address {
+            0x0000 ..= 0x08FF if self.is_boot_rom_address(address) => { self.boot_rom[address as usize] },
             0x0000 ..= 0x7FFF => { self.cartridge.read_byte(address) },
             0x8000 ..= 0x9FFF => { self.ppu.read_byte(address) },
             0xA000 ..= 0xBFFF => { self.cartridge.read_byte(address) },
@@ -93,10 +314,11 @@ impl Mmu {
             0xD000 ..= 0xDFFF | (0xF000 ..= 0xFDFF) => { self.wram[(self.wram_bank * 0x1000) | address as usize & 0x0FFF] },
             0xFE00 ..= 0xFE9F => { self.ppu.read_byte(address) },
             0xFF00 ..= 0xFF00 => { self.joypad.read_byte(address) },
-            0xFF01 ..= 0xFF02 => { 0xFF }, // serial transfer
+            0xFF01 ..= 0xFF02 => { self.serial.read_byte(address) },
             0xFF04 ..= 0xFF07 => { self.timer.read_byte(address) },
-            0xFF0F => { self.interrupt_flags },
-            0xFF10 ..= 0xFF3F => { self.sram[address as usize - 0xFF10] },
+            // The upper three bits are unused and read as 1
+            0xFF0F => { self.interrupt_flags | 0xE0 },
+            0xFF10 ..= 0xFF3F => { self.psg.read_byte(address) },
             0xFF4D => (if self.speed == Speed::FAST { 0x80 } else { 0 }) | (if self.switch_speed { 1 } else { 0 }),
             0xFF40 ..= 0xFF4F => { self.ppu.read_byte(address) },
             0xFF51 ..= 0xFF55 => { self.dma.read_byte(address) },
@@ -117,14 +339,25 @@ impl Mmu {
             0xD000 ..= 0xDFFF | (0xF000 ..= 0xFDFF) => { self.wram[(self.wram_bank * 0x1000) | (address as usize & 0x0FFF)] = value },
             0xFE00 ..= 0xFE9F => { self.ppu.write_byte(address, value) },
             0xFF00 => { self.joypad.write_byte(address, value) },
-            0xFF01 ..= 0xFF02 => { }, // serial transfer
-            0xFF04 ..= 0xFF07 => { self.timer.write_byte(address, value) },
-            0xFF0F => { self.interrupt_flags = value },
-            0xFF10 ..= 0xFF3F => { self.sram[address as usize - 0xFF10] = value },
+            0xFF01 ..= 0xFF02 => { self.serial.write_byte(address, value) },
+            0xFF04 => {
+                self.psg.reset_div(self.timer.read_byte(address), self.div_apu_mask());
+                self.timer.write_byte(address, value)
+            },
+            0xFF05 ..= 0xFF07 => { self.timer.write_byte(address, value) },
+            0xFF0F => { self.interrupt_flags = value & 0x1F },
+            0xFF10 ..= 0xFF3F => { self.psg.write_byte(address, value) },
             0xFF46 => { execute_odma(self, value) },
             0xFF4D => { if value & 0x1 == 0x1 { self.switch_speed = true; } },
+            // Locked once the boot rom is unmapped
+            0xFF4C => { if self.boot_rom_mapped { self.key0 = value; } },
             0xFF40 ..= 0xFF4F => { self.ppu.write_byte(address, value) },
-            0xFF51 ..= 0xFF55 => { self.dma.write_byte(address, value) },
+            0xFF51 ..= 0xFF55 => {
+                if let Err(error) = self.dma.write_byte(address, value) {
+                    self.error = Some(error);
+                }
+            },
+            0xFF50 => { if self.boot_rom_mapped && value != 0 { self.unmap_boot_rom(); } },
             0xFF68 ..= 0xFF6B => { self.ppu.write_byte(address, value) },
             0xFF70 ..= 0xFF70 => { self.wram_bank = match value & 0x7 { 0 => 1, n => n as usize }; },
             0xFF80 ..= 0xFFFE => { self.hram[address as usize & 0x007F] = value; },
@@ -133,6 +366,88 @@ impl Mmu {
         };
     }
 
+    // Memory accesses made by the cpu, see cycle_accurate
+    pub fn cpu_read_byte(&mut self, address: u16) -> u8 {
+        self.tick_access();
+        let value = self.read_byte(address);
+        if !self.watchpoints.is_empty() {
+            debugger::check_access(&self.watchpoints, &mut self.watch_hit, address, Access::READ, value);
+        }
+        if self.profiler.is_some() {
+            if let (Some(offset), Some(profiler)) = (self.rom_offset(address), &mut self.profiler) {
+                profiler.record_read(offset);
+            }
+        }
+        return value;
+    }
+
+    // Called by the cpu after each instruction it ran, cycles are in cpu speed clock cycles.
+    // The rom bytes of the instruction are code, the other rom bytes it read are data.
+    pub fn profile_instruction(&mut self, pc: u16, opcode: u8, cycles: u32) {
+        let bank = self.get_bank(pc);
+        let mut code = [0; 3];
+        let mut length = 0;
+        for offset in 0 .. disassembler::instruction_length(opcode) {
+            if let Some(offset) = self.rom_offset(pc.wrapping_add(offset)) {
+                code[length] = offset;
+                length += 1;
+            }
+        }
+        if let Some(profiler) = &mut self.profiler {
+            profiler.record_instruction(bank, pc, cycles, &code[.. length]);
+        }
+    }
+
+    pub fn cpu_write_byte(&mut self, address: u16, value: u8) {
+        self.tick_access();
+        if !self.watchpoints.is_empty() {
+            debugger::check_access(&self.watchpoints, &mut self.watch_hit, address, Access::WRITE, value);
+        }
+        self.write_byte(address, value);
+    }
+
+    pub fn cpu_read_word(&mut self, address: u16) -> u16 {
+        let low = self.cpu_read_byte(address) as u16;
+        let high = self.cpu_read_byte(address.wrapping_add(1)) as u16;
+        return high << 8 | low;
+    }
+
+    pub fn cpu_write_word(&mut self, address: u16, value: u16) {
+        self.cpu_write_byte(address, (value & 0xFF) as u8);
+        self.cpu_write_byte(address.wrapping_add(1), (value >> 8) as u8);
+    }
+
+    // A cycle in which the cpu does not access memory, for cycles that precede an access
+    pub fn cpu_internal_cycle(&mut self) {
+        self.tick_access();
+    }
+
+    fn tick_access(&mut self) {
+        if self.cycle_accurate {
+            self.instruction_ticks += 4;
+            self.instruction_elapsed += self.execute_ticks(4);
+        }
+    }
+
+    // Clocks the part of an instruction that was not clocked by its memory accesses. Ticks are
+    // the length of the instruction in cpu speed clock cycles, returns it in normal speed cycles.
+    pub fn finish_instruction(&mut self, ticks: u32) -> u32 {
+        let remaining = ticks.saturating_sub(self.instruction_ticks);
+        let elapsed = self.instruction_elapsed + self.execute_ticks(remaining);
+        self.instruction_ticks = 0;
+        self.instruction_elapsed = 0;
+        return elapsed;
+    }
+
+    // Like finish_instruction, for a cpu that STOP left stopped
+    pub fn finish_stopped_instruction(&mut self, ticks: u32) -> u32 {
+        let remaining = ticks.saturating_sub(self.instruction_ticks);
+        let elapsed = self.instruction_elapsed + self.execute_stopped_ticks(remaining);
+        self.instruction_ticks = 0;
+        self.instruction_elapsed = 0;
+        return elapsed;
+    }
+
     pub fn read_word(&self, address: u16) -> u16 {
         let low = (self.read_byte(address) as u16);
         let high  = (self.read_byte(address + 1) as u16);
@@ -148,14 +463,26 @@ impl Mmu {
         self.write_byte(address + 1, high);
     }
 
-    pub fn toggle_speed(&mut self) {
-        if self.switch_speed {
+    // Returns true when a switch was armed
+    pub fn toggle_speed(&mut self) -> bool {
+        let switched = self.switch_speed;
+        if switched {
             self.speed = if Speed::FAST == self.speed { Speed::SLOW } else { Speed::FAST };
         }
         self.switch_speed = false;
+        return switched;
     }
 
-    pub fn execute_ticks(&mut self, ticks: u32) {
+    // The frame sequencer of the sound controller is clocked by bit 4 of DIV, bit 5 in double speed
+    fn div_apu_mask(&self) -> u8 {
+        return match self.speed {
+            Speed::SLOW => 0x10,
+            Speed::FAST => 0x20,
+        };
+    }
+
+    // Returns the elapsed time in normal speed clock cycles
+    pub fn execute_ticks(&mut self, ticks: u32) -> u32 {
         let cpu_divider = match self.speed {
             Speed::SLOW => 1,
             Speed::FAST => 2,
@@ -165,9 +492,15 @@ impl Mmu {
         let gpu_ticks = ticks / cpu_divider + dma_ticks;
         let timer_ticks = ticks + dma_ticks * cpu_divider;
 
+        let div = self.timer.read_byte(0xFF04);
         self.timer.execute_ticks(timer_ticks);
         self.ppu.execute_ticks(gpu_ticks);
 
+        self.psg.execute_ticks(gpu_ticks);
+        self.psg.execute_div_ticks(div, self.timer.read_byte(0xFF04), self.div_apu_mask());
+        self.cartridge.execute_ticks(gpu_ticks);
+        self.serial.execute_ticks(timer_ticks);
+
         // Gather interrupts
 
         self.interrupt_flags |= self.timer.interrupt_flags;
@@ -175,31 +508,36 @@ impl Mmu {
 
         self.interrupt_flags |= self.ppu.interrupt_flags;
         self.ppu.interrupt_flags = 0;
+
+        self.interrupt_flags |= self.serial.interrupt_flags;
+        self.serial.interrupt_flags = 0;
+
+        self.interrupt_flags |= self.joypad.interrupt;
+        self.joypad.interrupt = 0;
+
+        return gpu_ticks;
+    }
+
+    // STOP halts the system clock, the timer, ppu, sound and serial port stand still. Only the
+    // rtc of the cartridge runs on, from its own crystal, and the joypad can wake the cpu.
+    fn execute_stopped_ticks(&mut self, ticks: u32) -> u32 {
+        let gpu_ticks = match self.speed {
+            Speed::SLOW => ticks,
+            Speed::FAST => ticks / 2,
+        };
+        self.ppu.execute_stopped_ticks(gpu_ticks);
+        self.psg.execute_stopped_ticks(gpu_ticks);
+        self.cartridge.execute_ticks(gpu_ticks);
+
+        self.interrupt_flags |= self.joypad.interrupt;
+        self.joypad.interrupt = 0;
+        return gpu_ticks;
     }
 
     pub fn reset(&mut self, model: GameboyType) {
         self.write_byte(0xFF05, 0);
         self.write_byte(0xFF06, 0);
         self.write_byte(0xFF07, 0);
-        self.write_byte(0xFF10, 0x80);
-        self.write_byte(0xFF11, 0xBF);
-        self.write_byte(0xFF12, 0xF3);
-        self.write_byte(0xFF14, 0xBF);
-        self.write_byte(0xFF16, 0x3F);
-        self.write_byte(0xFF16, 0x3F);
-        self.write_byte(0xFF17, 0);
-        self.write_byte(0xFF19, 0xBF);
-        self.write_byte(0xFF1A, 0x7F);
-        self.write_byte(0xFF1B, 0xFF);
-        self.write_byte(0xFF1C, 0x9F);
-        self.write_byte(0xFF1E, 0xFF);
-        self.write_byte(0xFF20, 0xFF);
-        self.write_byte(0xFF21, 0);
-        self.write_byte(0xFF22, 0);
-        self.write_byte(0xFF23, 0xBF);
-        self.write_byte(0xFF24, 0x77);
-        self.write_byte(0xFF25, 0xF3);
-        self.write_byte(0xFF26, 0xF1);
         self.write_byte(0xFF40, 0x91);
         self.write_byte(0xFF42, 0);
         self.write_byte(0xFF43, 0);
@@ -209,6 +547,14 @@ impl Mmu {
         self.write_byte(0xFF49, 0xFF);
         self.write_byte(0xFF4A, 0);
         self.write_byte(0xFF4B, 0);
+
+        // The boot rom starts with the lcd off and sets up the rest itself
+        self.key0 = 0;
+        self.boot_rom_mapped = !self.boot_rom.is_empty();
+        if self.boot_rom_mapped {
+            self.write_byte(0xFF40, 0);
+            self.write_byte(0xFF47, 0);
+        }
     }
 
 }
@@ -1,20 +1,21 @@
 use crate::cpu::Cpu;
 use crate::mmu::Mmu;
+use crate::error::EmulatorError;
 
 #[allow(unreachable_patterns)]
-pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
+pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> Result<(), EmulatorError> {
     match opcode {
         0x00 => {
             cpu.pc += 1;
             cpu.cycles += 1;
         }
         0x01 => {
-            cpu.set_bc(mmu.read_word(cpu.pc + 1));
+            cpu.set_bc(mmu.cpu_read_word(cpu.pc + 1));
             cpu.pc += 3;
             cpu.cycles += 3;
         }
         0x02 => {
-            mmu.write_byte(cpu.get_bc(), cpu.a);
+            mmu.cpu_write_byte(cpu.get_bc(), cpu.a);
             cpu.pc += 1;
             cpu.cycles += 2;
         }
@@ -34,7 +35,7 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
             cpu.cycles += 1;
         }
         0x06 => {
-            cpu.b = mmu.read_byte(cpu.pc + 1);
+            cpu.b = mmu.cpu_read_byte(cpu.pc + 1);
             cpu.pc += 2;
             cpu.cycles += 2;
         }
@@ -46,8 +47,8 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
             cpu.cycles += 1;
         }
         0x08 => {
-            let address = mmu.read_word(cpu.pc + 1);
-            mmu.write_word(address, cpu.sp);
+            let address = mmu.cpu_read_word(cpu.pc + 1);
+            mmu.cpu_write_word(address, cpu.sp);
 
             cpu.pc += 3;
             cpu.cycles += 5;
@@ -60,7 +61,7 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
             cpu.cycles += 2;
         }
         0x0A => {
-            cpu.a = mmu.read_byte(cpu.get_bc());
+            cpu.a = mmu.cpu_read_byte(cpu.get_bc());
 
             cpu.pc += 1;
             cpu.cycles += 2;
@@ -85,7 +86,7 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
             cpu.cycles += 1;
         }
         0x0E => {
-            cpu.c = mmu.read_byte(cpu.pc + 1);
+            cpu.c = mmu.cpu_read_byte(cpu.pc + 1);
 
             cpu.pc += 2;
             cpu.cycles += 2;
@@ -98,19 +99,24 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
             cpu.cycles += 1;
         }
         0x10 => {
-            mmu.toggle_speed();
+            // https://gbdev.io/pandocs/Reducing_Power_Consumption.html#using-the-stop-instruction
+            // Switches speed when armed through KEY1, otherwise stops until a joypad line goes low
+            if !mmu.toggle_speed() {
+                cpu.stopped = true;
+            }
+            mmu.write_byte(0xFF04, 0);
 
             cpu.pc += 2;
             cpu.cycles += 1;
         }
         0x11 => {
-            cpu.set_de(mmu.read_word(cpu.pc + 1));
+            cpu.set_de(mmu.cpu_read_word(cpu.pc + 1));
 
             cpu.pc += 3;
             cpu.cycles += 3;
         }
         0x12 => {
-            mmu.write_byte(cpu.get_de(), cpu.a);
+            mmu.cpu_write_byte(cpu.get_de(), cpu.a);
 
             cpu.pc += 1;
             cpu.cycles += 2;
@@ -134,7 +140,7 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
             cpu.cycles += 1;
         }
         0x16 => {
-            cpu.d = mmu.read_byte(cpu.pc + 1);
+            cpu.d = mmu.cpu_read_byte(cpu.pc + 1);
 
             cpu.pc += 2;
             cpu.cycles += 2;
@@ -147,7 +153,7 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
             cpu.cycles += 1;
         }
         0x18 => {
-            let offset = mmu.read_byte(cpu.pc + 1) as i8;
+            let offset = mmu.cpu_read_byte(cpu.pc + 1) as i8;
             cpu.pc = ((cpu.pc as u32 as i32) + (offset as i32)) as u16;
 
             cpu.pc += 2;
@@ -161,7 +167,7 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
             cpu.cycles += 2;
         }
         0x1A => {
-            cpu.a = mmu.read_byte(cpu.get_de());
+            cpu.a = mmu.cpu_read_byte(cpu.get_de());
 
             cpu.pc += 1;
             cpu.cycles += 2;
@@ -185,7 +191,7 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
             cpu.cycles += 1;
         }
         0x1E => {
-            cpu.e = mmu.read_byte(cpu.pc + 1);
+            cpu.e = mmu.cpu_read_byte(cpu.pc + 1);
 
             cpu.pc += 2;
             cpu.cycles += 2;
@@ -199,7 +205,7 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
         }
         0x20 => {
             if !cpu.get_f_zero() {
-                let offset = mmu.read_byte(cpu.pc + 1) as i8;
+                let offset = mmu.cpu_read_byte(cpu.pc + 1) as i8;
                 cpu.pc = ((cpu.pc as u32 as i32) + (offset as i32)) as u16;
 
                 cpu.pc += 2;
@@ -210,14 +216,14 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
             }
         }
         0x21 => {
-            let value = mmu.read_word(cpu.pc + 1);
+            let value = mmu.cpu_read_word(cpu.pc + 1);
             cpu.set_hl(value);
 
             cpu.pc += 3;
-            cpu.cycles += 2;
+            cpu.cycles += 3;
         }
         0x22 => {
-            mmu.write_byte(cpu.get_hl(), cpu.a);
+            mmu.cpu_write_byte(cpu.get_hl(), cpu.a);
             cpu.set_hl(cpu.get_hl().wrapping_add(1));
 
             cpu.pc += 1;
@@ -243,7 +249,7 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
             cpu.cycles += 1;
         }
         0x26 => {
-            cpu.h = mmu.read_byte(cpu.pc + 1);
+            cpu.h = mmu.cpu_read_byte(cpu.pc + 1);
 
             cpu.pc += 2;
             cpu.cycles += 2;
@@ -271,7 +277,7 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
         }
         0x28 => {
             if cpu.get_f_zero() {
-                let offset = mmu.read_byte(cpu.pc + 1) as i8;
+                let offset = mmu.cpu_read_byte(cpu.pc + 1) as i8;
                 cpu.pc = ((cpu.pc as u32 as i32) + (offset as i32)) as u16;
 
                 cpu.pc += 2;
@@ -289,7 +295,7 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
             cpu.cycles += 2;
         }
         0x2A => {
-            cpu.a = mmu.read_byte(cpu.get_hl());
+            cpu.a = mmu.cpu_read_byte(cpu.get_hl());
             cpu.set_hl(cpu.get_hl().wrapping_add(1));
 
             cpu.pc += 1;
@@ -314,7 +320,7 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
             cpu.cycles += 1;
         }
         0x2E => {
-            cpu.l = mmu.read_byte(cpu.pc + 1);
+            cpu.l = mmu.cpu_read_byte(cpu.pc + 1);
 
             cpu.pc += 2;
             cpu.cycles += 2;
@@ -329,7 +335,7 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
         }
         0x30 => {
             if !cpu.get_f_carry() {
-                let offset = mmu.read_byte(cpu.pc + 1) as i8;
+                let offset = mmu.cpu_read_byte(cpu.pc + 1) as i8;
                 cpu.pc = ((cpu.pc as u32 as i32) + (offset as i32)) as u16;
 
                 cpu.pc += 2;
@@ -340,13 +346,13 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
             }
         }
         0x31 => {
-            cpu.sp = mmu.read_word(cpu.pc + 1);
+            cpu.sp = mmu.cpu_read_word(cpu.pc + 1);
 
             cpu.pc += 3;
             cpu.cycles += 3;
         }
         0x32 => {
-            mmu.write_byte(cpu.get_hl(), cpu.a);
+            mmu.cpu_write_byte(cpu.get_hl(), cpu.a);
             cpu.set_hl(cpu.get_hl() - 1);
 
             cpu.pc += 1;
@@ -360,25 +366,25 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
         }
         0x34 => {
             let addr = cpu.get_hl();
-            let mut value = mmu.read_byte(addr);
+            let mut value = mmu.cpu_read_byte(addr);
             value = cpu.apply_inc_u8_with_flags(value);
-            mmu.write_byte(addr, value);
+            mmu.cpu_write_byte(addr, value);
 
             cpu.pc += 1;
             cpu.cycles += 3;
         }
         0x35 => {
             let addr = cpu.get_hl();
-            let mut value = mmu.read_byte(addr);
+            let mut value = mmu.cpu_read_byte(addr);
             value = cpu.apply_dec_u8_with_flags(value);
-            mmu.write_byte(addr, value);
+            mmu.cpu_write_byte(addr, value);
 
             cpu.pc += 1;
             cpu.cycles += 3;
         }
         0x36 => {
-            let value = mmu.read_byte(cpu.pc + 1);
-            mmu.write_byte(cpu.get_hl(), value);
+            let value = mmu.cpu_read_byte(cpu.pc + 1);
+            mmu.cpu_write_byte(cpu.get_hl(), value);
 
             cpu.pc += 2;
             cpu.cycles += 3;
@@ -393,7 +399,7 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
         }
         0x38 => {
             if cpu.get_f_carry() {
-                let offset = mmu.read_byte(cpu.pc + 1) as i8;
+                let offset = mmu.cpu_read_byte(cpu.pc + 1) as i8;
                 cpu.pc = ((cpu.pc as u32 as i32) + (offset as i32)) as u16;
 
                 cpu.pc += 2;
@@ -411,7 +417,7 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
             cpu.cycles += 2;
         }
         0x3A => {
-            cpu.a = mmu.read_byte(cpu.get_hl());
+            cpu.a = mmu.cpu_read_byte(cpu.get_hl());
             cpu.set_hl(cpu.get_hl() - 1);
 
             cpu.pc += 1;
@@ -436,7 +442,7 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
             cpu.cycles += 1;
         }
         0x3E => {
-            cpu.a = mmu.read_byte(cpu.pc + 1);
+            cpu.a = mmu.cpu_read_byte(cpu.pc + 1);
 
             cpu.pc += 2;
             cpu.cycles += 2;
@@ -484,7 +490,7 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
             cpu.cycles += 1;
         }
         0x46 => {
-            cpu.b = mmu.read_byte(cpu.get_hl());
+            cpu.b = mmu.cpu_read_byte(cpu.get_hl());
 
             cpu.pc += 1;
             cpu.cycles += 2;
@@ -530,7 +536,7 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
             cpu.cycles += 1;
         }
         0x4E => {
-            cpu.c = mmu.read_byte(cpu.get_hl());
+            cpu.c = mmu.cpu_read_byte(cpu.get_hl());
 
             cpu.pc += 1;
             cpu.cycles += 2;
@@ -576,7 +582,7 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
             cpu.cycles += 1;
         }
         0x56 => {
-            cpu.d = mmu.read_byte(cpu.get_hl());
+            cpu.d = mmu.cpu_read_byte(cpu.get_hl());
 
             cpu.pc += 1;
             cpu.cycles += 2;
@@ -622,7 +628,7 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
             cpu.cycles += 1;
         }
         0x5E => {
-            cpu.e = mmu.read_byte(cpu.get_hl());
+            cpu.e = mmu.cpu_read_byte(cpu.get_hl());
 
             cpu.pc += 1;
             cpu.cycles += 2;
@@ -668,7 +674,7 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
             cpu.cycles += 1;
         }
         0x66 => {
-            cpu.h = mmu.read_byte(cpu.get_hl());
+            cpu.h = mmu.cpu_read_byte(cpu.get_hl());
 
             cpu.pc += 1;
             cpu.cycles += 2;
@@ -695,7 +701,7 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
             cpu.l = cpu.d;
 
             cpu.pc += 1;
-            cpu.cycles += 2;
+            cpu.cycles += 1;
         }
         0x6B => {
             cpu.l = cpu.e;
@@ -714,7 +720,7 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
             cpu.cycles += 1;
         }
         0x6E => {
-            cpu.l = mmu.read_byte(cpu.get_hl());
+            cpu.l = mmu.cpu_read_byte(cpu.get_hl());
 
             cpu.pc += 1;
             cpu.cycles += 2;
@@ -726,49 +732,58 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
             cpu.cycles += 1;
         }
         0x70 => {
-            mmu.write_byte(cpu.get_hl(), cpu.b);
+            mmu.cpu_write_byte(cpu.get_hl(), cpu.b);
 
             cpu.pc += 1;
             cpu.cycles += 2;
         }
         0x71 => {
-            mmu.write_byte(cpu.get_hl(), cpu.c);
+            mmu.cpu_write_byte(cpu.get_hl(), cpu.c);
 
             cpu.pc += 1;
             cpu.cycles += 2;
         }
         0x72 => {
-            mmu.write_byte(cpu.get_hl(), cpu.d);
+            mmu.cpu_write_byte(cpu.get_hl(), cpu.d);
 
             cpu.pc += 1;
             cpu.cycles += 2;
         }
         0x73 => {
-            mmu.write_byte(cpu.get_hl(), cpu.e);
+            mmu.cpu_write_byte(cpu.get_hl(), cpu.e);
 
             cpu.pc += 1;
             cpu.cycles += 2;
         }
         0x74 => {
-            mmu.write_byte(cpu.get_hl(), cpu.h);
+            mmu.cpu_write_byte(cpu.get_hl(), cpu.h);
 
             cpu.pc += 1;
             cpu.cycles += 2;
         }
         0x75 => {
-            mmu.write_byte(cpu.get_hl(), cpu.l);
+            mmu.cpu_write_byte(cpu.get_hl(), cpu.l);
 
             cpu.pc += 1;
             cpu.cycles += 2;
         }
         0x76 => {
-            cpu.halted = true;
+            // With IME=0 and an interrupt pending the cpu does not halt and fails to increment
+            // pc after fetching the next opcode, so the byte after HALT is read twice. Right after
+            // EI the interrupt is serviced instead and returns to the HALT.
+            let pending = mmu.interrupt_enable & mmu.interrupt_flags & 0x1F != 0;
+            if cpu.interrupt_master_enable || !pending {
+                cpu.halted = true;
+                cpu.pc += 1;
+            } else if cpu.enable_interrupt_counter == 0 {
+                cpu.halt_bug = true;
+                cpu.pc += 1;
+            }
 
-            cpu.pc += 1;
             cpu.cycles += 1;
         }
         0x77 => {
-            mmu.write_byte(cpu.get_hl(), cpu.a);
+            mmu.cpu_write_byte(cpu.get_hl(), cpu.a);
 
             cpu.pc += 1;
             cpu.cycles += 2;
@@ -810,7 +825,7 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
             cpu.cycles += 1;
         }
         0x7E => {
-            cpu.a = mmu.read_byte(cpu.get_hl());
+            cpu.a = mmu.cpu_read_byte(cpu.get_hl());
 
             cpu.pc += 1;
             cpu.cycles += 2;
@@ -856,7 +871,7 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
             cpu.cycles += 1;
         }
         0x86 => {
-            let value = mmu.read_byte(cpu.get_hl());
+            let value = mmu.cpu_read_byte(cpu.get_hl());
             cpu.a = cpu.apply_add_u8_with_flags(cpu.a, value, false);
 
             cpu.pc += 1;
@@ -872,40 +887,40 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
             cpu.a = cpu.apply_add_u8_with_flags(cpu.a, cpu.b, true);
 
             cpu.pc += 1;
-            cpu.cycles += 2;
+            cpu.cycles += 1;
         }
         0x89 => {
             cpu.a = cpu.apply_add_u8_with_flags(cpu.a, cpu.c, true);
 
             cpu.pc += 1;
-            cpu.cycles += 2;
+            cpu.cycles += 1;
         }
         0x8A => {
             cpu.a = cpu.apply_add_u8_with_flags(cpu.a, cpu.d, true);
 
             cpu.pc += 1;
-            cpu.cycles += 2;
+            cpu.cycles += 1;
         }
         0x8B => {
             cpu.a = cpu.apply_add_u8_with_flags(cpu.a, cpu.e, true);
 
             cpu.pc += 1;
-            cpu.cycles += 2;
+            cpu.cycles += 1;
         }
         0x8C => {
             cpu.a = cpu.apply_add_u8_with_flags(cpu.a, cpu.h, true);
 
             cpu.pc += 1;
-            cpu.cycles += 2;
+            cpu.cycles += 1;
         }
         0x8D => {
             cpu.a = cpu.apply_add_u8_with_flags(cpu.a, cpu.l, true);
 
             cpu.pc += 1;
-            cpu.cycles += 2;
+            cpu.cycles += 1;
         }
         0x8E => {
-            let value = mmu.read_byte(cpu.get_hl());
+            let value = mmu.cpu_read_byte(cpu.get_hl());
             cpu.a = cpu.apply_add_u8_with_flags(cpu.a, value, true);
 
             cpu.pc += 1;
@@ -915,7 +930,7 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
             cpu.a = cpu.apply_add_u8_with_flags(cpu.a, cpu.a, true);
 
             cpu.pc += 1;
-            cpu.cycles += 2;
+            cpu.cycles += 1;
         }
         0x90 => {
             cpu.a = cpu.apply_sub_u8_with_flags(cpu.a, cpu.b, false);
@@ -954,7 +969,7 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
             cpu.cycles += 1;
         }
         0x96 => {
-            let value = mmu.read_byte(cpu.get_hl());
+            let value = mmu.cpu_read_byte(cpu.get_hl());
             cpu.a = cpu.apply_sub_u8_with_flags(cpu.a, value, false);
 
             cpu.pc += 1;
@@ -1003,7 +1018,7 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
             cpu.cycles += 1;
         }
         0x9E => {
-            let value = mmu.read_byte(cpu.get_hl());
+            let value = mmu.cpu_read_byte(cpu.get_hl());
             cpu.a = cpu.apply_sub_u8_with_flags(cpu.a, value, true);
 
             cpu.pc += 1;
@@ -1052,7 +1067,7 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
             cpu.cycles += 1;
         }
         0xA6 => {
-            let value = mmu.read_byte(cpu.get_hl());
+            let value = mmu.cpu_read_byte(cpu.get_hl());
             cpu.a = cpu.apply_and_u8_with_flags(cpu.a, value);
 
             cpu.pc += 1;
@@ -1101,7 +1116,7 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
             cpu.cycles += 1;
         }
         0xAE => {
-            let value = mmu.read_byte(cpu.get_hl());
+            let value = mmu.cpu_read_byte(cpu.get_hl());
             cpu.a = cpu.apply_xor_u8_with_flags(cpu.a, value);
 
             cpu.pc += 1;
@@ -1150,7 +1165,7 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
             cpu.cycles += 1;
         }
         0xB6 => {
-            let value = mmu.read_byte(cpu.get_hl());
+            let value = mmu.cpu_read_byte(cpu.get_hl());
             cpu.a = cpu.apply_or_u8_with_flags(cpu.a, value);
 
             cpu.pc += 1;
@@ -1199,7 +1214,7 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
             cpu.cycles += 1;
         }
         0xBE => {
-            let value = mmu.read_byte(cpu.get_hl());
+            let value = mmu.cpu_read_byte(cpu.get_hl());
             cpu.apply_sub_u8_with_flags(cpu.a, value, false);
 
             cpu.pc += 1;
@@ -1213,6 +1228,7 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
         }
         0xC0 => {
             if !cpu.get_f_zero() {
+                mmu.cpu_internal_cycle();
                 cpu.pc = cpu.pop_word(mmu);
                 cpu.cycles += 5;
             } else {
@@ -1229,7 +1245,7 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
         }
         0xC2 => {
             if !cpu.get_f_zero() {
-                cpu.pc = mmu.read_word(cpu.pc + 1);
+                cpu.pc = mmu.cpu_read_word(cpu.pc + 1);
 
                 cpu.cycles += 4;
             } else {
@@ -1238,13 +1254,14 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
             }
         }
         0xC3 => {
-            cpu.pc = mmu.read_word(cpu.pc + 1);
+            cpu.pc = mmu.cpu_read_word(cpu.pc + 1);
             cpu.cycles += 4;
         }
         0xC4 => {
             if !cpu.get_f_zero() {
+                let address = mmu.cpu_read_word(cpu.pc + 1);
                 cpu.push_word(mmu, cpu.pc + 3);
-                cpu.pc = mmu.read_word(cpu.pc + 1);
+                cpu.pc = address;
 
                 cpu.cycles += 6;
             } else {
@@ -1259,7 +1276,7 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
             cpu.cycles += 4;
         }
         0xC6 => {
-            let value = mmu.read_byte(cpu.pc + 1);
+            let value = mmu.cpu_read_byte(cpu.pc + 1);
             cpu.a = cpu.apply_add_u8_with_flags(cpu.a, value, false);
 
 
@@ -1274,6 +1291,7 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
         }
         0xC8 => {
             if cpu.get_f_zero() {
+                mmu.cpu_internal_cycle();
                 cpu.pc = cpu.pop_word(mmu);
 
                 cpu.cycles += 5;
@@ -1288,7 +1306,7 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
         }
         0xCA => {
             if cpu.get_f_zero() {
-                cpu.pc = mmu.read_word(cpu.pc + 1);
+                cpu.pc = mmu.cpu_read_word(cpu.pc + 1);
 
                 cpu.cycles += 4;
             } else {
@@ -1301,8 +1319,9 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
         }
         0xCC => {
             if cpu.get_f_zero() {
-                cpu.push_word(mmu,cpu.pc + 3);
-                cpu.pc = mmu.read_word(cpu.pc + 1);
+                let address = mmu.cpu_read_word(cpu.pc + 1);
+                cpu.push_word(mmu, cpu.pc + 3);
+                cpu.pc = address;
 
                 cpu.cycles += 6;
             } else {
@@ -1311,13 +1330,14 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
             }
         }
         0xCD => {
+            let address = mmu.cpu_read_word(cpu.pc + 1);
             cpu.push_word(mmu, cpu.pc + 3);
-            cpu.pc = mmu.read_word(cpu.pc + 1);
+            cpu.pc = address;
 
             cpu.cycles += 6;
         }
         0xCE => {
-            let value = mmu.read_byte(cpu.pc + 1);
+            let value = mmu.cpu_read_byte(cpu.pc + 1);
             cpu.a = cpu.apply_add_u8_with_flags(cpu.a, value, true);
 
             cpu.pc += 2;
@@ -1331,6 +1351,7 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
         }
         0xD0 => {
             if !cpu.get_f_carry() {
+                mmu.cpu_internal_cycle();
                 cpu.pc = cpu.pop_word(mmu);
 
                 cpu.cycles += 5;
@@ -1348,7 +1369,7 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
         }
         0xD2 => {
             if !cpu.get_f_carry() {
-                cpu.pc = mmu.read_word(cpu.pc + 1);
+                cpu.pc = mmu.cpu_read_word(cpu.pc + 1);
                 cpu.cycles += 4;
             } else {
                 cpu.pc += 3;
@@ -1356,12 +1377,13 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
             }
         }
         0xD3 => {
-            panic!("not implemented");
+            return cpu.lock_up(opcode);
         }
         0xD4 => {
             if !cpu.get_f_carry() {
+                let address = mmu.cpu_read_word(cpu.pc + 1);
                 cpu.push_word(mmu, cpu.pc + 3);
-                cpu.pc = mmu.read_word(cpu.pc + 1);
+                cpu.pc = address;
                 cpu.cycles += 6;
             } else {
                 cpu.pc += 3;
@@ -1375,7 +1397,7 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
             cpu.cycles += 4;
         }
         0xD6 => {
-            let value = mmu.read_byte(cpu.pc + 1);
+            let value = mmu.cpu_read_byte(cpu.pc + 1);
             cpu.a = cpu.apply_sub_u8_with_flags(cpu.a, value, false);
 
             cpu.pc += 2;
@@ -1389,6 +1411,7 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
         }
         0xD8 => {
             if cpu.get_f_carry() {
+                mmu.cpu_internal_cycle();
                 cpu.pc = cpu.pop_word(mmu);
 
                 cpu.cycles += 5;
@@ -1399,13 +1422,14 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
         }
         0xD9 => {
             cpu.pc = cpu.pop_word(mmu);
-            cpu.enable_interrupt_counter = 1;
+            cpu.interrupt_master_enable = true;
+            cpu.enable_interrupt_counter = 0;
 
             cpu.cycles += 4;
         }
         0xDA => {
             if cpu.get_f_carry() {
-                cpu.pc = mmu.read_word(cpu.pc + 1);
+                cpu.pc = mmu.cpu_read_word(cpu.pc + 1);
 
                 cpu.cycles += 4;
             } else {
@@ -1415,12 +1439,13 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
             }
         }
         0xDB => {
-            panic!("not implemented");
+            return cpu.lock_up(opcode);
         }
         0xDC => {
             if cpu.get_f_carry() {
+                let address = mmu.cpu_read_word(cpu.pc + 1);
                 cpu.push_word(mmu, cpu.pc + 3);
-                cpu.pc = mmu.read_word(cpu.pc + 1);
+                cpu.pc = address;
                 cpu.cycles += 6;
             } else {
                 cpu.pc += 3;
@@ -1428,10 +1453,10 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
             }
         }
         0xDD => {
-            panic!("not implemented");
+            return cpu.lock_up(opcode);
         }
         0xDE => {
-            let value = mmu.read_byte(cpu.pc + 1);
+            let value = mmu.cpu_read_byte(cpu.pc + 1);
             cpu.a = cpu.apply_sub_u8_with_flags(cpu.a, value, true);
 
             cpu.pc += 2;
@@ -1444,10 +1469,10 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
             cpu.cycles += 4;
         }
         0xE0 => {
-            let value = mmu.read_byte(cpu.pc + 1) as u16;
+            let value = mmu.cpu_read_byte(cpu.pc + 1) as u16;
             let a = 0xFF00 | value;
 
-            mmu.write_byte(a, cpu.a);
+            mmu.cpu_write_byte(a, cpu.a);
 
             cpu.pc += 2;
             cpu.cycles += 3;
@@ -1461,16 +1486,16 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
         }
         0xE2 => {
             let address = 0xFF00 | (cpu.c as u16);
-            mmu.write_byte(address, cpu.a);
+            mmu.cpu_write_byte(address, cpu.a);
 
             cpu.pc += 1;
             cpu.cycles += 2;
         }
         0xE3 => {
-            panic!("not implemented");
+            return cpu.lock_up(opcode);
         }
         0xE4 => {
-            panic!("not implemented");
+            return cpu.lock_up(opcode);
         }
         0xE5 => {
             cpu.push_word(mmu, cpu.get_hl());
@@ -1479,7 +1504,7 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
             cpu.cycles += 4;
         }
         0xE6 => {
-            let value = mmu.read_byte(cpu.pc + 1);
+            let value = mmu.cpu_read_byte(cpu.pc + 1);
             cpu.a = cpu.apply_and_u8_with_flags(cpu.a, value);
 
             cpu.pc += 2;
@@ -1492,7 +1517,7 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
             cpu.cycles += 4;
         }
         0xE8 => {
-            let value = mmu.read_byte(cpu.pc + 1) as i8 as i16;
+            let value = mmu.cpu_read_byte(cpu.pc + 1) as i8 as i16;
             let result = cpu.apply_add_i16_with_flags(cpu.sp as i16, value);
             cpu.sp = result;
 
@@ -1505,23 +1530,23 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
             cpu.cycles += 1;
         }
         0xEA => {
-            let a = mmu.read_word(cpu.pc + 1);
-            mmu.write_byte(a, cpu.a);
+            let a = mmu.cpu_read_word(cpu.pc + 1);
+            mmu.cpu_write_byte(a, cpu.a);
 
             cpu.pc += 3;
             cpu.cycles += 4;
         }
         0xEB => {
-            panic!("not implemented");
+            return cpu.lock_up(opcode);
         }
         0xEC => {
-            panic!("not implemented");
+            return cpu.lock_up(opcode);
         }
         0xED => {
-            panic!("not implemented");
+            return cpu.lock_up(opcode);
         }
         0xEE => {
-            let value = mmu.read_byte(cpu.pc + 1);
+            let value = mmu.cpu_read_byte(cpu.pc + 1);
             cpu.a = cpu.apply_xor_u8_with_flags(cpu.a, value);
 
             cpu.pc += 2;
@@ -1534,8 +1559,8 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
             cpu.cycles += 4;
         }
         0xF0 => {
-            let address = 0xFF00 | mmu.read_byte(cpu.pc + 1) as u16;
-            cpu.a = mmu.read_byte(address);
+            let address = 0xFF00 | mmu.cpu_read_byte(cpu.pc + 1) as u16;
+            cpu.a = mmu.cpu_read_byte(address);
 
             cpu.pc += 2;
             cpu.cycles += 3;
@@ -1549,20 +1574,21 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
         }
         0xF2 => {
             let address = 0xFF00 | cpu.c as u16;
-            cpu.a = mmu.read_byte(address);
+            cpu.a = mmu.cpu_read_byte(address);
 
             cpu.pc += 1;
             cpu.cycles += 2;
         }
         0xF3 => {
-            // Schedules interrupt handling to be enabled after the next machine cycle
-            cpu.disable_interrupt_counter = 2;
+            // Takes effect immediately and cancels a pending EI
+            cpu.interrupt_master_enable = false;
+            cpu.enable_interrupt_counter = 0;
 
             cpu.pc += 1;
             cpu.cycles += 1;
         }
         0xF4 => {
-            panic!("not implemented");
+            return cpu.lock_up(opcode);
         }
         0xF5 => {
             cpu.push_word(mmu, cpu.get_af());
@@ -1571,7 +1597,7 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
             cpu.cycles += 4;
         }
         0xF6 => {
-            let value = mmu.read_byte(cpu.pc + 1);
+            let value = mmu.cpu_read_byte(cpu.pc + 1);
             cpu.a = cpu.apply_or_u8_with_flags(cpu.a, value);
 
             cpu.pc += 2;
@@ -1584,7 +1610,7 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
             cpu.cycles += 4;
         }
         0xF8 => {
-            let value = mmu.read_byte(cpu.pc + 1) as i8 as i16;
+            let value = mmu.cpu_read_byte(cpu.pc + 1) as i8 as i16;
             let result = cpu.apply_add_i16_with_flags(cpu.sp as i16, value);
             cpu.set_hl(result);
 
@@ -1598,8 +1624,8 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
             cpu.cycles += 2;
         }
         0xFA => {
-            let address = mmu.read_word(cpu.pc + 1);
-            cpu.a = mmu.read_byte(address);
+            let address = mmu.cpu_read_word(cpu.pc + 1);
+            cpu.a = mmu.cpu_read_byte(address);
 
             cpu.pc += 3;
             cpu.cycles += 4;
@@ -1611,13 +1637,13 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
             cpu.cycles += 1;
         }
         0xFC => {
-            panic!("not implemented");
+            return cpu.lock_up(opcode);
         }
         0xFD => {
-            panic!("not implemented");
+            return cpu.lock_up(opcode);
         }
         0xFE => {
-            let value = mmu.read_byte(cpu.pc + 1);
+            let value = mmu.cpu_read_byte(cpu.pc + 1);
             cpu.apply_sub_u8_with_flags(cpu.a, value, false);
 
             cpu.pc += 2;
@@ -1629,13 +1655,15 @@ pub fn execute_operation(opcode: u8, cpu: &mut Cpu, mmu: &mut Mmu) -> () {
 
             cpu.cycles += 4;
         }
-        _ => { panic!("opcode not found {}", opcode) }
+        _ => { return cpu.lock_up(opcode); }
     }
+
+    return Ok(());
 }
 
 #[allow(unreachable_patterns)]
 pub fn op_cb(cpu: &mut Cpu, mmu: &mut Mmu) {
-    let opcode = mmu.read_byte(cpu.pc + 1);
+    let opcode = mmu.cpu_read_byte(cpu.pc + 1);
 
     cpu.opcode = (cpu.opcode << 8) as u16 | opcode as u16;
     cpu.pc += 1;
@@ -1672,10 +1700,10 @@ pub fn op_cb(cpu: &mut Cpu, mmu: &mut Mmu) {
         }
         0x06 => {
             let address = cpu.get_hl();
-            let value = mmu.read_byte(address);
+            let value = mmu.cpu_read_byte(address);
 
             let result = cpu.apply_rotate_left_with_flags(value, true);
-            mmu.write_byte(address, result);
+            mmu.cpu_write_byte(address, result);
 
             cpu.pc += 1;
             cpu.cycles += 4;
@@ -1717,10 +1745,10 @@ pub fn op_cb(cpu: &mut Cpu, mmu: &mut Mmu) {
         }
         0x0E => {
             let address = cpu.get_hl();
-            let value = mmu.read_byte(address);
+            let value = mmu.cpu_read_byte(address);
 
             let result = cpu.apply_rotate_right_with_flags(value, true);
-            mmu.write_byte(address, result);
+            mmu.cpu_write_byte(address, result);
 
             cpu.pc += 1;
             cpu.cycles += 4;
@@ -1762,10 +1790,10 @@ pub fn op_cb(cpu: &mut Cpu, mmu: &mut Mmu) {
         }
         0x16 => {
             let address = cpu.get_hl();
-            let value = mmu.read_byte(address);
+            let value = mmu.cpu_read_byte(address);
 
             let result = cpu.apply_rotate_left_with_flags(value, false);
-            mmu.write_byte(address, result);
+            mmu.cpu_write_byte(address, result);
 
             cpu.pc += 1;
             cpu.cycles += 4;
@@ -1807,10 +1835,10 @@ pub fn op_cb(cpu: &mut Cpu, mmu: &mut Mmu) {
         }
         0x1E => {
             let address = cpu.get_hl();
-            let value = mmu.read_byte(address);
+            let value = mmu.cpu_read_byte(address);
 
             let result = cpu.apply_rotate_right_with_flags(value, false);
-            mmu.write_byte(address, result);
+            mmu.cpu_write_byte(address, result);
 
             cpu.pc += 1;
             cpu.cycles += 4;
@@ -1852,10 +1880,10 @@ pub fn op_cb(cpu: &mut Cpu, mmu: &mut Mmu) {
         }
         0x26 => {
             let address = cpu.get_hl();
-            let value = mmu.read_byte(address);
+            let value = mmu.cpu_read_byte(address);
 
             let result = cpu.apply_shift_left_with_flags(value);
-            mmu.write_byte(address, result);
+            mmu.cpu_write_byte(address, result);
 
             cpu.pc += 1;
             cpu.cycles += 4;
@@ -1897,10 +1925,10 @@ pub fn op_cb(cpu: &mut Cpu, mmu: &mut Mmu) {
         }
         0x2E => {
             let address = cpu.get_hl();
-            let value = mmu.read_byte(address);
+            let value = mmu.cpu_read_byte(address);
 
             let result = cpu.apply_shift_right_with_flags(value, true);
-            mmu.write_byte(address, result);
+            mmu.cpu_write_byte(address, result);
 
             cpu.pc += 1;
             cpu.cycles += 4;
@@ -1942,10 +1970,10 @@ pub fn op_cb(cpu: &mut Cpu, mmu: &mut Mmu) {
         }
         0x36 => {
             let address = cpu.get_hl();
-            let value = mmu.read_byte(address);
+            let value = mmu.cpu_read_byte(address);
 
             let result = cpu.apply_swap_bytes(value);
-            mmu.write_byte(address, result);
+            mmu.cpu_write_byte(address, result);
 
             cpu.pc += 1;
             cpu.cycles += 4;
@@ -1987,10 +2015,10 @@ pub fn op_cb(cpu: &mut Cpu, mmu: &mut Mmu) {
         }
         0x3E => {
             let address = cpu.get_hl();
-            let value = mmu.read_byte(address);
+            let value = mmu.cpu_read_byte(address);
 
             let result = cpu.apply_shift_right_with_flags(value, false);
-            mmu.write_byte(address, result);
+            mmu.cpu_write_byte(address, result);
 
             cpu.pc += 1;
             cpu.cycles += 4;
@@ -2032,12 +2060,12 @@ pub fn op_cb(cpu: &mut Cpu, mmu: &mut Mmu) {
         }
         0x46 => {
             let address = cpu.get_hl();
-            let value = mmu.read_byte(address);
+            let value = mmu.cpu_read_byte(address);
 
             cpu.apply_bit_test(value, 0);
 
             cpu.pc += 1;
-            cpu.cycles += 4;
+            cpu.cycles += 3;
         }
         0x47 => {
             cpu.apply_bit_test(cpu.a, 0);
@@ -2076,12 +2104,12 @@ pub fn op_cb(cpu: &mut Cpu, mmu: &mut Mmu) {
         }
         0x4E => {
             let address = cpu.get_hl();
-            let value = mmu.read_byte(address);
+            let value = mmu.cpu_read_byte(address);
 
             cpu.apply_bit_test(value, 1);
 
             cpu.pc += 1;
-            cpu.cycles += 4;
+            cpu.cycles += 3;
         }
         0x4F => {
             cpu.apply_bit_test(cpu.a, 1);
@@ -2120,12 +2148,12 @@ pub fn op_cb(cpu: &mut Cpu, mmu: &mut Mmu) {
         }
         0x56 => {
             let address = cpu.get_hl();
-            let value = mmu.read_byte(address);
+            let value = mmu.cpu_read_byte(address);
 
             cpu.apply_bit_test(value, 2);
 
             cpu.pc += 1;
-            cpu.cycles += 4;
+            cpu.cycles += 3;
         }
         0x57 => {
             cpu.apply_bit_test(cpu.a, 2);
@@ -2164,12 +2192,12 @@ pub fn op_cb(cpu: &mut Cpu, mmu: &mut Mmu) {
         }
         0x5E => {
             let address = cpu.get_hl();
-            let value = mmu.read_byte(address);
+            let value = mmu.cpu_read_byte(address);
 
             cpu.apply_bit_test(value, 3);
 
             cpu.pc += 1;
-            cpu.cycles += 4;
+            cpu.cycles += 3;
         }
         0x5F => {
             cpu.apply_bit_test(cpu.a, 3);
@@ -2208,12 +2236,12 @@ pub fn op_cb(cpu: &mut Cpu, mmu: &mut Mmu) {
         }
         0x66 => {
             let address = cpu.get_hl();
-            let value = mmu.read_byte(address);
+            let value = mmu.cpu_read_byte(address);
 
             cpu.apply_bit_test(value, 4);
 
             cpu.pc += 1;
-            cpu.cycles += 4;
+            cpu.cycles += 3;
         }
         0x67 => {
             cpu.apply_bit_test(cpu.a, 4);
@@ -2252,12 +2280,12 @@ pub fn op_cb(cpu: &mut Cpu, mmu: &mut Mmu) {
         }
         0x6E => {
             let address = cpu.get_hl();
-            let value = mmu.read_byte(address);
+            let value = mmu.cpu_read_byte(address);
 
             cpu.apply_bit_test(value, 5);
 
             cpu.pc += 1;
-            cpu.cycles += 4;
+            cpu.cycles += 3;
         }
         0x6F => {
             cpu.apply_bit_test(cpu.a, 5);
@@ -2296,12 +2324,12 @@ pub fn op_cb(cpu: &mut Cpu, mmu: &mut Mmu) {
         }
         0x76 => {
             let address = cpu.get_hl();
-            let value = mmu.read_byte(address);
+            let value = mmu.cpu_read_byte(address);
 
             cpu.apply_bit_test(value, 6);
 
             cpu.pc += 1;
-            cpu.cycles += 4;
+            cpu.cycles += 3;
         }
         0x77 => {
             cpu.apply_bit_test(cpu.a, 6);
@@ -2340,12 +2368,12 @@ pub fn op_cb(cpu: &mut Cpu, mmu: &mut Mmu) {
         }
         0x7E => {
             let address = cpu.get_hl();
-            let value = mmu.read_byte(address);
+            let value = mmu.cpu_read_byte(address);
 
             cpu.apply_bit_test(value, 7);
 
             cpu.pc += 1;
-            cpu.cycles += 4;
+            cpu.cycles += 3;
         }
         0x7F => {
             cpu.apply_bit_test(cpu.a, 7);
@@ -2384,9 +2412,9 @@ pub fn op_cb(cpu: &mut Cpu, mmu: &mut Mmu) {
         }
         0x86 => {
             let address = cpu.get_hl();
-            let value = mmu.read_byte(address);
+            let value = mmu.cpu_read_byte(address);
 
-            mmu.write_byte(address, value & !(1 << 0));
+            mmu.cpu_write_byte(address, value & !(1 << 0));
 
             cpu.pc += 1;
             cpu.cycles += 4;
@@ -2428,9 +2456,9 @@ pub fn op_cb(cpu: &mut Cpu, mmu: &mut Mmu) {
         }
         0x8E => {
             let address = cpu.get_hl();
-            let value = mmu.read_byte(address);
+            let value = mmu.cpu_read_byte(address);
 
-            mmu.write_byte(address, value & !(1 << 1));
+            mmu.cpu_write_byte(address, value & !(1 << 1));
 
             cpu.pc += 1;
             cpu.cycles += 4;
@@ -2472,9 +2500,9 @@ pub fn op_cb(cpu: &mut Cpu, mmu: &mut Mmu) {
         }
         0x96 => {
             let address = cpu.get_hl();
-            let value = mmu.read_byte(address);
+            let value = mmu.cpu_read_byte(address);
 
-            mmu.write_byte(address, value & !(1 << 2));
+            mmu.cpu_write_byte(address, value & !(1 << 2));
 
             cpu.pc += 1;
             cpu.cycles += 4;
@@ -2516,9 +2544,9 @@ pub fn op_cb(cpu: &mut Cpu, mmu: &mut Mmu) {
         }
         0x9E => {
             let address = cpu.get_hl();
-            let value = mmu.read_byte(address);
+            let value = mmu.cpu_read_byte(address);
 
-            mmu.write_byte(address, value & !(1 << 3));
+            mmu.cpu_write_byte(address, value & !(1 << 3));
 
             cpu.pc += 1;
             cpu.cycles += 4;
@@ -2560,9 +2588,9 @@ pub fn op_cb(cpu: &mut Cpu, mmu: &mut Mmu) {
         }
         0xA6 => {
             let address = cpu.get_hl();
-            let value = mmu.read_byte(address);
+            let value = mmu.cpu_read_byte(address);
 
-            mmu.write_byte(address, value & !(1 << 4));
+            mmu.cpu_write_byte(address, value & !(1 << 4));
 
             cpu.pc += 1;
             cpu.cycles += 4;
@@ -2604,9 +2632,9 @@ pub fn op_cb(cpu: &mut Cpu, mmu: &mut Mmu) {
         }
         0xAE => {
             let address = cpu.get_hl();
-            let value = mmu.read_byte(address);
+            let value = mmu.cpu_read_byte(address);
 
-            mmu.write_byte(address, value & !(1 << 5));
+            mmu.cpu_write_byte(address, value & !(1 << 5));
 
             cpu.pc += 1;
             cpu.cycles += 4;
@@ -2648,9 +2676,9 @@ pub fn op_cb(cpu: &mut Cpu, mmu: &mut Mmu) {
         }
         0xB6 => {
             let address = cpu.get_hl();
-            let value = mmu.read_byte(address);
+            let value = mmu.cpu_read_byte(address);
 
-            mmu.write_byte(address, value & !(1 << 6));
+            mmu.cpu_write_byte(address, value & !(1 << 6));
 
             cpu.pc += 1;
             cpu.cycles += 4;
@@ -2692,9 +2720,9 @@ pub fn op_cb(cpu: &mut Cpu, mmu: &mut Mmu) {
         }
         0xBE => {
             let address = cpu.get_hl();
-            let value = mmu.read_byte(address);
+            let value = mmu.cpu_read_byte(address);
 
-            mmu.write_byte(address, value & !(1 << 7));
+            mmu.cpu_write_byte(address, value & !(1 << 7));
 
             cpu.pc += 1;
             cpu.cycles += 4;
@@ -2736,9 +2764,9 @@ pub fn op_cb(cpu: &mut Cpu, mmu: &mut Mmu) {
         }
         0xC6 => {
             let address = cpu.get_hl();
-            let value = mmu.read_byte(address);
+            let value = mmu.cpu_read_byte(address);
 
-            mmu.write_byte(address, value | (1 << 0));
+            mmu.cpu_write_byte(address, value | (1 << 0));
 
             cpu.pc += 1;
             cpu.cycles += 4;
@@ -2780,9 +2808,9 @@ pub fn op_cb(cpu: &mut Cpu, mmu: &mut Mmu) {
         }
         0xCE => {
             let address = cpu.get_hl();
-            let value = mmu.read_byte(address);
+            let value = mmu.cpu_read_byte(address);
 
-            mmu.write_byte(address, value | (1 << 1));
+            mmu.cpu_write_byte(address, value | (1 << 1));
 
             cpu.pc += 1;
             cpu.cycles += 4;
@@ -2824,9 +2852,9 @@ pub fn op_cb(cpu: &mut Cpu, mmu: &mut Mmu) {
         }
         0xD6 => {
             let address = cpu.get_hl();
-            let value = mmu.read_byte(address);
+            let value = mmu.cpu_read_byte(address);
 
-            mmu.write_byte(address, value | (1 << 2));
+            mmu.cpu_write_byte(address, value | (1 << 2));
 
             cpu.pc += 1;
             cpu.cycles += 4;
@@ -2868,9 +2896,9 @@ pub fn op_cb(cpu: &mut Cpu, mmu: &mut Mmu) {
         }
         0xDE => {
             let address = cpu.get_hl();
-            let value = mmu.read_byte(address);
+            let value = mmu.cpu_read_byte(address);
 
-            mmu.write_byte(address, value | (1 << 3));
+            mmu.cpu_write_byte(address, value | (1 << 3));
 
             cpu.pc += 1;
             cpu.cycles += 4;
@@ -2912,9 +2940,9 @@ pub fn op_cb(cpu: &mut Cpu, mmu: &mut Mmu) {
         }
         0xE6 => {
             let address = cpu.get_hl();
-            let value = mmu.read_byte(address);
+            let value = mmu.cpu_read_byte(address);
 
-            mmu.write_byte(address, value | (1 << 4));
+            mmu.cpu_write_byte(address, value | (1 << 4));
 
             cpu.pc += 1;
             cpu.cycles += 4;
@@ -2956,9 +2984,9 @@ pub fn op_cb(cpu: &mut Cpu, mmu: &mut Mmu) {
         }
         0xEE => {
             let address = cpu.get_hl();
-            let value = mmu.read_byte(address);
+            let value = mmu.cpu_read_byte(address);
 
-            mmu.write_byte(address, value | (1 << 5));
+            mmu.cpu_write_byte(address, value | (1 << 5));
 
             cpu.pc += 1;
             cpu.cycles += 4;
@@ -3000,9 +3028,9 @@ pub fn op_cb(cpu: &mut Cpu, mmu: &mut Mmu) {
         }
         0xF6 => {
             let address = cpu.get_hl();
-            let value = mmu.read_byte(address);
+            let value = mmu.cpu_read_byte(address);
 
-            mmu.write_byte(address, value | (1 << 6));
+            mmu.cpu_write_byte(address, value | (1 << 6));
 
             cpu.pc += 1;
             cpu.cycles += 4;
@@ -3044,9 +3072,9 @@ pub fn op_cb(cpu: &mut Cpu, mmu: &mut Mmu) {
         }
         0xFE => {
             let address = cpu.get_hl();
-            let value = mmu.read_byte(address);
+            let value = mmu.cpu_read_byte(address);
 
-            mmu.write_byte(address, value | (1 << 7));
+            mmu.cpu_write_byte(address, value | (1 << 7));
 
             cpu.pc += 1;
             cpu.cycles += 4;
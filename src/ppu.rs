@@ -1,9 +1,7 @@
 use crate::console::GameboyType;
-use crate::logger::log;
 use crate::mmu::Mmu;
-use wasm_bindgen::prelude::*;
-use web_sys::CanvasRenderingContext2d;
-use js_sys::*;
+use crate::error::EmulatorError;
+use crate::state::{StateWriter, StateReader};
 
 pub const VRAM_SIZE: usize = 0x4000;
 pub const VOAM_SIZE: usize = 0xA0;
@@ -11,6 +9,8 @@ pub const SCREEN_W: usize = 160;
 pub const SCREEN_H: usize = 144;
 pub const INTERRUPT_TIMER_MASK: u8 = 0x02;
 pub const INTERRUPT_V_BLANK_MASK: u8 = 0x01;
+// 154 lines of 456 cycles
+const FRAME_TICKS: u32 = 70224;
 
 #[derive(PartialEq, Copy, Clone)]
 enum PaletteType {
@@ -59,7 +59,6 @@ pub enum GpuMode {
     VBlank = 1,
 }
 
-#[wasm_bindgen]
 pub struct Ppu {
     // 0xFF40 (http://bgb.bircd.org/pandocs.htm#videodisplay)
     lcd_display_enable: bool,
@@ -120,11 +119,16 @@ pub struct Ppu {
     ly: u8,
     wly: u32,
     model: GameboyType,
+    // DMG mode of a CGB, selected by its boot rom for DMG cartridges. The DMG palettes pick
+    // colors of the first color palettes, which the boot rom set up from the title checksum.
+    compatibility: bool,
     frame: [u8; SCREEN_W * SCREEN_H * 4],
+    frame_ready: bool,
     buffer: [u8; SCREEN_W * SCREEN_H * 4],
+    // Cycles into the current blank frame while STOP halts the ppu
+    stopped_clock: u32,
 }
 
-#[wasm_bindgen]
 #[allow(dead_code)]
 impl Ppu {
 
@@ -183,9 +187,12 @@ impl Ppu {
             ly: 0,
             wly: 0,
             model: GameboyType::CLASSIC,
+            compatibility: false,
 
             frame: [0; SCREEN_W * SCREEN_H * 4],
+            frame_ready: false,
             buffer: [0; SCREEN_W * SCREEN_H * 4],
+            stopped_clock: 0,
         };
     }
 
@@ -196,7 +203,145 @@ impl Ppu {
         self.clock = 0;
         self.mode = GpuMode::Read;
         self.model = model;
+        self.compatibility = false;
         self.ly = 0;
+        self.frame_ready = false;
+        self.stopped_clock = 0;
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.lcd_display_enable);
+        writer.write_u16(self.window_tile_map_select);
+        writer.write_bool(self.window_display_enable);
+        writer.write_u16(self.bg_tile_data_select);
+        writer.write_u16(self.bg_tile_map_select);
+        writer.write_i32(self.sprite_size);
+        writer.write_bool(self.sprite_enable);
+        writer.write_bool(self.bg_display_enable);
+
+        writer.write_bool(self.lyc_interrupt_enable);
+        writer.write_bool(self.mode_2_interrupt);
+        writer.write_bool(self.mode_1_interrupt);
+        writer.write_bool(self.mode_0_interrupt);
+
+        writer.write_u8(self.scroll_y_coord);
+        writer.write_u8(self.scroll_x_coord);
+        writer.write_u8(self.window_y_coord);
+        writer.write_u8(self.window_x_coord);
+        writer.write_u8(self.lcd_y_coordinate);
+        writer.write_u8(self.lyc);
+
+        writer.write_u8(self.pal_bg_palette_data);
+        writer.write_u8(self.pal_obj_palette_0_data);
+        writer.write_u8(self.pal_obj_palette_1_data);
+        writer.write_bytes(&self.pal_bg_palette);
+        writer.write_bytes(&self.pal_obj_palette_0);
+        writer.write_bytes(&self.pal_obj_palette_1);
+
+        writer.write_u8(self.cbg_bg_palette_index);
+        writer.write_bool(self.cbg_bg_palette_increment);
+        writer.write_bytes(&Ppu::flatten_cgb_palette(&self.cbg_bg_palette));
+        writer.write_u8(self.cbg_obj_index);
+        writer.write_bool(self.cbg_obj_increment);
+        writer.write_bytes(&Ppu::flatten_cgb_palette(&self.cbg_obj));
+
+        writer.write_u32(self.vram_bank as u32);
+        writer.write_bytes(&self.vram);
+        writer.write_bytes(&self.voam);
+
+        writer.write_u8(self.interrupt_flags);
+        writer.write_bool(self.h_blank);
+        writer.write_bool(self.v_blank);
+        writer.write_bool(self.obj_master_priority);
+
+        writer.write_u8(self.mode as u8);
+        writer.write_u32(self.clock);
+        writer.write_u8(self.ly);
+        writer.write_u32(self.wly);
+        writer.write_model(self.model);
+        writer.write_bytes(&self.frame);
+        writer.write_bool(self.frame_ready);
+        writer.write_bytes(&self.buffer);
+        writer.write_bool(self.compatibility);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<(), EmulatorError> {
+        self.lcd_display_enable = reader.read_bool()?;
+        self.window_tile_map_select = reader.read_u16()?;
+        self.window_display_enable = reader.read_bool()?;
+        self.bg_tile_data_select = reader.read_u16()?;
+        self.bg_tile_map_select = reader.read_u16()?;
+        self.sprite_size = reader.read_i32()?;
+        self.sprite_enable = reader.read_bool()?;
+        self.bg_display_enable = reader.read_bool()?;
+
+        self.lyc_interrupt_enable = reader.read_bool()?;
+        self.mode_2_interrupt = reader.read_bool()?;
+        self.mode_1_interrupt = reader.read_bool()?;
+        self.mode_0_interrupt = reader.read_bool()?;
+
+        self.scroll_y_coord = reader.read_u8()?;
+        self.scroll_x_coord = reader.read_u8()?;
+        self.window_y_coord = reader.read_u8()?;
+        self.window_x_coord = reader.read_u8()?;
+        self.lcd_y_coordinate = reader.read_u8()?;
+        self.lyc = reader.read_u8()?;
+
+        self.pal_bg_palette_data = reader.read_u8()?;
+        self.pal_obj_palette_0_data = reader.read_u8()?;
+        self.pal_obj_palette_1_data = reader.read_u8()?;
+        reader.read_bytes_into(&mut self.pal_bg_palette)?;
+        reader.read_bytes_into(&mut self.pal_obj_palette_0)?;
+        reader.read_bytes_into(&mut self.pal_obj_palette_1)?;
+
+        let mut palette = [0u8; 8 * 4 * 3];
+        self.cbg_bg_palette_index = reader.read_u8()?;
+        self.cbg_bg_palette_increment = reader.read_bool()?;
+        reader.read_bytes_into(&mut palette)?;
+        Ppu::unflatten_cgb_palette(&palette, &mut self.cbg_bg_palette);
+        self.cbg_obj_index = reader.read_u8()?;
+        self.cbg_obj_increment = reader.read_bool()?;
+        reader.read_bytes_into(&mut palette)?;
+        Ppu::unflatten_cgb_palette(&palette, &mut self.cbg_obj);
+
+        self.vram_bank = reader.read_u32()? as usize;
+        if self.vram_bank > 1 {
+            return Err(reader.invalid("vram bank"));
+        }
+        reader.read_bytes_into(&mut self.vram)?;
+        reader.read_bytes_into(&mut self.voam)?;
+
+        self.interrupt_flags = reader.read_u8()?;
+        self.h_blank = reader.read_bool()?;
+        self.v_blank = reader.read_bool()?;
+        self.obj_master_priority = reader.read_bool()?;
+
+        self.mode = match reader.read_u8()? {
+            0 => GpuMode::HBlank,
+            1 => GpuMode::VBlank,
+            2 => GpuMode::Read,
+            3 => GpuMode::Transfer,
+            _ => return Err(reader.invalid("mode")),
+        };
+        self.clock = reader.read_u32()?;
+        self.ly = reader.read_u8()?;
+        self.wly = reader.read_u32()?;
+        self.model = reader.read_model()?;
+        reader.read_bytes_into(&mut self.frame)?;
+        self.frame_ready = reader.read_bool()?;
+        reader.read_bytes_into(&mut self.buffer)?;
+        self.compatibility = reader.minor() >= 5 && reader.read_bool()?;
+        return Ok(());
+    }
+
+    fn flatten_cgb_palette(palette: &[[[u8; 3]; 4]; 8]) -> Vec<u8> {
+        return palette.iter().flatten().flatten().cloned().collect();
+    }
+
+    fn unflatten_cgb_palette(data: &[u8], palette: &mut [[[u8; 3]; 4]; 8]) {
+        for (i, value) in palette.iter_mut().flatten().flatten().enumerate() {
+            *value = data[i];
+        }
     }
 
     pub fn execute_ticks(&mut self, ticks: u32) -> () {
@@ -271,16 +416,32 @@ impl Ppu {
 
     }
 
-    pub fn get_frame(&self) -> js_sys::Array {
-        let arr = js_sys::Array::new_with_length(self.frame.len() as u32);
-        for i in 0 .. self.frame.len() {
-            arr.set(i as u32, JsValue::from(self.frame[i]));
+    // STOP freezes the ppu where it is and blanks the lcd. Blank frames keep coming at the usual
+    // rate so the host does not wait on a frame that never finishes.
+    pub fn execute_stopped_ticks(&mut self, ticks: u32) {
+        self.stopped_clock += ticks;
+        if self.stopped_clock >= FRAME_TICKS {
+            self.stopped_clock -= FRAME_TICKS;
+            self.frame = [0xFF; SCREEN_W * SCREEN_H * 4];
+            self.frame_ready = true;
         }
-        return arr;
+    }
+
+    // RGBA pixels of the last completed frame
+    pub fn get_frame(&self) -> &[u8] {
+        return &self.frame;
+    }
+
+    // Returns true once after every completed frame
+    pub fn take_frame_ready(&mut self) -> bool {
+        let ready = self.frame_ready;
+        self.frame_ready = false;
+        return ready;
     }
 
     fn render_frame(&mut self) {
         self.frame = self.buffer.clone();
+        self.frame_ready = true;
     }
 
     fn render_scan_line(&mut self) {
@@ -365,6 +526,11 @@ impl Ppu {
                 let g = self.cbg_bg_palette[attributes.palette_number][palette_index][1];
                 let b = self.cbg_bg_palette[attributes.palette_number][palette_index][2];
 
+                self.set_rgb_at(display_x as usize, self.ly as usize, r, g, b);
+            } else if self.compatibility {
+                let shade = (self.pal_bg_palette_data >> (2 * palette_index)) & 0x03;
+                let [r, g, b] = self.cbg_bg_palette[0][shade as usize];
+
                 self.set_rgb_at(display_x as usize, self.ly as usize, r, g, b);
             } else {
                 let r = self.pal_bg_palette[palette_index];
@@ -528,6 +694,12 @@ impl Ppu {
                     let g = palette[1];
                     let b = palette[2];
 
+                    self.set_rgb_at(sprite_x_cord as usize, sprite_y_cord as usize, r, g, b);
+                } else if self.compatibility {
+                    let data = if sprite_oam.pal_palette_index == 1 { self.pal_obj_palette_1_data } else { self.pal_obj_palette_0_data };
+                    let shade = (data >> (2 * palette_index)) & 0x03;
+                    let [r, g, b] = self.cbg_obj[sprite_oam.pal_palette_index as usize][shade as usize];
+
                     self.set_rgb_at(sprite_x_cord as usize, sprite_y_cord as usize, r, g, b);
                 } else {
                     let palette = if sprite_oam.pal_palette_index == 1 { self.pal_obj_palette_1 } else { self.pal_obj_palette_0 };
@@ -772,7 +944,7 @@ impl Ppu {
                 }
             },
             0xFF6C => 0x0,
-            _ => panic!("invalid"),
+            _ => 0xFF,
         }
     }
 
@@ -838,7 +1010,7 @@ impl Ppu {
                 if self.cbg_obj_increment { self.cbg_obj_index = (self.cbg_obj_index + 1) & 0x3F; };
             },
             0xFF6C => {}
-            _ => panic!("invalid {}", address),
+            _ => {},
         }
     }
 
@@ -893,4 +1065,10 @@ impl Ppu {
         self.model = model;
     }
 
+    // Switches a CGB to the DMG mode, see compatibility
+    pub fn enter_compatibility_mode(&mut self) {
+        self.model = GameboyType::CLASSIC;
+        self.compatibility = true;
+    }
+
 }
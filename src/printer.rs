@@ -0,0 +1,323 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use crate::error::EmulatorError;
+use crate::serial::SerialDevice;
+
+// https://gbdev.io/pandocs/Gameboy_Printer.html
+const MAGIC: [u8; 2] = [0x88, 0x33];
+
+const COMMAND_INIT: u8 = 0x01;
+const COMMAND_PRINT: u8 = 0x02;
+const COMMAND_DATA: u8 = 0x04;
+const COMMAND_STATUS: u8 = 0x0F;
+
+// Bit 0 - Checksum error
+// Bit 1 - Printer busy
+// Bit 2 - Image data full
+// Bit 3 - Unprocessed data
+const STATUS_CHECKSUM_ERROR: u8 = 0x01;
+const STATUS_BUSY: u8 = 0x02;
+const STATUS_FULL: u8 = 0x04;
+const STATUS_UNPROCESSED: u8 = 0x08;
+
+// Sent back while the game clocks the first byte after the checksum
+const DEVICE_ID: u8 = 0x81;
+
+// The paper is 160 pixels wide, a data packet holds two rows of 20 tiles
+pub const PRINT_WIDTH: usize = 160;
+const TILES_PER_ROW: usize = PRINT_WIDTH / 8;
+const BYTES_PER_ROW: usize = TILES_PER_ROW * 16;
+
+// The printer buffers 9 data packets, a full screen of 144 lines
+const MAX_IMAGE_DATA: usize = BYTES_PER_ROW * 18;
+
+// Number of status inquiries answered with the busy flag after printing, games wait for it to clear
+const BUSY_INQUIRIES: u8 = 4;
+
+// Gray levels of the thermal paper, the same as the DMG palette of the ppu
+const SHADES: [u8; 4] = [255, 192, 96, 0];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PacketState {
+    Magic(usize),
+    Command,
+    Compression,
+    LengthLow,
+    LengthHigh,
+    Data,
+    ChecksumLow,
+    ChecksumHigh,
+    DeviceId,
+    Status,
+}
+
+// An image printed by a single print command
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PrintJob {
+    pub width: usize,
+    pub height: usize,
+    // RGBA pixels, width * height * 4
+    pub pixels: Vec<u8>,
+    pub sheets: u8,
+    // Paper fed before and after the image, in the upper and lower nibble of the margins byte
+    pub margin_before: u8,
+    pub margin_after: u8,
+    // Shade of each color index, like BGP
+    pub palette: u8,
+    // 0x00 is the lightest and 0x7F the darkest burn, 0x40 is the default
+    pub exposure: u8,
+}
+
+impl PrintJob {
+
+    pub fn to_png(&self) -> Result<Vec<u8>, EmulatorError> {
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header()
+            .and_then(|mut writer| writer.write_image_data(&self.pixels))
+            .map_err(|e| EmulatorError::Io(format!("unable to encode print: {}", e)))?;
+        return Ok(bytes);
+    }
+}
+
+// Finished print jobs, shared between the printer plugged into a console and the host
+#[derive(Clone, Default)]
+pub struct PrintQueue {
+    jobs: Rc<RefCell<VecDeque<PrintJob>>>,
+}
+
+impl PrintQueue {
+
+    pub fn take_job(&self) -> Option<PrintJob> {
+        return self.jobs.borrow_mut().pop_front();
+    }
+
+    pub fn len(&self) -> usize {
+        return self.jobs.borrow().len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.jobs.borrow().is_empty();
+    }
+}
+
+// Game Boy Printer, plugged into the link port with attach_serial_device. The game drives the
+// clock and sends packets of magic, command, compression flag, length, data and checksum,
+// followed by two bytes during which the printer answers with its id and status.
+pub struct Printer {
+    state: PacketState,
+    command: u8,
+    compressed: bool,
+    length: usize,
+    data: Vec<u8>,
+    checksum: u16,
+    received_checksum: u16,
+
+    image: Vec<u8>,
+    status: u8,
+    busy: u8,
+    queue: PrintQueue,
+}
+
+impl Printer {
+
+    pub fn new() -> Self {
+        return Printer {
+            state: PacketState::Magic(0),
+            command: 0,
+            compressed: false,
+            length: 0,
+            data: Vec::new(),
+            checksum: 0,
+            received_checksum: 0,
+            image: Vec::new(),
+            status: 0,
+            busy: 0,
+            queue: PrintQueue::default(),
+        }
+    }
+
+    // Handle to the jobs printed from now on, keep it before plugging the printer in
+    pub fn queue(&self) -> PrintQueue {
+        return self.queue.clone();
+    }
+
+    fn execute_packet(&mut self) {
+        if self.checksum != self.received_checksum {
+            self.status |= STATUS_CHECKSUM_ERROR;
+            return;
+        }
+        self.status &= !STATUS_CHECKSUM_ERROR;
+
+        match self.command {
+            COMMAND_INIT => {
+                self.image.clear();
+                self.status = 0;
+                self.busy = 0;
+            },
+            COMMAND_DATA => {
+                let data = if self.compressed { decompress(&self.data) } else { std::mem::take(&mut self.data) };
+                let space = MAX_IMAGE_DATA - self.image.len();
+                self.image.extend_from_slice(&data[.. data.len().min(space)]);
+                if !self.image.is_empty() {
+                    self.status |= STATUS_UNPROCESSED;
+                }
+                if self.image.len() == MAX_IMAGE_DATA {
+                    self.status |= STATUS_FULL;
+                }
+            },
+            COMMAND_PRINT => {
+                if self.data.len() < 4 {
+                    return;
+                }
+                self.print(self.data[0], self.data[1], self.data[2], self.data[3]);
+            },
+            COMMAND_STATUS if self.busy > 0 => {
+                self.busy -= 1;
+                if self.busy == 0 {
+                    self.status &= !STATUS_BUSY;
+                }
+            },
+            _ => {},
+        }
+    }
+
+    fn print(&mut self, sheets: u8, margins: u8, palette: u8, exposure: u8) {
+        // A palette of 0 is treated as the default
+        let palette = if palette == 0 { 0xE4 } else { palette };
+        let rows = self.image.len() / BYTES_PER_ROW;
+        // Nothing to print, a job without a single row can't be saved as an image
+        if rows == 0 {
+            return;
+        }
+        let height = rows * 8;
+        let mut pixels = vec![0; PRINT_WIDTH * height * 4];
+
+        for (tile_index, tile) in self.image[.. rows * BYTES_PER_ROW].chunks(16).enumerate() {
+            let tile_x = (tile_index % TILES_PER_ROW) * 8;
+            let tile_y = (tile_index / TILES_PER_ROW) * 8;
+            for y in 0 .. 8 {
+                let low = tile[y * 2];
+                let high = tile[y * 2 + 1];
+                for x in 0 .. 8 {
+                    let bit = 7 - x;
+                    let color = (((high >> bit) & 0x01) << 1) | ((low >> bit) & 0x01);
+                    let shade = SHADES[((palette >> (color * 2)) & 0x03) as usize];
+                    let i = ((tile_y + y) * PRINT_WIDTH + tile_x + x) * 4;
+                    pixels[i .. i + 4].copy_from_slice(&[shade, shade, shade, 0xFF]);
+                }
+            }
+        }
+
+        self.queue.jobs.borrow_mut().push_back(PrintJob {
+            width: PRINT_WIDTH,
+            height,
+            pixels,
+            sheets,
+            margin_before: margins >> 4,
+            margin_after: margins & 0x0F,
+            palette,
+            exposure: exposure & 0x7F,
+        });
+
+        self.image.clear();
+        self.status &= !(STATUS_UNPROCESSED | STATUS_FULL);
+        self.status |= STATUS_BUSY;
+        self.busy = BUSY_INQUIRIES;
+    }
+}
+
+impl Default for Printer {
+    fn default() -> Self {
+        return Printer::new();
+    }
+}
+
+impl SerialDevice for Printer {
+
+    fn transfer(&mut self, byte: u8) -> u8 {
+        let mut reply = 0x00;
+        self.state = match self.state {
+            PacketState::Magic(index) => {
+                if byte == MAGIC[index] {
+                    if index + 1 == MAGIC.len() { PacketState::Command } else { PacketState::Magic(index + 1) }
+                } else if byte == MAGIC[0] {
+                    PacketState::Magic(1)
+                } else {
+                    PacketState::Magic(0)
+                }
+            },
+            PacketState::Command => {
+                self.command = byte;
+                self.checksum = byte as u16;
+                PacketState::Compression
+            },
+            PacketState::Compression => {
+                self.compressed = byte & 0x01 == 0x01;
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                PacketState::LengthLow
+            },
+            PacketState::LengthLow => {
+                self.length = byte as usize;
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                PacketState::LengthHigh
+            },
+            PacketState::LengthHigh => {
+                self.length |= (byte as usize) << 8;
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                self.data.clear();
+                if self.length == 0 { PacketState::ChecksumLow } else { PacketState::Data }
+            },
+            PacketState::Data => {
+                self.data.push(byte);
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                if self.data.len() == self.length { PacketState::ChecksumLow } else { PacketState::Data }
+            },
+            PacketState::ChecksumLow => {
+                self.received_checksum = byte as u16;
+                PacketState::ChecksumHigh
+            },
+            PacketState::ChecksumHigh => {
+                self.received_checksum |= (byte as u16) << 8;
+                self.execute_packet();
+                PacketState::DeviceId
+            },
+            PacketState::DeviceId => {
+                reply = DEVICE_ID;
+                PacketState::Status
+            },
+            PacketState::Status => {
+                reply = self.status;
+                PacketState::Magic(0)
+            },
+        };
+        return reply;
+    }
+}
+
+// Runs of bytes, a control byte with bit 7 set repeats the next byte (control & 0x7F) + 2 times,
+// otherwise it is followed by control + 1 literal bytes
+fn decompress(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::new();
+    let mut i = 0;
+    while i < data.len() {
+        let control = data[i];
+        i += 1;
+        if control & 0x80 == 0x80 {
+            let count = (control & 0x7F) as usize + 2;
+            if let Some(byte) = data.get(i) {
+                output.resize(output.len() + count, *byte);
+            }
+            i += 1;
+        } else {
+            let end = (i + control as usize + 1).min(data.len());
+            output.extend_from_slice(&data[i .. end]);
+            i = end;
+        }
+    }
+    return output;
+}
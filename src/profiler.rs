@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+
+// Flags of a byte in the coverage map, like the code/data logs of other emulators
+pub const COVERAGE_CODE: u8 = 0x01;
+pub const COVERAGE_DATA: u8 = 0x02;
+
+// Instructions and cycles run at an address in a bank
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Hotspot {
+    pub bank: usize,
+    pub address: u16,
+    pub instructions: u64,
+    // Cpu speed clock cycles, interrupt dispatches and halted time are not counted
+    pub cycles: u64,
+}
+
+pub struct Profiler {
+    counts: HashMap<(usize, u16), (u64, u64)>,
+    // A byte of flags per rom byte
+    coverage: Vec<u8>,
+    // Rom offsets read by the current instruction, sorted into code and data once it ran
+    reads: Vec<usize>,
+}
+
+impl Profiler {
+
+    pub fn new(rom_size: usize) -> Self {
+        return Profiler {
+            counts: HashMap::new(),
+            coverage: vec![0; rom_size],
+            reads: Vec::new(),
+        }
+    }
+
+    pub(crate) fn record_read(&mut self, offset: usize) {
+        self.reads.push(offset);
+    }
+
+    // Code are the rom offsets of the bytes of the instruction, empty when it runs outside of rom
+    pub(crate) fn record_instruction(&mut self, bank: usize, address: u16, cycles: u32, code: &[usize]) {
+        let count = self.counts.entry((bank, address)).or_insert((0, 0));
+        count.0 += 1;
+        count.1 += cycles as u64;
+
+        for offset in code {
+            mark(&mut self.coverage, *offset, COVERAGE_CODE);
+        }
+        for offset in self.reads.drain(..) {
+            if !code.contains(&offset) {
+                mark(&mut self.coverage, offset, COVERAGE_DATA);
+            }
+        }
+    }
+
+    // Every address that ran, the most cycles first
+    pub fn hotspots(&self) -> Vec<Hotspot> {
+        let mut hotspots: Vec<Hotspot> = self.counts.iter()
+            .map(|(&(bank, address), &(instructions, cycles))| Hotspot { bank, address, instructions, cycles })
+            .collect();
+        hotspots.sort_by(|a, b| b.cycles.cmp(&a.cycles).then((a.bank, a.address).cmp(&(b.bank, b.address))));
+        return hotspots;
+    }
+
+    pub fn total_cycles(&self) -> u64 {
+        return self.counts.values().map(|count| count.1).sum();
+    }
+
+    // COVERAGE_CODE and COVERAGE_DATA flags of each rom byte
+    pub fn coverage(&self) -> &[u8] {
+        return &self.coverage;
+    }
+
+    pub fn clear(&mut self) {
+        self.counts.clear();
+        self.coverage.iter_mut().for_each(|flags| *flags = 0);
+        self.reads.clear();
+    }
+}
+
+// The rom may have been replaced since profiling started
+fn mark(coverage: &mut [u8], offset: usize, flag: u8) {
+    if let Some(flags) = coverage.get_mut(offset) {
+        *flags |= flag;
+    }
+}
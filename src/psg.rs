@@ -1,45 +1,951 @@
-use crate::mmu::Mmu;
-use wasm_bindgen::prelude::*;
+use crate::console::GameboyType;
+use crate::error::EmulatorError;
+use crate::state::{StateWriter, StateReader};
 
-static MAX_VOLUME: u16 = 8000;
-static TWO_DECI: f32 = 0.8;
-static VOLUME_LEVELS: usize = 0xF;
+// https://gbdev.io/pandocs/#sound-controller
+// https://gbdev.gg8.se/wiki/articles/Gameboy_sound_hardware
+
+pub const SAMPLE_RATE: u32 = 44100;
+pub const CLOCK_RATE: u32 = 4194304;
+
+// Stereo samples are interleaved, so one second of audio is twice the sample rate.
+const MAX_BUFFERED_SAMPLES: usize = (SAMPLE_RATE * 2) as usize;
+
+// Charge factor of the output capacitor at 44.1kHz, removes the DC offset of the DACs.
+const CAPACITOR_CHARGE_FACTOR: f32 = 0.996;
+
+const DUTY_TABLE: [[u8; 8]; 4] = [
+    [0, 0, 0, 0, 0, 0, 0, 1], // 12.5%
+    [1, 0, 0, 0, 0, 0, 0, 1], // 25%
+    [1, 0, 0, 0, 0, 1, 1, 1], // 50%
+    [0, 1, 1, 1, 1, 1, 1, 0], // 75%
+];
+
+const NOISE_DIVISORS: [u32; 8] = [8, 16, 32, 48, 64, 80, 96, 112];
+
+// Bits that always read back as 1 for FF10 - FF2F, write only and unused bits included.
+const READ_MASKS: [u8; 0x20] = [
+    0x80, 0x3F, 0x00, 0xFF, 0xBF, // NR10 - NR14
+    0xFF, 0x3F, 0x00, 0xFF, 0xBF, // ---- - NR24
+    0x7F, 0xFF, 0x9F, 0xFF, 0xBF, // NR30 - NR34
+    0xFF, 0xFF, 0x00, 0x00, 0xBF, // ---- - NR44
+    0x00, 0x00, 0x70,             // NR50 - NR52
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Envelope {
+    initial_volume: u8,
+    increase: bool,
+    period: u8,
+    volume: u8,
+    timer: u8,
+}
+
+impl Envelope {
+
+    fn new() -> Self {
+        return Envelope {
+            initial_volume: 0,
+            increase: false,
+            period: 0,
+            volume: 0,
+            timer: 0,
+        }
+    }
+
+    // NRx2
+    // Bit 7-4 - Initial Volume of envelope (0-0Fh) (0=No Sound)
+    // Bit 3   - Envelope Direction (0=Decrease, 1=Increase)
+    // Bit 2-0 - Number of envelope sweep (n: 0-7)
+    fn write(&mut self, value: u8) {
+        self.initial_volume = value >> 4;
+        self.increase = value & 0x08 == 0x08;
+        self.period = value & 0x07;
+    }
+
+    fn trigger(&mut self) {
+        self.timer = self.period;
+        self.volume = self.initial_volume;
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.initial_volume);
+        writer.write_bool(self.increase);
+        writer.write_u8(self.period);
+        writer.write_u8(self.volume);
+        writer.write_u8(self.timer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), EmulatorError> {
+        self.initial_volume = reader.read_u8()? & 0x0F;
+        self.increase = reader.read_bool()?;
+        self.period = reader.read_u8()? & 0x07;
+        self.volume = reader.read_u8()? & 0x0F;
+        self.timer = reader.read_u8()?;
+        return Ok(());
+    }
+
+    fn clock(&mut self) {
+        if self.period == 0 {
+            return;
+        }
+
+        if self.timer > 0 {
+            self.timer -= 1;
+        }
+
+        if self.timer == 0 {
+            self.timer = self.period;
+            if self.increase && self.volume < 0xF {
+                self.volume += 1;
+            } else if !self.increase && self.volume > 0 {
+                self.volume -= 1;
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct LengthCounter {
+    max: u16,
+    counter: u16,
+    enabled: bool,
+}
+
+impl LengthCounter {
+
+    fn new(max: u16) -> Self {
+        return LengthCounter {
+            max,
+            counter: 0,
+            enabled: false,
+        }
+    }
+
+    fn load(&mut self, value: u8) {
+        self.counter = self.max - (value as u16 & (self.max - 1));
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u16(self.counter);
+        writer.write_bool(self.enabled);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), EmulatorError> {
+        self.counter = reader.read_u16()?;
+        self.enabled = reader.read_bool()?;
+        if self.counter > self.max {
+            return Err(reader.invalid("length counter"));
+        }
+        return Ok(());
+    }
+
+    // Returns true when the counter reaches zero and the channel should be turned off
+    fn clock(&mut self) -> bool {
+        if self.enabled && self.counter > 0 {
+            self.counter -= 1;
+            return self.counter == 0;
+        }
+        return false;
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct SquareChannel {
+    enabled: bool,
+    dac_enabled: bool,
+    duty: u8,
+    duty_position: usize,
+    frequency: u16,
+    timer: u32,
+    length: LengthCounter,
+    envelope: Envelope,
+
+    // Only used by channel 1
+    sweep_period: u8,
+    sweep_negate: bool,
+    sweep_shift: u8,
+    sweep_timer: u8,
+    sweep_enabled: bool,
+    sweep_shadow: u16,
+    sweep_negate_used: bool,
+}
+
+impl SquareChannel {
+
+    fn new() -> Self {
+        return SquareChannel {
+            enabled: false,
+            dac_enabled: false,
+            duty: 0,
+            duty_position: 0,
+            frequency: 0,
+            timer: 0,
+            length: LengthCounter::new(64),
+            envelope: Envelope::new(),
+            sweep_period: 0,
+            sweep_negate: false,
+            sweep_shift: 0,
+            sweep_timer: 0,
+            sweep_enabled: false,
+            sweep_shadow: 0,
+            sweep_negate_used: false,
+        }
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.enabled);
+        writer.write_bool(self.dac_enabled);
+        writer.write_u8(self.duty);
+        writer.write_u8(self.duty_position as u8);
+        writer.write_u16(self.frequency);
+        writer.write_u32(self.timer);
+        self.length.save_state(writer);
+        self.envelope.save_state(writer);
+        writer.write_u8(self.sweep_period);
+        writer.write_bool(self.sweep_negate);
+        writer.write_u8(self.sweep_shift);
+        writer.write_u8(self.sweep_timer);
+        writer.write_bool(self.sweep_enabled);
+        writer.write_u16(self.sweep_shadow);
+        writer.write_bool(self.sweep_negate_used);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), EmulatorError> {
+        self.enabled = reader.read_bool()?;
+        self.dac_enabled = reader.read_bool()?;
+        self.duty = reader.read_u8()? & 0x03;
+        self.duty_position = (reader.read_u8()? & 0x07) as usize;
+        self.frequency = reader.read_u16()? & 0x7FF;
+        self.timer = reader.read_u32()?;
+        self.length.load_state(reader)?;
+        self.envelope.load_state(reader)?;
+        self.sweep_period = reader.read_u8()? & 0x07;
+        self.sweep_negate = reader.read_bool()?;
+        self.sweep_shift = reader.read_u8()? & 0x07;
+        self.sweep_timer = reader.read_u8()?;
+        self.sweep_enabled = reader.read_bool()?;
+        self.sweep_shadow = reader.read_u16()?;
+        self.sweep_negate_used = reader.read_bool()?;
+        return Ok(());
+    }
+
+    fn execute_tick(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+        }
+        if self.timer == 0 {
+            self.timer = (2048 - self.frequency as u32) * 4;
+            self.duty_position = (self.duty_position + 1) & 0x7;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if !self.enabled || !self.dac_enabled { return 0; }
+        return DUTY_TABLE[self.duty as usize][self.duty_position] * self.envelope.volume;
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.dac_enabled;
+        self.timer = (2048 - self.frequency as u32) * 4;
+        self.envelope.trigger();
+
+        self.sweep_shadow = self.frequency;
+        self.sweep_timer = if self.sweep_period == 0 { 8 } else { self.sweep_period };
+        self.sweep_enabled = self.sweep_period != 0 || self.sweep_shift != 0;
+        self.sweep_negate_used = false;
+        if self.sweep_shift != 0 {
+            self.calculate_sweep();
+        }
+    }
+
+    // NR10
+    // Bit 6-4 - Sweep Time
+    // Bit 3   - Sweep Increase/Decrease (0: Addition, 1: Subtraction)
+    // Bit 2-0 - Number of sweep shift (n: 0-7)
+    fn write_sweep(&mut self, value: u8) {
+        self.sweep_period = (value >> 4) & 0x07;
+        self.sweep_negate = value & 0x08 == 0x08;
+        self.sweep_shift = value & 0x07;
+
+        // Clearing the negate bit after a subtraction was used disables the channel
+        if !self.sweep_negate && self.sweep_negate_used {
+            self.enabled = false;
+        }
+    }
+
+    fn calculate_sweep(&mut self) -> u16 {
+        let delta = self.sweep_shadow >> self.sweep_shift;
+        let frequency = if self.sweep_negate {
+            self.sweep_negate_used = true;
+            self.sweep_shadow.wrapping_sub(delta)
+        } else {
+            self.sweep_shadow + delta
+        };
+
+        if frequency > 2047 {
+            self.enabled = false;
+        }
+        return frequency;
+    }
+
+    fn clock_sweep(&mut self) {
+        if self.sweep_timer > 0 {
+            self.sweep_timer -= 1;
+        }
+        if self.sweep_timer != 0 {
+            return;
+        }
+
+        self.sweep_timer = if self.sweep_period == 0 { 8 } else { self.sweep_period };
+        if !self.sweep_enabled || self.sweep_period == 0 {
+            return;
+        }
+
+        let frequency = self.calculate_sweep();
+        if frequency <= 2047 && self.sweep_shift != 0 {
+            self.sweep_shadow = frequency;
+            self.frequency = frequency;
+            self.calculate_sweep();
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct WaveChannel {
+    enabled: bool,
+    dac_enabled: bool,
+    volume_shift: u8,
+    frequency: u16,
+    timer: u32,
+    position: usize,
+    sample_buffer: u8,
+    length: LengthCounter,
+    ram: [u8; 0x10],
+}
+
+impl WaveChannel {
+
+    fn new() -> Self {
+        return WaveChannel {
+            enabled: false,
+            dac_enabled: false,
+            volume_shift: 0,
+            frequency: 0,
+            timer: 0,
+            position: 0,
+            sample_buffer: 0,
+            length: LengthCounter::new(256),
+            ram: [0; 0x10],
+        }
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.enabled);
+        writer.write_bool(self.dac_enabled);
+        writer.write_u8(self.volume_shift);
+        writer.write_u16(self.frequency);
+        writer.write_u32(self.timer);
+        writer.write_u8(self.position as u8);
+        writer.write_u8(self.sample_buffer);
+        self.length.save_state(writer);
+        writer.write_bytes(&self.ram);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), EmulatorError> {
+        self.enabled = reader.read_bool()?;
+        self.dac_enabled = reader.read_bool()?;
+        self.volume_shift = reader.read_u8()? & 0x03;
+        self.frequency = reader.read_u16()? & 0x7FF;
+        self.timer = reader.read_u32()?;
+        self.position = (reader.read_u8()? & 0x1F) as usize;
+        self.sample_buffer = reader.read_u8()? & 0x0F;
+        self.length.load_state(reader)?;
+        reader.read_bytes_into(&mut self.ram)?;
+        return Ok(());
+    }
+
+    fn execute_tick(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+        }
+        if self.timer == 0 {
+            self.timer = (2048 - self.frequency as u32) * 2;
+            self.position = (self.position + 1) & 0x1F;
+
+            let byte = self.ram[self.position / 2];
+            self.sample_buffer = if self.position & 0x1 == 0 { byte >> 4 } else { byte & 0x0F };
+        }
+    }
+
+    // NR32
+    // Bit 6-5 - Select output level (0: Mute, 1: 100%, 2: 50%, 3: 25%)
+    fn output(&self) -> u8 {
+        if !self.enabled || !self.dac_enabled { return 0; }
+        return match self.volume_shift {
+            0 => 0,
+            n => self.sample_buffer >> (n - 1),
+        };
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.dac_enabled;
+        self.timer = (2048 - self.frequency as u32) * 2 + 6;
+        self.position = 0;
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct NoiseChannel {
+    enabled: bool,
+    dac_enabled: bool,
+    clock_shift: u8,
+    width_mode: bool,
+    divisor_code: u8,
+    timer: u32,
+    lfsr: u16,
+    length: LengthCounter,
+    envelope: Envelope,
+}
+
+impl NoiseChannel {
+
+    fn new() -> Self {
+        return NoiseChannel {
+            enabled: false,
+            dac_enabled: false,
+            clock_shift: 0,
+            width_mode: false,
+            divisor_code: 0,
+            timer: 0,
+            lfsr: 0x7FFF,
+            length: LengthCounter::new(64),
+            envelope: Envelope::new(),
+        }
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.enabled);
+        writer.write_bool(self.dac_enabled);
+        writer.write_u8(self.clock_shift);
+        writer.write_bool(self.width_mode);
+        writer.write_u8(self.divisor_code);
+        writer.write_u32(self.timer);
+        writer.write_u16(self.lfsr);
+        self.length.save_state(writer);
+        self.envelope.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), EmulatorError> {
+        self.enabled = reader.read_bool()?;
+        self.dac_enabled = reader.read_bool()?;
+        self.clock_shift = reader.read_u8()? & 0x0F;
+        self.width_mode = reader.read_bool()?;
+        self.divisor_code = reader.read_u8()? & 0x07;
+        self.timer = reader.read_u32()?;
+        self.lfsr = reader.read_u16()? & 0x7FFF;
+        self.length.load_state(reader)?;
+        self.envelope.load_state(reader)?;
+        return Ok(());
+    }
+
+    fn period(&self) -> u32 {
+        return NOISE_DIVISORS[self.divisor_code as usize] << self.clock_shift;
+    }
+
+    fn execute_tick(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+        }
+        if self.timer == 0 {
+            self.timer = self.period();
+
+            // The low two bits are xored, shifted right and the result is put in the high bit.
+            // In 7 bit mode the result is also put into bit 6.
+            let xor = (self.lfsr & 0x1) ^ ((self.lfsr >> 1) & 0x1);
+            self.lfsr = (self.lfsr >> 1) | (xor << 14);
+            if self.width_mode {
+                self.lfsr = (self.lfsr & !0x40) | (xor << 6);
+            }
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if !self.enabled || !self.dac_enabled { return 0; }
+        return if self.lfsr & 0x1 == 0 { self.envelope.volume } else { 0 };
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.dac_enabled;
+        self.timer = self.period();
+        self.lfsr = 0x7FFF;
+        self.envelope.trigger();
+    }
+}
 
-//http://www.codeslinger.co.uk/pages/projects/mastersystem/sound.html
-#[wasm_bindgen]
 pub struct Psg {
-    volumes: [f32; 0xF],
-    ch0: u8, // Tone and sweep
-    ch1: u8, // Tone
-    ch2: u8, // Wave
-    ch3: u8, // Noise
+    ch1: SquareChannel, // Tone and sweep
+    ch2: SquareChannel, // Tone
+    ch3: WaveChannel,   // Wave
+    ch4: NoiseChannel,  // Noise
+
+    registers: [u8; 0x20],
+    power: bool,
+
+    // 512Hz frame sequencer, clocked from the falling edge of DIV
+    frame_sequencer_step: u8,
+
+    sample_counter: u32,
+    capacitor_left: f32,
+    capacitor_right: f32,
+    samples: Vec<f32>,
+    model: GameboyType,
 }
 
 impl Psg {
+
     pub fn new() -> Self {
         return Psg {
-            ch0: 0xFF,
-            ch1: 0x00,
-            ch2: 0x00,
-            ch3: 0x00,
-            volumes : {
-                let mut vol_table : [f32; 0xF] = [0.0; 0xF];
-                let mut curvol: f32 = MAX_VOLUME as f32;
-                for i in 0..VOLUME_LEVELS {
-                    vol_table[i] = curvol;
-                    curvol *= TWO_DECI;
+            ch1: SquareChannel::new(),
+            ch2: SquareChannel::new(),
+            ch3: WaveChannel::new(),
+            ch4: NoiseChannel::new(),
+            registers: [0; 0x20],
+            power: true,
+            frame_sequencer_step: 0,
+            sample_counter: 0,
+            capacitor_left: 0.0,
+            capacitor_right: 0.0,
+            samples: Vec::new(),
+            model: GameboyType::CLASSIC,
+        }
+    }
+
+    pub fn reset(&mut self, model: GameboyType) {
+        self.ch1 = SquareChannel::new();
+        self.ch2 = SquareChannel::new();
+        self.ch3 = WaveChannel::new();
+        self.ch4 = NoiseChannel::new();
+        self.registers = [0; 0x20];
+        self.power = true;
+        self.frame_sequencer_step = 0;
+        self.sample_counter = 0;
+        self.capacitor_left = 0.0;
+        self.capacitor_right = 0.0;
+        self.samples.clear();
+        self.model = model;
+
+        // Register values left behind by the boot rom
+        // https://gbdev.io/pandocs/#power-up-sequence
+        self.write_byte(0xFF10, 0x80);
+        self.write_byte(0xFF11, 0xBF);
+        self.write_byte(0xFF12, 0xF3);
+        self.write_byte(0xFF16, 0x3F);
+        self.write_byte(0xFF17, 0x00);
+        self.write_byte(0xFF1A, 0x7F);
+        self.write_byte(0xFF1B, 0xFF);
+        self.write_byte(0xFF1C, 0x9F);
+        self.write_byte(0xFF20, 0xFF);
+        self.write_byte(0xFF21, 0x00);
+        self.write_byte(0xFF22, 0x00);
+        self.write_byte(0xFF24, 0x77);
+        self.write_byte(0xFF25, 0xF3);
+
+        // The boot sound has finished playing, but channel 1 is still reported as on
+        self.ch1.enabled = true;
+    }
+
+    // Buffered samples are not part of the state, they belong to whoever is playing them
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        self.ch1.save_state(writer);
+        self.ch2.save_state(writer);
+        self.ch3.save_state(writer);
+        self.ch4.save_state(writer);
+        writer.write_bytes(&self.registers);
+        writer.write_bool(self.power);
+        writer.write_u8(self.frame_sequencer_step);
+        writer.write_u32(self.sample_counter);
+        writer.write_f32(self.capacitor_left);
+        writer.write_f32(self.capacitor_right);
+        writer.write_model(self.model);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<(), EmulatorError> {
+        self.ch1.load_state(reader)?;
+        self.ch2.load_state(reader)?;
+        self.ch3.load_state(reader)?;
+        self.ch4.load_state(reader)?;
+        reader.read_bytes_into(&mut self.registers)?;
+        self.power = reader.read_bool()?;
+        self.frame_sequencer_step = reader.read_u8()? & 0x07;
+        self.sample_counter = reader.read_u32()?;
+        self.capacitor_left = reader.read_f32()?;
+        self.capacitor_right = reader.read_f32()?;
+        self.model = reader.read_model()?;
+        self.samples.clear();
+        if self.sample_counter >= CLOCK_RATE {
+            return Err(reader.invalid("sample counter"));
+        }
+        return Ok(());
+    }
+
+    // While the wave channel is playing the color model only reaches the byte being played, the
+    // classic model reads $FF and drops writes. It gets through on the cycle the channel reads the
+    // byte itself, which is not emulated.
+    fn wave_ram_index(&self, address: u16) -> Option<usize> {
+        if !self.ch3.enabled {
+            return Some((address - 0xFF30) as usize);
+        }
+        return match self.model {
+            GameboyType::COLOR => Some(self.ch3.position / 2),
+            GameboyType::CLASSIC => None,
+        };
+    }
+
+    pub fn read_byte(&self, address: u16) -> u8 {
+        match address {
+            0xFF26 => {
+                (if self.power { 0x80 } else { 0 }) |
+                    (if self.ch1.enabled { 0x01 } else { 0 }) |
+                    (if self.ch2.enabled { 0x02 } else { 0 }) |
+                    (if self.ch3.enabled { 0x04 } else { 0 }) |
+                    (if self.ch4.enabled { 0x08 } else { 0 }) |
+                    0x70
+            },
+            0xFF10 ..= 0xFF2F => {
+                let index = (address - 0xFF10) as usize;
+                self.registers[index] | READ_MASKS[index]
+            },
+            0xFF30 ..= 0xFF3F => match self.wave_ram_index(address) {
+                Some(index) => self.ch3.ram[index],
+                None => 0xFF,
+            },
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write_byte(&mut self, address: u16, value: u8) {
+        if let 0xFF30 ..= 0xFF3F = address {
+            if let Some(index) = self.wave_ram_index(address) {
+                self.ch3.ram[index] = value;
+            }
+            return;
+        }
+
+        if address == 0xFF26 {
+            self.write_power(value);
+            return;
+        }
+
+        if !self.power {
+            // On the classic model the length counters can still be written while powered off
+            if self.model == GameboyType::CLASSIC {
+                match address {
+                    0xFF11 => self.ch1.length.load(value & 0x3F),
+                    0xFF16 => self.ch2.length.load(value & 0x3F),
+                    0xFF1B => self.ch3.length.load(value),
+                    0xFF20 => self.ch4.length.load(value & 0x3F),
+                    _ => {},
                 }
-                vol_table
             }
+            return;
+        }
+
+        if let 0xFF10 ..= 0xFF2F = address {
+            self.registers[(address - 0xFF10) as usize] = value;
+        }
+
+        match address {
+            // Channel 1 - Tone & Sweep
+            0xFF10 => self.ch1.write_sweep(value),
+            0xFF11 => {
+                self.ch1.duty = value >> 6;
+                self.ch1.length.load(value & 0x3F);
+            },
+            0xFF12 => {
+                self.ch1.envelope.write(value);
+                self.ch1.dac_enabled = value & 0xF8 != 0;
+                if !self.ch1.dac_enabled { self.ch1.enabled = false; }
+            },
+            0xFF13 => self.ch1.frequency = (self.ch1.frequency & 0x700) | value as u16,
+            0xFF14 => {
+                self.ch1.frequency = (self.ch1.frequency & 0xFF) | (((value & 0x07) as u16) << 8);
+                let (enabled, trigger) = self.write_length_control(1, value);
+                self.ch1.enabled = enabled;
+                if trigger { self.ch1.trigger(); }
+            },
+
+            // Channel 2 - Tone
+            0xFF16 => {
+                self.ch2.duty = value >> 6;
+                self.ch2.length.load(value & 0x3F);
+            },
+            0xFF17 => {
+                self.ch2.envelope.write(value);
+                self.ch2.dac_enabled = value & 0xF8 != 0;
+                if !self.ch2.dac_enabled { self.ch2.enabled = false; }
+            },
+            0xFF18 => self.ch2.frequency = (self.ch2.frequency & 0x700) | value as u16,
+            0xFF19 => {
+                self.ch2.frequency = (self.ch2.frequency & 0xFF) | (((value & 0x07) as u16) << 8);
+                let (enabled, trigger) = self.write_length_control(2, value);
+                self.ch2.enabled = enabled;
+                if trigger { self.ch2.trigger(); }
+            },
+
+            // Channel 3 - Wave Output
+            0xFF1A => {
+                self.ch3.dac_enabled = value & 0x80 == 0x80;
+                if !self.ch3.dac_enabled { self.ch3.enabled = false; }
+            },
+            0xFF1B => self.ch3.length.load(value),
+            0xFF1C => self.ch3.volume_shift = (value >> 5) & 0x03,
+            0xFF1D => self.ch3.frequency = (self.ch3.frequency & 0x700) | value as u16,
+            0xFF1E => {
+                self.ch3.frequency = (self.ch3.frequency & 0xFF) | (((value & 0x07) as u16) << 8);
+                let (enabled, trigger) = self.write_length_control(3, value);
+                self.ch3.enabled = enabled;
+                if trigger { self.ch3.trigger(); }
+            },
+
+            // Channel 4 - Noise
+            0xFF20 => self.ch4.length.load(value & 0x3F),
+            0xFF21 => {
+                self.ch4.envelope.write(value);
+                self.ch4.dac_enabled = value & 0xF8 != 0;
+                if !self.ch4.dac_enabled { self.ch4.enabled = false; }
+            },
+            0xFF22 => {
+                self.ch4.clock_shift = value >> 4;
+                self.ch4.width_mode = value & 0x08 == 0x08;
+                self.ch4.divisor_code = value & 0x07;
+            },
+            0xFF23 => {
+                let (enabled, trigger) = self.write_length_control(4, value);
+                self.ch4.enabled = enabled;
+                if trigger { self.ch4.trigger(); }
+            },
+
+            // NR50 and NR51 are read straight from the registers when mixing
+            _ => {},
         }
     }
 
+    // NR52
+    // Bit 7 - All sound on/off (0: stop all sound circuits) (Read/Write)
+    // Bit 3-0 - Sound 4..1 ON flag (Read Only)
+    fn write_power(&mut self, value: u8) {
+        let power = value & 0x80 == 0x80;
+
+        if self.power && !power {
+            // Powering off clears every register and stops all channels, wave ram is untouched.
+            // Length counters survive on the classic model.
+            let wave_ram = self.ch3.ram;
+            let lengths = [self.ch1.length, self.ch2.length, self.ch3.length, self.ch4.length];
 
+            self.ch1 = SquareChannel::new();
+            self.ch2 = SquareChannel::new();
+            self.ch3 = WaveChannel::new();
+            self.ch4 = NoiseChannel::new();
+            self.ch3.ram = wave_ram;
+            self.registers = [0; 0x20];
+
+            if self.model == GameboyType::CLASSIC {
+                self.ch1.length.counter = lengths[0].counter;
+                self.ch2.length.counter = lengths[1].counter;
+                self.ch3.length.counter = lengths[2].counter;
+                self.ch4.length.counter = lengths[3].counter;
+            }
+        } else if !self.power && power {
+            self.frame_sequencer_step = 0;
+        }
+
+        self.power = power;
+    }
 
-    pub fn output() -> u16 {
-        //calculate volume
-        0
-   }
+    // NRx4
+    // Bit 7   - Initial (1=Restart Sound)
+    // Bit 6   - Counter/consecutive selection (1=Stop output when length in NRx1 expires)
+    // Returns the new enabled state of the channel and whether it should be triggered.
+    fn write_length_control(&mut self, channel: u8, value: u8) -> (bool, bool) {
+        // The length counter is clocked on even steps, if the next step won't clock it we are
+        // in the first half of the length period.
+        let first_half = self.frame_sequencer_step & 0x1 == 1;
+        let trigger = value & 0x80 == 0x80;
+        let length_enable = value & 0x40 == 0x40;
 
+        let (length, mut enabled) = match channel {
+            1 => (&mut self.ch1.length, self.ch1.enabled),
+            2 => (&mut self.ch2.length, self.ch2.enabled),
+            3 => (&mut self.ch3.length, self.ch3.enabled),
+            _ => (&mut self.ch4.length, self.ch4.enabled),
+        };
 
+        // Enabling the length counter in the first half of the period gives it an extra clock
+        let was_enabled = length.enabled;
+        length.enabled = length_enable;
+        if !was_enabled && length_enable && first_half && length.counter > 0 {
+            length.counter -= 1;
+            if length.counter == 0 && !trigger {
+                enabled = false;
+            }
+        }
+
+        if trigger && length.counter == 0 {
+            length.counter = length.max;
+            if length_enable && first_half {
+                length.counter -= 1;
+            }
+        }
+
+        return (enabled, trigger);
+    }
+
+    // Called with the value of DIV before and after the timer was advanced. The frame sequencer
+    // is clocked every time the selected bit of DIV goes from 1 to 0.
+    pub fn execute_div_ticks(&mut self, previous_div: u8, div: u8, div_mask: u8) {
+        let mut value = previous_div;
+        while value != div {
+            let next = value.wrapping_add(1);
+            if value & div_mask != 0 && next & div_mask == 0 {
+                self.clock_frame_sequencer();
+            }
+            value = next;
+        }
+    }
+
+    // Writing DIV resets it, which counts as a falling edge when the selected bit was set.
+    pub fn reset_div(&mut self, previous_div: u8, div_mask: u8) {
+        if previous_div & div_mask != 0 {
+            self.clock_frame_sequencer();
+        }
+    }
+
+    // Step   Length Ctr  Vol Env     Sweep
+    // ---------------------------------------
+    // 0      Clock       -           -
+    // 1      -           -           -
+    // 2      Clock       -           Clock
+    // 3      -           -           -
+    // 4      Clock       -           -
+    // 5      -           -           -
+    // 6      Clock       -           Clock
+    // 7      -           Clock       -
+    fn clock_frame_sequencer(&mut self) {
+        if !self.power {
+            return;
+        }
+
+        let step = self.frame_sequencer_step;
+
+        if step & 0x1 == 0 {
+            if self.ch1.length.clock() { self.ch1.enabled = false; }
+            if self.ch2.length.clock() { self.ch2.enabled = false; }
+            if self.ch3.length.clock() { self.ch3.enabled = false; }
+            if self.ch4.length.clock() { self.ch4.enabled = false; }
+        }
+
+        if step == 2 || step == 6 {
+            self.ch1.clock_sweep();
+        }
+
+        if step == 7 {
+            self.ch1.envelope.clock();
+            self.ch2.envelope.clock();
+            self.ch4.envelope.clock();
+        }
+
+        self.frame_sequencer_step = (step + 1) & 0x7;
+    }
+
+    // Ticks are in normal speed clock cycles, the sound controller is not affected by double speed
+    pub fn execute_ticks(&mut self, ticks: u32) {
+        for _i in 0 .. ticks {
+            self.execute_tick();
+        }
+    }
+
+    pub fn execute_tick(&mut self) {
+        if self.power {
+            self.ch1.execute_tick();
+            self.ch2.execute_tick();
+            self.ch3.execute_tick();
+            self.ch4.execute_tick();
+        }
+
+        self.sample_counter += SAMPLE_RATE;
+        if self.sample_counter >= CLOCK_RATE {
+            self.sample_counter -= CLOCK_RATE;
+            self.push_sample();
+        }
+    }
+
+    // STOP halts the channels with the rest of the system, the output holds its level and the
+    // high-pass filter settles to silence
+    pub fn execute_stopped_ticks(&mut self, ticks: u32) {
+        for _i in 0 .. ticks {
+            self.sample_counter += SAMPLE_RATE;
+            if self.sample_counter >= CLOCK_RATE {
+                self.sample_counter -= CLOCK_RATE;
+                self.push_sample();
+            }
+        }
+    }
+
+    fn push_sample(&mut self) {
+        let (left, right) = self.mix();
+
+        let left = self.high_pass(left, true);
+        let right = self.high_pass(right, false);
+
+        if self.samples.len() >= MAX_BUFFERED_SAMPLES {
+            // Nobody is draining the buffer, drop the oldest half second
+            self.samples.drain(0 .. MAX_BUFFERED_SAMPLES / 2);
+        }
+        self.samples.push(left);
+        self.samples.push(right);
+    }
+
+    // NR50
+    // Bit 6-4 - SO2 output level (volume)  (0-7)
+    // Bit 2-0 - SO1 output level (volume)  (0-7)
+    // NR51
+    // Bit 7-4 - Output sound 4..1 to SO2 terminal
+    // Bit 3-0 - Output sound 4..1 to SO1 terminal
+    fn mix(&self) -> (f32, f32) {
+        if !self.power {
+            return (0.0, 0.0);
+        }
+
+        let nr50 = self.registers[0x14];
+        let nr51 = self.registers[0x15];
+
+        let outputs = [self.ch1.output(), self.ch2.output(), self.ch3.output(), self.ch4.output()];
+
+        let mut left = 0.0;
+        let mut right = 0.0;
+        for (i, output) in outputs.iter().enumerate() {
+            let amplitude = *output as f32 / 15.0;
+            if nr51 & (0x10 << i) != 0 { left += amplitude; }
+            if nr51 & (0x01 << i) != 0 { right += amplitude; }
+        }
+
+        let left_volume = (((nr50 >> 4) & 0x07) + 1) as f32 / 8.0;
+        let right_volume = ((nr50 & 0x07) + 1) as f32 / 8.0;
+
+        return (left / 4.0 * left_volume, right / 4.0 * right_volume);
+    }
+
+    fn high_pass(&mut self, input: f32, left: bool) -> f32 {
+        let capacitor = if left { &mut self.capacitor_left } else { &mut self.capacitor_right };
+        let output = input - *capacitor;
+        *capacitor = input - output * CAPACITOR_CHARGE_FACTOR;
+        return output;
+    }
+
+    // Interleaved stereo samples (left, right) at SAMPLE_RATE produced since the last call
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        return std::mem::take(&mut self.samples);
+    }
 }
@@ -0,0 +1,225 @@
+use crate::error::EmulatorError;
+use crate::state::{StateWriter, StateReader};
+
+// https://gbdev.io/pandocs/#mbc3
+// The clock runs from its own 32768Hz crystal, so it counts normal speed cycles.
+const CYCLES_PER_SECOND: u32 = 4194304;
+
+// Battery save trailer shared with VBA and BGB, appended after the cartridge ram:
+// 5 x u32 live registers, 5 x u32 latched registers, u64 unix timestamp of the save
+pub const RTC_TRAILER_SIZE: usize = 48;
+// Older saves store the timestamp as a u32
+const RTC_TRAILER_SIZE_32: usize = 44;
+
+const DAY_HIGH_HALT: u8 = 0x40;
+const DAY_HIGH_CARRY: u8 = 0x80;
+
+pub struct Rtc {
+    seconds: u8,
+    minutes: u8,
+    hours: u8,
+    days: u16,
+    halt: bool,
+    carry: bool,
+    latched: [u8; 5],
+    latch_write: u8,
+    cycles: u32,
+}
+
+impl Rtc {
+
+    pub fn new() -> Self {
+        return Rtc {
+            seconds: 0,
+            minutes: 0,
+            hours: 0,
+            days: 0,
+            halt: false,
+            carry: false,
+            latched: [0; 5],
+            latch_write: 0xFF,
+            cycles: 0,
+        }
+    }
+
+    // 08h  RTC S   Seconds   0-59 (0-3Bh)
+    // 09h  RTC M   Minutes   0-59 (0-3Bh)
+    // 0Ah  RTC H   Hours     0-23 (0-17h)
+    // 0Bh  RTC DL  Lower 8 bits of Day Counter (0-FFh)
+    // 0Ch  RTC DH  Upper 1 bit of Day Counter, Carry Bit, Halt Flag
+    //      Bit 0  Most significant bit of Day Counter (Bit 8)
+    //      Bit 6  Halt (0=Active, 1=Stop Timer)
+    //      Bit 7  Day Counter Carry Bit (1=Counter Overflow)
+    fn registers(&self) -> [u8; 5] {
+        return [
+            self.seconds,
+            self.minutes,
+            self.hours,
+            (self.days & 0xFF) as u8,
+            ((self.days >> 8) as u8 & 0x01) |
+                (if self.halt { DAY_HIGH_HALT } else { 0 }) |
+                (if self.carry { DAY_HIGH_CARRY } else { 0 }),
+        ];
+    }
+
+    fn set_registers(&mut self, registers: [u8; 5]) {
+        self.seconds = registers[0] & 0x3F;
+        self.minutes = registers[1] & 0x3F;
+        self.hours = registers[2] & 0x1F;
+        self.days = registers[3] as u16 | ((registers[4] as u16 & 0x01) << 8);
+        self.halt = registers[4] & DAY_HIGH_HALT == DAY_HIGH_HALT;
+        self.carry = registers[4] & DAY_HIGH_CARRY == DAY_HIGH_CARRY;
+    }
+
+    // Reads return the registers as they were at the last latch
+    pub fn read_byte(&self, register: usize) -> u8 {
+        return match register {
+            0x08 ..= 0x0C => self.latched[register - 0x08],
+            _ => 0xFF,
+        };
+    }
+
+    pub fn write_byte(&mut self, register: usize, value: u8) {
+        let mut registers = self.registers();
+        match register {
+            0x08 ..= 0x0C => registers[register - 0x08] = value,
+            _ => return,
+        }
+        // Writing the seconds restarts the current second
+        if register == 0x08 {
+            self.cycles = 0;
+        }
+        self.set_registers(registers);
+    }
+
+    // Writing 00h and then 01h copies the current time into the latched registers
+    pub fn write_latch(&mut self, value: u8) {
+        if self.latch_write == 0x00 && value == 0x01 {
+            self.latched = self.registers();
+        }
+        self.latch_write = value;
+    }
+
+    pub fn execute_ticks(&mut self, ticks: u32) {
+        if self.halt {
+            return;
+        }
+        self.cycles += ticks;
+        while self.cycles >= CYCLES_PER_SECOND {
+            self.cycles -= CYCLES_PER_SECOND;
+            self.tick_second();
+        }
+    }
+
+    // Out of range values keep counting until their bits overflow, without carrying
+    fn tick_second(&mut self) {
+        self.seconds = (self.seconds + 1) & 0x3F;
+        if self.seconds != 60 { return; }
+        self.seconds = 0;
+
+        self.minutes = (self.minutes + 1) & 0x3F;
+        if self.minutes != 60 { return; }
+        self.minutes = 0;
+
+        self.hours = (self.hours + 1) & 0x1F;
+        if self.hours != 24 { return; }
+        self.hours = 0;
+
+        self.days += 1;
+        if self.days > 0x1FF {
+            self.days = 0;
+            self.carry = true;
+        }
+    }
+
+    // Catches up on time that passed while the emulator was not running
+    pub fn advance_seconds(&mut self, mut seconds: u64) {
+        if self.halt {
+            return;
+        }
+
+        while seconds > 0 && (self.seconds >= 60 || self.minutes >= 60 || self.hours >= 24) {
+            self.tick_second();
+            seconds -= 1;
+        }
+
+        let total = self.seconds as u64 + self.minutes as u64 * 60 + self.hours as u64 * 3600 +
+            self.days as u64 * 86400 + seconds;
+        self.seconds = (total % 60) as u8;
+        self.minutes = (total / 60 % 60) as u8;
+        self.hours = (total / 3600 % 24) as u8;
+
+        let days = total / 86400;
+        if days > 0x1FF {
+            self.carry = true;
+        }
+        self.days = (days & 0x1FF) as u16;
+    }
+
+    pub fn export_trailer(&self, now: u64) -> Vec<u8> {
+        let mut trailer = Vec::with_capacity(RTC_TRAILER_SIZE);
+        for value in self.registers().iter().chain(self.latched.iter()) {
+            trailer.extend_from_slice(&(*value as u32).to_le_bytes());
+        }
+        trailer.extend_from_slice(&now.to_le_bytes());
+        return trailer;
+    }
+
+    pub fn is_trailer_size(size: usize) -> bool {
+        return size == RTC_TRAILER_SIZE || size == RTC_TRAILER_SIZE_32;
+    }
+
+    // Restores the clock and advances it by the time passed since the trailer was written
+    pub fn import_trailer(&mut self, trailer: &[u8], now: u64) {
+        let value = |index: usize| trailer[index * 4];
+        let mut registers = [0u8; 5];
+        for (i, register) in registers.iter_mut().enumerate() {
+            *register = value(i);
+        }
+        for (i, latched) in self.latched.iter_mut().enumerate() {
+            *latched = value(5 + i);
+        }
+        self.set_registers(registers);
+        self.cycles = 0;
+
+        let mut timestamp = [0u8; 8];
+        let timestamp_size = trailer.len() - 40;
+        timestamp[.. timestamp_size].copy_from_slice(&trailer[40 ..]);
+        let saved_at = u64::from_le_bytes(timestamp);
+        if now > saved_at {
+            self.advance_seconds(now - saved_at);
+        }
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.registers());
+        writer.write_bytes(&self.latched);
+        writer.write_u8(self.latch_write);
+        writer.write_u32(self.cycles);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<(), EmulatorError> {
+        let mut registers = [0u8; 5];
+        reader.read_bytes_into(&mut registers)?;
+        self.set_registers(registers);
+        reader.read_bytes_into(&mut self.latched)?;
+        self.latch_write = reader.read_u8()?;
+        self.cycles = reader.read_u32()?;
+        if self.cycles >= CYCLES_PER_SECOND {
+            return Err(reader.invalid("rtc cycles"));
+        }
+        return Ok(());
+    }
+}
+
+// Seconds since the unix epoch, used to advance the clock between sessions
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+pub fn unix_time() -> u64 {
+    return (js_sys::Date::now() / 1000.0) as u64;
+}
+
+#[cfg(not(all(feature = "wasm", target_arch = "wasm32")))]
+pub fn unix_time() -> u64 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    return SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+}
@@ -0,0 +1,214 @@
+use crate::console::GameboyType;
+use crate::error::EmulatorError;
+use crate::state::{StateWriter, StateReader};
+
+// https://gbdev.io/pandocs/#serial-data-transfer-link-cable
+pub const INTERRUPT_SERIAL_MASK: u8 = 0x08;
+
+// The internal clock runs at 8192Hz, or 262144Hz with the CGB fast clock bit. It is derived
+// from the cpu clock, so these are counted in cpu speed cycles and double in double speed.
+const CYCLES_PER_BIT: u32 = 512;
+const CYCLES_PER_BIT_FAST: u32 = 16;
+
+// Sent bytes are kept for the host, bounded in case nobody reads them
+const MAX_BUFFERED_OUTPUT: usize = 0x10000;
+
+// Something plugged into the link port of a console that drives the clock itself
+pub trait SerialDevice {
+    // Exchanges a byte at the start of a transfer, returns the byte the device shifts back
+    fn transfer(&mut self, byte: u8) -> u8;
+}
+
+pub struct Serial {
+    // FF01 - SB - Serial transfer data
+    data: u8,
+    // FF02 - SC - Serial Transfer Control
+    // Bit 7 - Transfer Start Flag (0=No transfer is in progress or requested, 1=Transfer in progress, or requested)
+    // Bit 1 - Clock Speed (0=Normal, 1=Fast) ** CGB Mode Only **
+    // Bit 0 - Shift Clock (0=External Clock, 1=Internal Clock)
+    control: u8,
+
+    // Byte being shifted in, one bit per period
+    incoming: u8,
+    bits_remaining: u8,
+    cycles_per_bit: u32,
+    clock: u32,
+
+    device: Option<Box<dyn SerialDevice>>,
+    output: Vec<u8>,
+    model: GameboyType,
+    pub interrupt_flags: u8,
+}
+
+impl Serial {
+
+    pub fn new() -> Self {
+        return Serial {
+            data: 0,
+            control: 0,
+            incoming: 0xFF,
+            bits_remaining: 0,
+            cycles_per_bit: CYCLES_PER_BIT,
+            clock: 0,
+            device: None,
+            output: Vec::new(),
+            model: GameboyType::CLASSIC,
+            interrupt_flags: 0,
+        }
+    }
+
+    // The attached device stays plugged in
+    pub fn reset(&mut self, model: GameboyType) {
+        self.data = 0;
+        self.control = 0;
+        self.incoming = 0xFF;
+        self.bits_remaining = 0;
+        self.cycles_per_bit = CYCLES_PER_BIT;
+        self.clock = 0;
+        self.output.clear();
+        self.model = model;
+        self.interrupt_flags = 0;
+    }
+
+    pub fn attach(&mut self, device: Box<dyn SerialDevice>) -> Option<Box<dyn SerialDevice>> {
+        return self.device.replace(device);
+    }
+
+    pub fn detach(&mut self) -> Option<Box<dyn SerialDevice>> {
+        return self.device.take();
+    }
+
+    pub fn read_byte(&self, address: u16) -> u8 {
+        return match address {
+            0xFF01 => self.data,
+            0xFF02 => {
+                let unused = if self.model == GameboyType::COLOR { 0x7C } else { 0x7E };
+                self.control | unused
+            },
+            _ => 0xFF,
+        };
+    }
+
+    pub fn write_byte(&mut self, address: u16, value: u8) {
+        match address {
+            0xFF01 => self.data = value,
+            0xFF02 => {
+                self.control = value & if self.model == GameboyType::COLOR { 0x83 } else { 0x81 };
+                self.bits_remaining = 0;
+                if self.control & 0x81 == 0x81 {
+                    self.start_internal_transfer();
+                }
+            },
+            _ => {},
+        }
+    }
+
+    // Without a device the line is pulled high and 1s are shifted in
+    fn start_internal_transfer(&mut self) {
+        let outgoing = self.data;
+        self.push_output(outgoing);
+        self.incoming = match &mut self.device {
+            Some(device) => device.transfer(outgoing),
+            None => 0xFF,
+        };
+        self.cycles_per_bit = if self.control & 0x02 == 0x02 { CYCLES_PER_BIT_FAST } else { CYCLES_PER_BIT };
+        self.begin_shift();
+    }
+
+    // Called for the console on the other end of the cable when its peer starts a transfer with
+    // its internal clock. Returns the byte shifted out, or None when no transfer is waiting for
+    // an external clock, in which case the peer reads 1s.
+    pub fn external_transfer(&mut self, byte: u8, fast: bool) -> Option<u8> {
+        if self.control & 0x81 != 0x80 || self.bits_remaining != 0 {
+            return None;
+        }
+        let outgoing = self.data;
+        self.push_output(outgoing);
+        self.incoming = byte;
+        self.cycles_per_bit = if fast { CYCLES_PER_BIT_FAST } else { CYCLES_PER_BIT };
+        self.begin_shift();
+        return Some(outgoing);
+    }
+
+    // Replaces the byte being shifted in by a transfer that started before the byte was known,
+    // bits that were already shifted in are corrected
+    pub fn complete_transfer(&mut self, byte: u8) {
+        if self.bits_remaining == 0 {
+            return;
+        }
+        self.incoming = byte;
+        if self.bits_remaining < 8 {
+            let mask = (1 << (8 - self.bits_remaining)) - 1;
+            self.data = (self.data & !mask) | ((byte >> self.bits_remaining) & mask);
+        }
+    }
+
+    pub fn is_fast(&self) -> bool {
+        return self.cycles_per_bit == CYCLES_PER_BIT_FAST;
+    }
+
+    fn begin_shift(&mut self) {
+        self.bits_remaining = 8;
+        self.clock = 0;
+    }
+
+    fn push_output(&mut self, byte: u8) {
+        if self.output.len() >= MAX_BUFFERED_OUTPUT {
+            self.output.drain(0 .. MAX_BUFFERED_OUTPUT / 2);
+        }
+        self.output.push(byte);
+    }
+
+    pub fn take_output(&mut self) -> Vec<u8> {
+        return std::mem::take(&mut self.output);
+    }
+
+    // Ticks are in cpu speed clock cycles
+    pub fn execute_ticks(&mut self, ticks: u32) {
+        if self.bits_remaining == 0 {
+            return;
+        }
+
+        self.clock += ticks;
+        while self.clock >= self.cycles_per_bit && self.bits_remaining > 0 {
+            self.clock -= self.cycles_per_bit;
+            self.bits_remaining -= 1;
+            self.data = (self.data << 1) | ((self.incoming >> self.bits_remaining) & 0x01);
+
+            if self.bits_remaining == 0 {
+                self.control &= 0x7F;
+                self.interrupt_flags |= INTERRUPT_SERIAL_MASK;
+            }
+        }
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.data);
+        writer.write_u8(self.control);
+        writer.write_u8(self.incoming);
+        writer.write_u8(self.bits_remaining);
+        writer.write_u32(self.cycles_per_bit);
+        writer.write_u32(self.clock);
+        writer.write_model(self.model);
+        writer.write_u8(self.interrupt_flags);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<(), EmulatorError> {
+        self.data = reader.read_u8()?;
+        self.control = reader.read_u8()?;
+        self.incoming = reader.read_u8()?;
+        self.bits_remaining = reader.read_u8()?;
+        self.cycles_per_bit = match reader.read_u32()? {
+            CYCLES_PER_BIT => CYCLES_PER_BIT,
+            CYCLES_PER_BIT_FAST => CYCLES_PER_BIT_FAST,
+            _ => return Err(reader.invalid("serial clock")),
+        };
+        self.clock = reader.read_u32()?;
+        self.model = reader.read_model()?;
+        self.interrupt_flags = reader.read_u8()?;
+        if self.bits_remaining > 8 {
+            return Err(reader.invalid("serial transfer"));
+        }
+        return Ok(());
+    }
+}
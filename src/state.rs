@@ -0,0 +1,286 @@
+use crate::console::GameboyType;
+use crate::error::EmulatorError;
+
+// Save state format, all values are little endian.
+//
+// Offset  Size  Description
+// 0x00    4     Magic "RBST"
+// 0x04    1     Major version, states from another major version are refused
+// 0x05    1     Minor version, bumped when fields are appended to a section
+// 0x06    4     CRC32 of the rom the state was made against
+// 0x0A    ...   Sections until the end of the data
+//
+// Each section:
+// 4       Tag, see the SECTION_* constants
+// 4       Length of the payload
+// n       Payload, the fields written by the component's save_state
+//
+// Sections with unknown tags are skipped and trailing bytes of a payload that a reader does not
+// understand are ignored, so states from a newer minor version can still be loaded. Readers only
+// read fields appended in a later minor version when `StateReader::minor` says they are present.
+//
+// Minor versions:
+// 0       Initial format
+// 1       MBC3 real time clock appended to the cartridge section
+// 2       MBC1 BANK2 register stored in the ram bank instead of merged into the rom bank
+// 3       Serial section
+// 4       STOP, lockup and HALT bug flags appended to the cpu section
+// 5       Boot rom mapping and KEY0 appended to the mmu section, compatibility mode to the ppu
+
+pub const STATE_MAGIC: [u8; 4] = *b"RBST";
+pub const STATE_MAJOR_VERSION: u8 = 1;
+pub const STATE_MINOR_VERSION: u8 = 5;
+
+const HEADER_SIZE: usize = 0x0A;
+
+pub const SECTION_CPU: [u8; 4] = *b"CPU ";
+pub const SECTION_MMU: [u8; 4] = *b"MMU ";
+pub const SECTION_PPU: [u8; 4] = *b"PPU ";
+pub const SECTION_PSG: [u8; 4] = *b"PSG ";
+pub const SECTION_TIMER: [u8; 4] = *b"TIMR";
+pub const SECTION_DMA: [u8; 4] = *b"DMA ";
+pub const SECTION_JOYPAD: [u8; 4] = *b"JOYP";
+pub const SECTION_CARTRIDGE: [u8; 4] = *b"CART";
+pub const SECTION_SERIAL: [u8; 4] = *b"SERL";
+
+pub struct StateWriter {
+    data: Vec<u8>,
+    section_start: usize,
+}
+
+#[allow(dead_code)]
+impl StateWriter {
+
+    pub fn new(rom_checksum: u32) -> Self {
+        let mut writer = StateWriter {
+            data: Vec::new(),
+            section_start: 0,
+        };
+        writer.data.extend_from_slice(&STATE_MAGIC);
+        writer.write_u8(STATE_MAJOR_VERSION);
+        writer.write_u8(STATE_MINOR_VERSION);
+        writer.write_u32(rom_checksum);
+        return writer;
+    }
+
+    pub fn begin_section(&mut self, tag: [u8; 4]) {
+        self.data.extend_from_slice(&tag);
+        self.write_u32(0);
+        self.section_start = self.data.len();
+    }
+
+    pub fn end_section(&mut self) {
+        let length = (self.data.len() - self.section_start) as u32;
+        self.data[self.section_start - 4 .. self.section_start].copy_from_slice(&length.to_le_bytes());
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.data.push(if value { 1 } else { 0 });
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_i32(&mut self, value: i32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_f32(&mut self, value: f32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_model(&mut self, value: GameboyType) {
+        self.write_u8(match value { GameboyType::CLASSIC => 0, GameboyType::COLOR => 1 });
+    }
+
+    // Length prefixed, so a reader can verify it matches the size it expects
+    pub fn write_bytes(&mut self, value: &[u8]) {
+        self.write_u32(value.len() as u32);
+        self.data.extend_from_slice(value);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        return self.data;
+    }
+}
+
+pub struct SaveState<'a> {
+    minor: u8,
+    pub rom_checksum: u32,
+    sections: Vec<([u8; 4], &'a [u8])>,
+}
+
+impl<'a> SaveState<'a> {
+
+    pub fn parse(data: &'a [u8]) -> Result<SaveState<'a>, EmulatorError> {
+        if data.len() < HEADER_SIZE || data[0 .. 4] != STATE_MAGIC {
+            return Err(EmulatorError::InvalidState(String::from("not a RustyBoy save state")));
+        }
+
+        let major = data[4];
+        let minor = data[5];
+        if major != STATE_MAJOR_VERSION {
+            return Err(EmulatorError::InvalidState(
+                format!("unsupported save state version {}.{}", major, minor)));
+        }
+
+        let rom_checksum = u32::from_le_bytes([data[6], data[7], data[8], data[9]]);
+
+        let mut sections = Vec::new();
+        let mut position = HEADER_SIZE;
+        while position < data.len() {
+            if position + 8 > data.len() {
+                return Err(EmulatorError::InvalidState(String::from("truncated section header")));
+            }
+            let tag = [data[position], data[position + 1], data[position + 2], data[position + 3]];
+            let length = u32::from_le_bytes([data[position + 4], data[position + 5], data[position + 6], data[position + 7]]) as usize;
+            position += 8;
+
+            // Lengths come from the blob, adding them to the position could overflow on wasm32
+            if length > data.len() - position {
+                return Err(EmulatorError::InvalidState(
+                    format!("truncated section {}", String::from_utf8_lossy(&tag))));
+            }
+            sections.push((tag, &data[position .. position + length]));
+            position += length;
+        }
+
+        return Ok(SaveState {
+            minor,
+            rom_checksum,
+            sections,
+        });
+    }
+
+    pub fn minor(&self) -> u8 {
+        return self.minor;
+    }
+
+    pub fn section(&self, tag: [u8; 4]) -> Result<StateReader<'a>, EmulatorError> {
+        return match self.sections.iter().find(|(section_tag, _)| *section_tag == tag) {
+            Some((_, data)) => Ok(StateReader { tag, data, position: 0, minor: self.minor }),
+            None => Err(EmulatorError::InvalidState(
+                format!("missing section {}", String::from_utf8_lossy(&tag)))),
+        };
+    }
+}
+
+pub struct StateReader<'a> {
+    tag: [u8; 4],
+    data: &'a [u8],
+    position: usize,
+    minor: u8,
+}
+
+#[allow(dead_code)]
+impl<'a> StateReader<'a> {
+
+    // Minor version of the state, fields appended in later versions are only present when
+    // this is at least the version that introduced them.
+    pub fn minor(&self) -> u8 {
+        return self.minor;
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8], EmulatorError> {
+        if length > self.data.len() - self.position {
+            return Err(EmulatorError::InvalidState(
+                format!("section {} is too short", String::from_utf8_lossy(&self.tag))));
+        }
+        let bytes = &self.data[self.position .. self.position + length];
+        self.position += length;
+        return Ok(bytes);
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, EmulatorError> {
+        return Ok(self.take(1)?[0]);
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, EmulatorError> {
+        return Ok(self.read_u8()? != 0);
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, EmulatorError> {
+        let bytes = self.take(2)?;
+        return Ok(u16::from_le_bytes([bytes[0], bytes[1]]));
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, EmulatorError> {
+        let bytes = self.take(4)?;
+        return Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]));
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, EmulatorError> {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        return Ok(u64::from_le_bytes(bytes));
+    }
+
+    pub fn read_i32(&mut self) -> Result<i32, EmulatorError> {
+        return Ok(self.read_u32()? as i32);
+    }
+
+    pub fn read_f32(&mut self) -> Result<f32, EmulatorError> {
+        return Ok(f32::from_bits(self.read_u32()?));
+    }
+
+    pub fn read_model(&mut self) -> Result<GameboyType, EmulatorError> {
+        return match self.read_u8()? {
+            0 => Ok(GameboyType::CLASSIC),
+            1 => Ok(GameboyType::COLOR),
+            _ => Err(self.invalid("model")),
+        };
+    }
+
+    pub fn read_bytes(&mut self) -> Result<&'a [u8], EmulatorError> {
+        let length = self.read_u32()? as usize;
+        return self.take(length);
+    }
+
+    // Reads a length prefixed block that must exactly fill the destination
+    pub fn read_bytes_into(&mut self, destination: &mut [u8]) -> Result<(), EmulatorError> {
+        let bytes = self.read_bytes()?;
+        if bytes.len() != destination.len() {
+            return Err(EmulatorError::InvalidState(
+                format!("section {} has a block of {} bytes, expected {}",
+                        String::from_utf8_lossy(&self.tag), bytes.len(), destination.len())));
+        }
+        destination.copy_from_slice(bytes);
+        return Ok(());
+    }
+
+    pub fn invalid(&self, what: &str) -> EmulatorError {
+        return EmulatorError::InvalidState(
+            format!("section {} has an invalid {}", String::from_utf8_lossy(&self.tag), what));
+    }
+}
+
+// CRC-32 (IEEE 802.3) used to tie a state to the rom it was made against
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut table = [0u32; 256];
+    for (i, entry) in table.iter_mut().enumerate() {
+        let mut value = i as u32;
+        for _j in 0 .. 8 {
+            value = if value & 1 == 1 { 0xEDB88320 ^ (value >> 1) } else { value >> 1 };
+        }
+        *entry = value;
+    }
+
+    let mut crc = 0xFFFFFFFFu32;
+    for byte in data {
+        crc = table[((crc ^ *byte as u32) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    return !crc;
+}
@@ -0,0 +1,96 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::error::EmulatorError;
+
+// Labels of a .sym file written by rgblink -n, one "bank:address name" per line and ; comments.
+// Banks only matter in the switchable rom and wram, which the mmu reports banks for, elsewhere
+// a label matches whatever is mapped.
+#[derive(Clone, Debug, Default)]
+pub struct SymbolTable {
+    labels: BTreeMap<(Option<usize>, u16), String>,
+    addresses: HashMap<String, (Option<usize>, u16)>,
+}
+
+impl SymbolTable {
+
+    pub fn new() -> Self {
+        return SymbolTable::default();
+    }
+
+    pub fn parse(text: &str) -> Result<Self, EmulatorError> {
+        let mut symbols = SymbolTable::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split(';').next().unwrap_or("").trim();
+            // Section headers of other assemblers
+            if line.is_empty() || line.starts_with('[') {
+                continue;
+            }
+            let invalid = || EmulatorError::InvalidSymbols { line: index + 1 };
+
+            let mut fields = line.split_whitespace();
+            let location = fields.next().ok_or_else(invalid)?;
+            let name = fields.next().ok_or_else(invalid)?;
+            let (bank, address) = location.split_once(':').ok_or_else(invalid)?;
+            let bank = usize::from_str_radix(bank, 16).map_err(|_e| invalid())?;
+            let address = u16::from_str_radix(address, 16).map_err(|_e| invalid())?;
+            symbols.insert(name, bank, address);
+        }
+        return Ok(symbols);
+    }
+
+    pub fn insert(&mut self, name: &str, bank: usize, address: u16) {
+        let key = key(bank, address);
+        self.labels.entry(key).or_insert_with(|| name.to_string());
+        self.addresses.insert(name.to_string(), key);
+    }
+
+    pub fn len(&self) -> usize {
+        return self.addresses.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.addresses.is_empty();
+    }
+
+    // The label at the address, bank is the one mapped there
+    pub fn label(&self, bank: usize, address: u16) -> Option<&str> {
+        return self.labels.get(&key(bank, address)).map(|name| name.as_str());
+    }
+
+    // The closest label at or before the address in the same memory area and the offset from it
+    pub fn locate(&self, bank: usize, address: u16) -> Option<(&str, u16)> {
+        let key = key(bank, address);
+        let ((label_bank, label_address), name) = self.labels.range(..= key).next_back()?;
+        if *label_bank != key.0 || area(*label_address) != area(address) {
+            return None;
+        }
+        return Some((name.as_str(), address - label_address));
+    }
+
+    // The bank, None for labels outside of the switchable areas, and address of a label
+    pub fn address(&self, name: &str) -> Option<(Option<usize>, u16)> {
+        return self.addresses.get(name).copied();
+    }
+}
+
+fn key(bank: usize, address: u16) -> (Option<usize>, u16) {
+    return (if is_banked(address) { Some(bank) } else { None }, address);
+}
+
+fn is_banked(address: u16) -> bool {
+    return matches!(address, 0x4000 ..= 0x7FFF | 0xD000 ..= 0xDFFF);
+}
+
+// https://gbdev.io/pandocs/Memory_Map.html
+fn area(address: u16) -> u8 {
+    return match address {
+        0x0000 ..= 0x3FFF => 0,
+        0x4000 ..= 0x7FFF => 1,
+        0x8000 ..= 0x9FFF => 2,
+        0xA000 ..= 0xBFFF => 3,
+        0xC000 ..= 0xCFFF => 4,
+        0xD000 ..= 0xDFFF => 5,
+        0xE000 ..= 0xFF7F => 6,
+        _ => 7,
+    };
+}
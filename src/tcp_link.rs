@@ -0,0 +1,224 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::rc::Rc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::console::Console;
+use crate::error::EmulatorError;
+use crate::link::CYCLES_PER_FRAME;
+use crate::serial::SerialDevice;
+
+// Every frame starts with its type and the length of its payload as u16 little endian.
+// HELLO   - "RBLK", protocol version, random u64 nonce, sync window as u32
+// SYNC    - sent at the end of every window: transfer started (0/1), byte, fast clock (0/1)
+// REPLY   - answer to the SYNC of the peer: byte shifted out (0/1), byte
+const MAGIC: &[u8; 4] = b"RBLK";
+const VERSION: u8 = 1;
+
+const FRAME_HELLO: u8 = 0x01;
+const FRAME_SYNC: u8 = 0x02;
+const FRAME_REPLY: u8 = 0x03;
+
+// In normal speed clock cycles, a quarter of a byte at 8192Hz. Transfers started during a window
+// are answered at its end, a window longer than a transfer makes the game read 1s.
+pub const DEFAULT_SYNC_WINDOW: u32 = 1024;
+
+// A byte on the fast clock in double speed. Both ends switch to it after a sync where either of
+// them was on the fast clock, several fast transfers would fit into a longer window.
+const FAST_SYNC_WINDOW: u32 = 64;
+
+// Bytes waiting for the peer, the oldest are dropped when nobody takes them
+const MAX_QUEUED: usize = 16;
+
+// A peer that stops answering for this long is considered gone
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+// Link cable to a console in another process. Both ends run freely within a sync window and
+// then wait for each other, so they never drift apart by more than one window and a slow
+// connection only costs speed, not bytes. Bytes sent faster than the windows go are queued and
+// reach the peer in order as it gets ready for them, the sender reads 1s for all but the last.
+pub struct TcpLink {
+    stream: TcpStream,
+    // Decided by the nonces of the handshake, the sync window of the master is used by both ends
+    master: bool,
+    sync_window: u32,
+    // The window in use, shortened while the fast clock is on
+    window: u32,
+    cycles: u32,
+    // Bytes started on the internal clock, handed to the peer one per window
+    sent: Rc<RefCell<VecDeque<u8>>>,
+}
+
+// Shifts in 1s until the reply of the peer arrives at the end of the window
+struct TcpPort {
+    sent: Rc<RefCell<VecDeque<u8>>>,
+}
+
+impl SerialDevice for TcpPort {
+    fn transfer(&mut self, byte: u8) -> u8 {
+        let mut sent = self.sent.borrow_mut();
+        if sent.len() >= MAX_QUEUED {
+            sent.pop_front();
+        }
+        sent.push_back(byte);
+        return 0xFF;
+    }
+}
+
+impl TcpLink {
+
+    pub fn connect<A: ToSocketAddrs>(address: A, sync_window: u32) -> Result<TcpLink, EmulatorError> {
+        let stream = TcpStream::connect(address).map_err(io_error)?;
+        return TcpLink::handshake(stream, sync_window);
+    }
+
+    // Waits for one peer to connect
+    pub fn accept(listener: &TcpListener, sync_window: u32) -> Result<TcpLink, EmulatorError> {
+        let (stream, _) = listener.accept().map_err(io_error)?;
+        return TcpLink::handshake(stream, sync_window);
+    }
+
+    fn handshake(stream: TcpStream, sync_window: u32) -> Result<TcpLink, EmulatorError> {
+        stream.set_nodelay(true).map_err(io_error)?;
+        stream.set_read_timeout(Some(TIMEOUT)).map_err(io_error)?;
+
+        let mut link = TcpLink {
+            stream,
+            master: false,
+            sync_window: sync_window.max(1),
+            window: 0,
+            cycles: 0,
+            sent: Rc::new(RefCell::new(VecDeque::new())),
+        };
+
+        let nonce = nonce();
+        let mut hello = MAGIC.to_vec();
+        hello.push(VERSION);
+        hello.extend_from_slice(&nonce.to_le_bytes());
+        hello.extend_from_slice(&link.sync_window.to_le_bytes());
+        link.write_frame(FRAME_HELLO, &hello)?;
+
+        let peer = link.read_frame(FRAME_HELLO)?;
+        if peer.len() != hello.len() || peer[0 .. 4] != MAGIC[..] {
+            return Err(EmulatorError::Io(String::from("link cable: peer is not a RustyBoy console")));
+        }
+        if peer[4] != VERSION {
+            return Err(EmulatorError::Io(format!("link cable: peer uses protocol version {}, expected {}", peer[4], VERSION)));
+        }
+
+        let mut peer_nonce = [0; 8];
+        peer_nonce.copy_from_slice(&peer[5 .. 13]);
+        let peer_nonce = u64::from_le_bytes(peer_nonce);
+        if peer_nonce == nonce {
+            return Err(EmulatorError::Io(String::from("link cable: both ends picked the same nonce, reconnect")));
+        }
+
+        link.master = nonce > peer_nonce;
+        if !link.master {
+            let mut peer_window = [0; 4];
+            peer_window.copy_from_slice(&peer[13 .. 17]);
+            link.sync_window = u32::from_le_bytes(peer_window).max(1);
+        }
+        link.window = link.sync_window;
+        return Ok(link);
+    }
+
+    pub fn is_master(&self) -> bool {
+        return self.master;
+    }
+
+    // The negotiated window in normal speed clock cycles
+    pub fn sync_window(&self) -> u32 {
+        return self.sync_window;
+    }
+
+    // Plugs the cable into the console, the device attached before is dropped
+    pub fn attach(&self, console: &mut Console) {
+        console.attach_serial_device(Box::new(TcpPort { sent: self.sent.clone() }));
+    }
+
+    // Runs the console for the given number of normal speed clock cycles, synchronizing with the
+    // peer at the end of every window
+    pub fn execute_cycles(&mut self, console: &mut Console, cycles: u32) -> Result<(), EmulatorError> {
+        let mut elapsed = 0;
+        while elapsed < cycles {
+            let step = console.step()?;
+            elapsed += step;
+            self.cycles += step;
+            if self.cycles >= self.window {
+                self.cycles -= self.window;
+                self.sync(console)?;
+            }
+        }
+        return Ok(());
+    }
+
+    pub fn run_frame(&mut self, console: &mut Console) -> Result<(), EmulatorError> {
+        return self.execute_cycles(console, CYCLES_PER_FRAME);
+    }
+
+    fn sync(&mut self, console: &mut Console) -> Result<(), EmulatorError> {
+        // Only the last byte can still be shifting in, the ones before it already read 1s
+        let (transfer, in_flight) = {
+            let mut sent = self.sent.borrow_mut();
+            (sent.pop_front(), sent.is_empty() && console.read_byte(0xFF02) & 0x80 == 0x80)
+        };
+        let fast = console.is_serial_fast();
+        self.write_frame(FRAME_SYNC, &[transfer.is_some() as u8, transfer.unwrap_or(0xFF), fast as u8])?;
+
+        let peer = self.read_frame(FRAME_SYNC)?;
+        if peer.len() != 3 {
+            return Err(EmulatorError::Io(String::from("link cable: malformed sync frame")));
+        }
+        self.window = if fast || peer[2] == 1 { self.sync_window.min(FAST_SYNC_WINDOW) } else { self.sync_window };
+        let reply = match (transfer, peer[0] == 1) {
+            // Both ends drive the clock, each one shifts in the byte of the other
+            (Some(byte), true) => Some(byte),
+            (None, true) => console.external_serial_transfer(peer[1], peer[2] == 1),
+            (_, false) => None,
+        };
+        self.write_frame(FRAME_REPLY, &[reply.is_some() as u8, reply.unwrap_or(0xFF)])?;
+
+        let peer_reply = self.read_frame(FRAME_REPLY)?;
+        if peer_reply.len() != 2 {
+            return Err(EmulatorError::Io(String::from("link cable: malformed reply frame")));
+        }
+        match transfer {
+            Some(_) if in_flight => console.complete_serial_transfer(if peer_reply[0] == 1 { peer_reply[1] } else { 0xFF }),
+            // Already done shifting here, the peer gets it once it is waiting again
+            Some(byte) if peer_reply[0] == 0 => self.sent.borrow_mut().push_front(byte),
+            _ => {},
+        }
+        return Ok(());
+    }
+
+    fn write_frame(&mut self, frame_type: u8, payload: &[u8]) -> Result<(), EmulatorError> {
+        let mut frame = vec![frame_type];
+        frame.extend_from_slice(&(payload.len() as u16).to_le_bytes());
+        frame.extend_from_slice(payload);
+        return self.stream.write_all(&frame).map_err(io_error);
+    }
+
+    fn read_frame(&mut self, frame_type: u8) -> Result<Vec<u8>, EmulatorError> {
+        let mut header = [0; 3];
+        self.stream.read_exact(&mut header).map_err(io_error)?;
+        if header[0] != frame_type {
+            return Err(EmulatorError::Io(format!("link cable: expected frame {:#04X}, got {:#04X}", frame_type, header[0])));
+        }
+        let mut payload = vec![0; u16::from_le_bytes([header[1], header[2]]) as usize];
+        self.stream.read_exact(&mut payload).map_err(io_error)?;
+        return Ok(payload);
+    }
+}
+
+fn io_error(error: std::io::Error) -> EmulatorError {
+    return EmulatorError::Io(format!("link cable: {}", error));
+}
+
+// Only needs to differ between the two ends
+fn nonce() -> u64 {
+    let time = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or(0);
+    return time ^ ((std::process::id() as u64) << 32) ^ (&time as *const u64 as u64);
+}
@@ -1,7 +1,7 @@
-use wasm_bindgen::prelude::*;
 use crate::console::GameboyType;
+use crate::error::EmulatorError;
+use crate::state::{StateWriter, StateReader};
 
-#[wasm_bindgen]
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Timer {
     div:  u8,
@@ -41,6 +41,32 @@ impl Timer {
         self.interrupt_flags = 0;
     }
 
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.div);
+        writer.write_u8(self.tima);
+        writer.write_u8(self.tma);
+        writer.write_u32(self.tac);
+        writer.write_bool(self.enabled);
+        writer.write_u32(self.divider_counter);
+        writer.write_u32(self.timer_counter);
+        writer.write_u8(self.interrupt_flags);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<(), EmulatorError> {
+        self.div = reader.read_u8()?;
+        self.tima = reader.read_u8()?;
+        self.tma = reader.read_u8()?;
+        self.tac = match reader.read_u32()? {
+            16 => 16, 64 => 64, 256 => 256, 1024 => 1024,
+            _ => return Err(reader.invalid("timer frequency")),
+        };
+        self.enabled = reader.read_bool()?;
+        self.divider_counter = reader.read_u32()?;
+        self.timer_counter = reader.read_u32()?;
+        self.interrupt_flags = reader.read_u8()?;
+        return Ok(());
+    }
+
     pub fn read_byte(&self, address: u16) -> u8 {
         match address {
             0xFF04 => self.div,
@@ -56,7 +82,8 @@ impl Timer {
 
     pub fn write_byte(&mut self, address: u16, value: u8) {
         match address {
-            0xFF04 => { self.div = 0; },
+            // Writing resets the whole internal counter, not only the visible upper byte
+            0xFF04 => { self.div = 0; self.divider_counter = 0; },
             0xFF05 => { self.tima = value; },
             0xFF06 => { self.tma = value; },
             0xFF07 => {
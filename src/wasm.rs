@@ -0,0 +1,435 @@
+use crate::console::Console;
+use crate::cpu::CpuState;
+use crate::link::LinkedPair;
+use crate::printer::{Printer, PrintQueue};
+use crate::logger::{TraceBuffer, TraceFilter};
+use crate::joypad::Button;
+use crate::error::EmulatorError;
+use crate::header::CartridgeHeader;
+use crate::disassembler::Instruction;
+use crate::debugger::{Debugger, Breakpoint, Watchpoint, Access, Condition, PauseReason};
+use wasm_bindgen::prelude::*;
+
+fn to_js_error(error: EmulatorError) -> JsValue {
+    return js_sys::Error::new(&error.to_string()).into();
+}
+
+// Plain object with the same field names as CartridgeHeader, sizes are undefined when unknown
+fn to_js_header(header: &CartridgeHeader) -> JsValue {
+    let object = js_sys::Object::new();
+    let set = |key: &str, value: JsValue| {
+        js_sys::Reflect::set(&object, &JsValue::from_str(key), &value).unwrap();
+    };
+    set("title", JsValue::from_str(&header.title));
+    set("manufacturer_code", JsValue::from_str(&header.manufacturer_code));
+    set("cgb_flag", JsValue::from(header.cgb_flag));
+    set("sgb_flag", JsValue::from(header.sgb_flag));
+    set("licensee_code", JsValue::from_str(&header.licensee_code));
+    set("cartridge_type", JsValue::from(header.cartridge_type));
+    set("rom_size", header.rom_size.map_or(JsValue::UNDEFINED, |size| JsValue::from(size as u32)));
+    set("ram_size", header.ram_size.map_or(JsValue::UNDEFINED, |size| JsValue::from(size as u32)));
+    set("destination_code", JsValue::from(header.destination_code));
+    set("version", JsValue::from(header.version));
+    set("header_checksum", JsValue::from(header.header_checksum));
+    set("header_checksum_valid", JsValue::from(header.header_checksum_valid));
+    set("global_checksum", JsValue::from(header.global_checksum));
+    set("global_checksum_valid", JsValue::from(header.global_checksum_valid));
+    set("logo_valid", JsValue::from(header.logo_valid));
+    return object.into();
+}
+
+// Plain object with the fields of Instruction and its formatted line as text
+fn to_js_instruction(instruction: &Instruction) -> JsValue {
+    let object = js_sys::Object::new();
+    let set = |key: &str, value: JsValue| {
+        js_sys::Reflect::set(&object, &JsValue::from_str(key), &value).unwrap();
+    };
+    set("address", JsValue::from(instruction.address));
+    set("bank", JsValue::from(instruction.bank as u32));
+    set("bytes", js_sys::Uint8Array::from(&instruction.bytes[..]).into());
+    set("mnemonic", JsValue::from_str(&instruction.mnemonic));
+    set("label", instruction.label.as_ref().map_or(JsValue::UNDEFINED, |label| JsValue::from_str(label)));
+    set("text", JsValue::from_str(&instruction.to_string()));
+    return object.into();
+}
+
+// Plain object with a reason of "breakpoint", "watchpoint", "step" or "locked_up" and the
+// fields of the PauseReason
+fn to_js_pause_reason(reason: &PauseReason) -> JsValue {
+    let object = js_sys::Object::new();
+    let set = |key: &str, value: JsValue| {
+        js_sys::Reflect::set(&object, &JsValue::from_str(key), &value).unwrap();
+    };
+    match *reason {
+        PauseReason::Breakpoint { address, bank } => {
+            set("reason", JsValue::from_str("breakpoint"));
+            set("address", JsValue::from(address));
+            set("bank", JsValue::from(bank as u32));
+        },
+        PauseReason::Watchpoint { address, access, value } => {
+            set("reason", JsValue::from_str("watchpoint"));
+            set("address", JsValue::from(address));
+            set("access", JsValue::from(access as u32));
+            set("value", JsValue::from(value));
+        },
+        PauseReason::Step => set("reason", JsValue::from_str("step")),
+        PauseReason::LockedUp => set("reason", JsValue::from_str("locked_up")),
+    }
+    return object.into();
+}
+
+// Undefined when the console ran to the end
+fn to_js_pause(result: Result<Option<PauseReason>, EmulatorError>) -> Result<JsValue, JsValue> {
+    return result.map(|reason| reason.as_ref().map_or(JsValue::UNDEFINED, to_js_pause_reason)).map_err(to_js_error);
+}
+
+// Thin wrapper exposing the console to javascript, all emulation lives in the core.
+#[wasm_bindgen(js_name = Console)]
+pub struct WasmConsole {
+    console: Console,
+    debugger: Debugger,
+    printer: Option<PrintQueue>,
+    trace: Option<TraceBuffer>,
+}
+
+#[wasm_bindgen(js_class = Console)]
+impl WasmConsole {
+
+    pub fn new() -> Self {
+        #[cfg(debug_assertions)]
+        console_error_panic_hook::set_once();
+
+        return WasmConsole {
+            console: Console::new(),
+            debugger: Debugger::new(),
+            printer: None,
+            trace: None,
+        }
+    }
+
+    pub fn load(&mut self, rom: &[u8]) -> Result<(), JsValue> {
+        return self.console.load(rom).map_err(to_js_error);
+    }
+
+    // Runs from the next reset, a DMG boot rom is 256 bytes and a CGB boot rom 2304 bytes
+    pub fn load_boot_rom(&mut self, data: &[u8]) -> Result<(), JsValue> {
+        return self.console.load_boot_rom(data).map_err(to_js_error);
+    }
+
+    pub fn clear_boot_rom(&mut self) {
+        self.console.clear_boot_rom();
+    }
+
+    pub fn is_booting(&self) -> bool {
+        return self.console.is_booting();
+    }
+
+    pub fn press_button(&mut self, button: Button) {
+        self.console.press_button(button);
+    }
+
+    pub fn release_button(&mut self, button: Button) {
+        self.console.release_button(button);
+    }
+
+    pub fn reset(&mut self) {
+        self.console.reset();
+        self.debugger.reset();
+    }
+
+    // The run functions go through the debugger and return why it paused, or undefined
+    pub fn execute_ticks(&mut self, ticks: u32) -> Result<JsValue, JsValue> {
+        return to_js_pause(self.debugger.execute_ticks(&mut self.console, ticks));
+    }
+
+    pub fn execute_tick(&mut self) -> Result<JsValue, JsValue> {
+        return to_js_pause(self.debugger.execute_ticks(&mut self.console, 1));
+    }
+
+    pub fn run_frame(&mut self) -> Result<JsValue, JsValue> {
+        return to_js_pause(self.debugger.run_frame(&mut self.console));
+    }
+
+    // The bank is undefined for any bank, the condition like "a == $10" is optional
+    pub fn add_breakpoint(&mut self, address: u16, bank: Option<u32>, condition: Option<String>) -> Result<(), JsValue> {
+        let condition = match condition {
+            Some(text) => Some(Condition::parse(&text).map_err(to_js_error)?),
+            None => None,
+        };
+        self.debugger.add_breakpoint(Breakpoint { address, bank: bank.map(|bank| bank as usize), condition });
+        return Ok(());
+    }
+
+    // Breaks at a label of the loaded symbols, like "Main.loop"
+    pub fn add_symbol_breakpoint(&mut self, name: &str, condition: Option<String>) -> Result<(), JsValue> {
+        let condition = match condition {
+            Some(text) => Some(Condition::parse(&text).map_err(to_js_error)?),
+            None => None,
+        };
+        return self.debugger.add_symbol_breakpoint(&self.console, name, condition).map_err(to_js_error);
+    }
+
+    pub fn remove_breakpoint(&mut self, address: u16, bank: Option<u32>) -> bool {
+        return self.debugger.remove_breakpoint(address, bank.map(|bank| bank as usize));
+    }
+
+    // Watches the addresses from start to end inclusive
+    pub fn add_watchpoint(&mut self, start: u16, end: u16, access: Access) {
+        self.debugger.add_watchpoint(Watchpoint { start, end, access });
+    }
+
+    pub fn remove_watchpoint(&mut self, start: u16, end: u16, access: Access) -> bool {
+        return self.debugger.remove_watchpoint(&Watchpoint { start, end, access });
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.debugger.clear();
+    }
+
+    // Steps take effect on the next run, which returns a "step" pause when they finish
+    pub fn step_into(&mut self) {
+        self.debugger.step_into();
+    }
+
+    pub fn step_over(&mut self) {
+        self.debugger.step_over(&self.console);
+    }
+
+    pub fn step_out(&mut self) {
+        self.debugger.step_out(&self.console);
+    }
+
+    pub fn run_to(&mut self, address: u16, bank: Option<u32>) {
+        self.debugger.run_to(address, bank.map(|bank| bank as usize));
+    }
+
+    // Plain object with the registers in lower case and ime
+    pub fn get_registers(&self) -> JsValue {
+        let registers = self.console.get_registers();
+        let object = js_sys::Object::new();
+        let set = |key: &str, value: JsValue| {
+            js_sys::Reflect::set(&object, &JsValue::from_str(key), &value).unwrap();
+        };
+        set("a", JsValue::from(registers.a));
+        set("f", JsValue::from(registers.f));
+        set("b", JsValue::from(registers.b));
+        set("c", JsValue::from(registers.c));
+        set("d", JsValue::from(registers.d));
+        set("e", JsValue::from(registers.e));
+        set("h", JsValue::from(registers.h));
+        set("l", JsValue::from(registers.l));
+        set("sp", JsValue::from(registers.sp));
+        set("pc", JsValue::from(registers.pc));
+        set("ime", JsValue::from(registers.ime));
+        return object.into();
+    }
+
+    // The .sym file written by rgblink for the loaded rom
+    pub fn load_symbols(&mut self, text: &str) -> Result<(), JsValue> {
+        return self.console.load_symbols(text).map_err(to_js_error);
+    }
+
+    // Closest label and offset, like "Main.loop+$03"
+    pub fn describe_address(&self, address: u16) -> String {
+        return self.console.describe_address(address);
+    }
+
+    // Array of lines, where the console is followed by the callers
+    pub fn get_backtrace(&self) -> js_sys::Array {
+        return self.debugger.backtrace(&self.console).iter().map(|line| JsValue::from_str(line)).collect();
+    }
+
+    // Keeps the last capacity instructions in the gameboy-doctor format, optionally only those
+    // with pc in start..=end and in the bank
+    pub fn start_trace(&mut self, capacity: usize, start: Option<u16>, end: Option<u16>, bank: Option<u32>) {
+        let buffer = TraceBuffer::new(capacity);
+        let filter = match (start, end, bank) {
+            (None, None, None) => None,
+            _ => Some(TraceFilter {
+                start: start.unwrap_or(0x0000),
+                end: end.unwrap_or(0xFFFF),
+                bank: bank.map(|bank| bank as usize),
+            }),
+        };
+        self.trace = Some(buffer.clone());
+        self.console.start_trace(Box::new(buffer), filter);
+    }
+
+    pub fn stop_trace(&mut self) -> Result<(), JsValue> {
+        return self.console.stop_trace().map_err(to_js_error);
+    }
+
+    // Lines traced since the last call, separated by newlines
+    pub fn take_trace(&mut self) -> String {
+        return match &self.trace {
+            Some(buffer) => buffer.take_lines().join("\n"),
+            None => String::new(),
+        };
+    }
+
+    pub fn start_profiling(&mut self) {
+        self.console.start_profiling();
+    }
+
+    pub fn stop_profiling(&mut self) {
+        self.console.stop_profiling();
+    }
+
+    // The hotspots with the most cycles while profiling, a line each
+    pub fn get_profile_report(&self, limit: usize) -> String {
+        return self.console.profile_report(limit);
+    }
+
+    // Code and data flags of each rom byte while profiling, empty otherwise
+    pub fn get_coverage(&self) -> Vec<u8> {
+        return self.console.profiler().map(|profiler| profiler.coverage().to_vec()).unwrap_or_default();
+    }
+
+    pub fn read_byte(&self, address: u16) -> u8 {
+        return self.console.read_byte(address);
+    }
+
+    // Header of the loaded cartridge, undefined until a rom is loaded
+    pub fn get_header(&self) -> JsValue {
+        return self.console.get_header().map_or(JsValue::UNDEFINED, to_js_header);
+    }
+
+    // Parses a header without loading the rom
+    pub fn parse_header(rom: &[u8]) -> Result<JsValue, JsValue> {
+        return CartridgeHeader::parse(rom).map(|header| to_js_header(&header)).map_err(to_js_error);
+    }
+
+    pub fn has_battery(&mut self) -> bool {
+        return self.console.has_battery();
+    }
+
+    pub fn is_save_dirty(&mut self) -> bool {
+        return self.console.is_save_dirty();
+    }
+
+    pub fn export_save(&mut self) -> Vec<u8> {
+        return self.console.export_save();
+    }
+
+    pub fn import_save(&mut self, save: &[u8]) -> Result<(), JsValue> {
+        return self.console.import_save(save).map_err(to_js_error);
+    }
+
+    pub fn save_state(&self) -> Vec<u8> {
+        return self.console.save_state();
+    }
+
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), JsValue> {
+        self.debugger.reset();
+        return self.console.load_state(state).map_err(to_js_error);
+    }
+
+    pub fn rom_checksum(&self) -> u32 {
+        return self.console.rom_checksum();
+    }
+
+    pub fn state_rom_checksum(state: &[u8]) -> Result<u32, JsValue> {
+        return Console::state_rom_checksum(state).map_err(to_js_error);
+    }
+
+    pub fn set_cycle_accurate(&mut self, enabled: bool) {
+        self.console.set_cycle_accurate(enabled);
+    }
+
+    pub fn get_cpu_state(&self) -> CpuState {
+        return self.console.get_cpu_state();
+    }
+
+    pub fn disassemble_at(&self, address: u16) -> JsValue {
+        return to_js_instruction(&self.console.disassemble_at(address));
+    }
+
+    // Array of the instructions from start up to end
+    pub fn disassemble(&self, start: u16, end: u16) -> js_sys::Array {
+        return self.console.disassemble(start, end).iter().map(to_js_instruction).collect();
+    }
+
+    // Plugs a Game Boy Printer into the link port
+    pub fn attach_printer(&mut self) {
+        let printer = Printer::new();
+        self.printer = Some(printer.queue());
+        self.console.attach_serial_device(Box::new(printer));
+    }
+
+    pub fn detach_printer(&mut self) {
+        if self.printer.take().is_some() {
+            self.console.detach_serial_device();
+        }
+    }
+
+    // PNG of the oldest print job not taken yet, undefined when there is none
+    pub fn take_print(&mut self) -> Result<Option<Vec<u8>>, JsValue> {
+        return match self.printer.as_ref().and_then(|queue| queue.take_job()) {
+            Some(job) => job.to_png().map(Some).map_err(to_js_error),
+            None => Ok(None),
+        };
+    }
+
+    pub fn get_frame(&self) -> Vec<u8> {
+        return self.console.get_frame().to_vec();
+    }
+
+    // Interleaved stereo samples at 44.1kHz produced since the last call
+    pub fn get_sound(&mut self) -> Vec<f32> {
+        return self.console.take_sound();
+    }
+
+}
+
+// Two consoles connected by a link cable, the page draws both screens
+#[wasm_bindgen(js_name = LinkedPair)]
+pub struct WasmLinkedPair {
+    pair: LinkedPair,
+}
+
+#[wasm_bindgen(js_class = LinkedPair)]
+impl WasmLinkedPair {
+
+    pub fn new() -> Self {
+        #[cfg(debug_assertions)]
+        console_error_panic_hook::set_once();
+
+        return WasmLinkedPair {
+            pair: LinkedPair::new(),
+        }
+    }
+
+    // Both consoles may run the same rom, the index is 0 or 1
+    pub fn load(&mut self, index: usize, rom: &[u8]) -> Result<(), JsValue> {
+        return self.pair.console_mut(index).load(rom).map_err(to_js_error);
+    }
+
+    pub fn press_button(&mut self, index: usize, button: Button) {
+        self.pair.console_mut(index).press_button(button);
+    }
+
+    pub fn release_button(&mut self, index: usize, button: Button) {
+        self.pair.console_mut(index).release_button(button);
+    }
+
+    pub fn reset(&mut self) {
+        self.pair.reset();
+    }
+
+    pub fn run_frame(&mut self) -> Result<(), JsValue> {
+        return self.pair.run_frame().map_err(to_js_error);
+    }
+
+    pub fn get_header(&self, index: usize) -> JsValue {
+        return self.pair.console(index).get_header().map_or(JsValue::UNDEFINED, to_js_header);
+    }
+
+    pub fn get_frame(&self, index: usize) -> Vec<u8> {
+        return self.pair.console(index).get_frame().to_vec();
+    }
+
+    pub fn get_sound(&mut self, index: usize) -> Vec<f32> {
+        return self.pair.console_mut(index).take_sound();
+    }
+
+}
@@ -0,0 +1,114 @@
+use rust_webpack_template::{Console, EmulatorError, GameboyType};
+
+mod common;
+
+// Stores $42 to $C000 and unmaps itself at the end of the boot rom, like the real ones do
+const DMG_BOOT: [u8; 8] = [
+    0x3E, 0x42,                   // ld a, $42
+    0xEA, 0x00, 0xC0,             // ld [$C000], a
+    0xC3, 0xFC, 0x00,             // jp $00FC
+];
+
+const UNMAP: [u8; 4] = [
+    0x3E, 0x01,                   // ld a, 1
+    0xE0, 0x50,                   // ldh [$FF50], a
+];
+
+// Color 0 of the first background palette red and color 3 blue, then the DMG compatibility mode
+const CGB_BOOT: [u8; 28] = [
+    0x3E, 0x80,                   // ld a, $80
+    0xE0, 0x68,                   // ldh [$FF68], a
+    0x3E, 0x1F,                   // ld a, $1F
+    0xE0, 0x69,                   // ldh [$FF69], a
+    0xAF,                         // xor a
+    0xE0, 0x69,                   // ldh [$FF69], a
+    0xE0, 0x69, 0xE0, 0x69,       // ldh [$FF69], a
+    0xE0, 0x69, 0xE0, 0x69,       // ldh [$FF69], a
+    0xE0, 0x69,                   // ldh [$FF69], a
+    0x3E, 0x7C,                   // ld a, $7C
+    0xE0, 0x69,                   // ldh [$FF69], a
+    0xC3, 0xF8, 0x00,             // jp $00F8
+];
+
+const DMG_MODE: [u8; 4] = [
+    0x3E, 0x04,                   // ld a, 4
+    0xE0, 0x4C,                   // ldh [$FF4C], a
+];
+
+// Turns the lcd on with every shade of the background set to 3
+const PROGRAM: [u8; 10] = [
+    0x3E, 0xFF,                   // ld a, $FF
+    0xE0, 0x47,                   // ldh [$FF47], a
+    0x3E, 0x91,                   // ld a, $91
+    0xE0, 0x40,                   // ldh [$FF40], a
+    0x18, 0xFE,                   // jr -2
+];
+
+fn load_console(boot_rom: &[u8]) -> Console {
+    let mut rom = common::build_rom(&PROGRAM);
+    rom[0x0000] = 0xAA;
+    rom[0x0200] = 0xBB;
+    let mut console = common::load_rom(&rom);
+    console.load_boot_rom(boot_rom).unwrap();
+    console.reset();
+    return console;
+}
+
+#[test]
+fn runs_dmg_boot_roms() {
+    let mut boot_rom = vec![0; 0x100];
+    boot_rom[.. DMG_BOOT.len()].copy_from_slice(&DMG_BOOT);
+    boot_rom[0xFC ..].copy_from_slice(&UNMAP);
+    let mut console = load_console(&boot_rom);
+
+    assert!(console.is_booting());
+    assert_eq!(console.get_model(), GameboyType::CLASSIC);
+    assert_eq!((console.get_registers().pc, console.get_registers().a), (0x0000, 0x00));
+    assert_eq!((console.read_byte(0x0000), console.read_byte(0x0200)), (0x3E, 0xBB));
+
+    console.execute_ticks(2).unwrap();
+    let state = console.save_state();
+    console.execute_ticks(3).unwrap();
+    assert!(!console.is_booting());
+    assert_eq!(console.get_registers().pc, 0x0100);
+    assert_eq!((console.read_byte(0x0000), console.read_byte(0xC000)), (0xAA, 0x42));
+
+    // Writing FF50 again does not map it back
+    console.write_byte(0xFF50, 0x00);
+    assert_eq!(console.read_byte(0x0000), 0xAA);
+
+    console.load_state(&state).unwrap();
+    assert!(console.is_booting());
+    assert_eq!(console.read_byte(0x0000), 0x3E);
+
+    assert_eq!(console.load_boot_rom(&[0; 0x200]), Err(EmulatorError::InvalidBootRom { size: 0x200 }));
+    console.clear_boot_rom();
+    console.reset();
+    assert_eq!((console.get_registers().pc, console.get_registers().a), (0x0100, 0x01));
+}
+
+#[test]
+fn runs_cgb_boot_roms_in_dmg_compatibility_mode() {
+    let mut boot_rom = vec![0; 0x900];
+    boot_rom[.. CGB_BOOT.len()].copy_from_slice(&CGB_BOOT);
+    boot_rom[0xF8 .. 0xFC].copy_from_slice(&DMG_MODE);
+    boot_rom[0xFC .. 0x100].copy_from_slice(&UNMAP);
+    boot_rom[0x200] = 0x55;
+    let mut console = load_console(&boot_rom);
+
+    // The cartridge header shows through between the two parts
+    assert_eq!(console.get_model(), GameboyType::COLOR);
+    assert_eq!((console.read_byte(0x0100), console.read_byte(0x0200)), (0x3E, 0x55));
+
+    while console.is_booting() {
+        console.execute_ticks(1).unwrap();
+    }
+    assert_eq!(console.get_model(), GameboyType::CLASSIC);
+    assert_eq!(console.read_byte(0x0200), 0xBB);
+
+    // KEY0 is locked after the boot rom
+    console.write_byte(0xFF4C, 0x80);
+    console.run_frame().unwrap();
+    console.run_frame().unwrap();
+    assert_eq!(console.get_frame()[0 .. 4], [0, 0, 248, 255]);
+}
@@ -0,0 +1,242 @@
+use std::fs;
+
+use rust_webpack_template::{CartridgeHeader, Console, EmulatorError};
+
+mod common;
+use common::load_rom;
+
+const RAM_SIZE: usize = 0x2000;
+
+// A 32KB rom with the given mapper and 8KB of ram whose entry point runs the given program
+fn build_rom(cartridge_type: u8, program: &[u8]) -> Vec<u8> {
+    return common::build_cartridge(cartridge_type, 0x02, program);
+}
+
+// Live registers, latched registers and timestamp of the rtc trailer
+fn read_trailer(save: &[u8]) -> ([u8; 5], [u8; 5], u64) {
+    assert_eq!(save.len(), RAM_SIZE + 48);
+    let trailer = &save[RAM_SIZE ..];
+    let mut live = [0; 5];
+    let mut latched = [0; 5];
+    for i in 0 .. 5 {
+        live[i] = trailer[i * 4];
+        latched[i] = trailer[20 + i * 4];
+    }
+    let mut timestamp = [0; 8];
+    timestamp.copy_from_slice(&trailer[40 ..]);
+    return (live, latched, u64::from_le_bytes(timestamp));
+}
+
+fn build_save(registers: [u8; 5], timestamp: u64) -> Vec<u8> {
+    let mut save = vec![0; RAM_SIZE];
+    for register in registers.iter().chain(registers.iter()) {
+        save.extend_from_slice(&(*register as u32).to_le_bytes());
+    }
+    save.extend_from_slice(&timestamp.to_le_bytes());
+    return save;
+}
+
+fn now() -> u64 {
+    return std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
+}
+
+#[test]
+fn rtc_counts_cycles_and_latches() {
+    let program = [
+        0x3E, 0x0A, 0xEA, 0x00, 0x00, // ld a, $0A; ld [$0000], a
+        0x3E, 0x08, 0xEA, 0x00, 0x40, // ld a, $08; ld [$4000], a
+        0x3E, 0x3B, 0xEA, 0x00, 0xA0, // ld a, 59; ld [$A000], a
+        0xAF, 0xEA, 0x00, 0x60,       // xor a; ld [$6000], a
+        0x3C, 0xEA, 0x00, 0x60,       // inc a; ld [$6000], a
+        0x18, 0xFE,                   // jr -2
+    ];
+
+    let mut console = load_rom(&build_rom(0x10, &program));
+
+    // Slightly more than one second
+    for _i in 0 .. 61 {
+        console.run_frame().unwrap();
+    }
+    assert!(console.is_save_dirty());
+
+    let (live, latched, _) = read_trailer(&console.export_save());
+    assert_eq!(live, [0, 1, 0, 0, 0]);
+    assert_eq!(latched, [59, 0, 0, 0, 0]);
+}
+
+#[test]
+fn rtc_advances_by_wall_clock() {
+    let mut console = load_rom(&build_rom(0x10, &[0x18, 0xFE]));
+
+    // 23:59:59 on the last day, one second later the day counter overflows
+    console.import_save(&build_save([59, 59, 23, 0xFF, 0x01], now() - 1)).unwrap();
+    let (live, latched, timestamp) = read_trailer(&console.export_save());
+    assert_eq!(live[2 ..], [0, 0, 0x80]);
+    assert_eq!(latched, [59, 59, 23, 0xFF, 0x01]);
+    assert!(timestamp + 1 >= now());
+
+    // A halted clock does not advance
+    console.import_save(&build_save([10, 20, 5, 3, 0x40], now() - 3600)).unwrap();
+    let (live, _, _) = read_trailer(&console.export_save());
+    assert_eq!(live, [10, 20, 5, 3, 0x40]);
+}
+
+#[test]
+fn rtc_save_without_trailer() {
+    let mut console = load_rom(&build_rom(0x10, &[0x18, 0xFE]));
+    console.import_save(&vec![0; RAM_SIZE]).unwrap();
+    assert_eq!(console.export_save().len(), RAM_SIZE + 48);
+}
+
+#[test]
+fn mbc2_banks_rom_and_stores_nibbles() {
+    let program = [
+        0x3E, 0x0A, 0xEA, 0x00, 0x00, // ld a, $0A; ld [$0000], a   ram enable
+        0x3E, 0x03, 0xEA, 0x00, 0x21, // ld a, $03; ld [$2100], a   rom bank 3
+        0xFA, 0x00, 0x40,             // ld a, [$4000]
+        0xEA, 0x00, 0xA0,             // ld [$A000], a
+        0x3E, 0x37, 0xEA, 0xFF, 0xBF, // ld a, $37; ld [$BFFF], a   echo of $A1FF
+        0xFA, 0x00, 0xA2,             // ld a, [$A200]              echo of $A000
+        0xFE, 0xFA,                   // cp $FA
+        0x20, 0x05,                   // jr nz, +5
+        0x3E, 0x01, 0xEA, 0x02, 0xA0, // ld a, $01; ld [$A002], a
+        0x18, 0xFE,                   // jr -2
+    ];
+
+    // MBC2+BATTERY with 64KB of rom
+    let mut rom = build_rom(0x06, &program);
+    rom[0x148] = 0x01;
+    rom[0x149] = 0x00;
+    rom.resize(0x10000, 0);
+    rom[3 * 0x4000] = 0x5A;
+
+    let mut console = load_rom(&rom);
+    assert!(console.has_battery());
+    console.run_frame().unwrap();
+
+    let save = console.export_save();
+    assert_eq!(save.len(), 0x200);
+    assert_eq!(save[0x000], 0x0A);
+    assert_eq!(save[0x1FF], 0x07);
+    assert_eq!(save[0x002], 0x01);
+}
+
+#[test]
+fn parses_header() {
+    let header = CartridgeHeader::parse(&fs::read("roms/cpu_instrs.gb").unwrap()).unwrap();
+    assert_eq!(header.title, "CPU_INSTRS");
+    assert_eq!(header.manufacturer_code, "");
+    assert_eq!(header.cgb_flag, 0x80);
+    assert_eq!(header.cartridge_type, 0x01);
+    assert_eq!(header.rom_size, Some(0x10000));
+    assert_eq!(header.ram_size, Some(0));
+    assert!(header.logo_valid);
+    assert!(header.header_checksum_valid);
+    assert!(header.is_valid());
+    // blargg's roms do not fix up the global checksum
+    assert!(!header.global_checksum_valid);
+
+    let header = CartridgeHeader::parse(&fs::read("roms/opus5.gb").unwrap()).unwrap();
+    assert_eq!(header.title, "Opus Test");
+    assert_eq!(header.licensee_code, "00");
+
+    let header = CartridgeHeader::parse(&fs::read("roms/tellinglys.gb").unwrap()).unwrap();
+    assert_eq!(header.licensee_code, "OK");
+
+    let mut console = Console::new();
+    assert!(console.get_header().is_none());
+    console.load(&fs::read("roms/cgb-acid2.gbc").unwrap()).unwrap();
+    assert_eq!(console.get_header().unwrap().title, "CGB-ACID2");
+    assert_eq!(console.get_header().unwrap().cgb_flag, 0xC0);
+}
+
+#[test]
+fn detects_bad_dump() {
+    let mut rom = fs::read("roms/ttt.gb").unwrap();
+    assert!(CartridgeHeader::parse(&rom).unwrap().global_checksum_valid);
+    rom[0x110] ^= 0xFF;
+    rom[0x134] ^= 0xFF;
+
+    let header = CartridgeHeader::parse(&rom).unwrap();
+    assert!(!header.logo_valid);
+    assert!(!header.header_checksum_valid);
+    assert!(!header.global_checksum_valid);
+    assert!(!header.is_valid());
+
+    assert_eq!(CartridgeHeader::parse(&rom[.. 0x14F]), Err(EmulatorError::TruncatedRom { expected: 0x150, actual: 0x14F }));
+}
+
+// MBC1+RAM+BATTERY with 32KB of ram, every bank is a copy of bank 0 with its number at 0x3FFF
+fn build_mbc1_rom(rom_size_code: u8, program: &[u8]) -> Vec<u8> {
+    let mut bank = build_rom(0x03, program);
+    bank.truncate(0x4000);
+    bank[0x148] = rom_size_code;
+    bank[0x149] = 0x03;
+
+    let mut rom = Vec::new();
+    for i in 0 .. (0x8000 << rom_size_code) / 0x4000 {
+        bank[0x3FFF] = i as u8;
+        rom.extend_from_slice(&bank);
+    }
+    return rom;
+}
+
+const MBC1_BANKING_PROGRAM: [u8; 45] = [
+    0x3E, 0x0A, 0xEA, 0x00, 0x00, // ld a, $0A; ld [$0000], a   ram enable
+    0xAF, 0xEA, 0x00, 0x20,       // xor a; ld [$2000], a        BANK1 = 0 selects 1
+    0x3E, 0x02, 0xEA, 0x00, 0x40, // ld a, $02; ld [$4000], a   BANK2 = 2
+    0xFA, 0xFF, 0x7F,             // ld a, [$7FFF]
+    0xEA, 0x00, 0xA0,             // ld [$A000], a
+    0xFA, 0xFF, 0x3F,             // ld a, [$3FFF]
+    0xEA, 0x01, 0xA0,             // ld [$A001], a
+    0x3E, 0x01, 0xEA, 0x00, 0x60, // ld a, $01; ld [$6000], a   mode 1
+    0xFA, 0xFF, 0x3F,             // ld a, [$3FFF]
+    0xEA, 0x02, 0xA0,             // ld [$A002], a               ram bank 2
+    0xFA, 0xFF, 0x7F,             // ld a, [$7FFF]
+    0xEA, 0x03, 0xA0,             // ld [$A003], a
+    0x18, 0xFE,                   // jr -2
+];
+
+#[test]
+fn mbc1_banking_modes() {
+    // 2MB, all 7 bank bits are used
+    let mut console = load_rom(&build_mbc1_rom(0x06, &MBC1_BANKING_PROGRAM));
+    console.run_frame().unwrap();
+
+    let save = console.export_save();
+    assert_eq!(save[0 .. 2], [0x41, 0x00]);
+    assert_eq!(save[0x4000 + 2 .. 0x4000 + 4], [0x40, 0x41]);
+
+    // 512KB, BANK2 is masked off
+    let mut console = load_rom(&build_mbc1_rom(0x04, &MBC1_BANKING_PROGRAM));
+    console.run_frame().unwrap();
+
+    let save = console.export_save();
+    assert_eq!(save[0 .. 2], [0x01, 0x00]);
+    assert_eq!(save[0x4000 + 2 .. 0x4000 + 4], [0x00, 0x01]);
+}
+
+#[test]
+fn mbc1_multicart_wiring() {
+    let program = [
+        0x3E, 0x0A, 0xEA, 0x00, 0x00, // ld a, $0A; ld [$0000], a
+        0x3E, 0x13, 0xEA, 0x00, 0x20, // ld a, $13; ld [$2000], a
+        0x3E, 0x02, 0xEA, 0x00, 0x40, // ld a, $02; ld [$4000], a
+        0xFA, 0xFF, 0x7F,             // ld a, [$7FFF]
+        0xEA, 0x00, 0xA0,             // ld [$A000], a
+        0x18, 0xFE,                   // jr -2
+    ];
+
+    // 1MB without a second header is a regular MBC1, bank 0x53 wraps to 0x13
+    let mut rom = build_mbc1_rom(0x05, &program);
+    let mut console = load_rom(&rom);
+    console.run_frame().unwrap();
+    assert_eq!(console.export_save()[0], 0x13);
+
+    // The game at bank 0x10 has a valid logo, BANK2 starts at bit 4
+    let logo = fs::read("roms/ttt.gb").unwrap()[0x104 .. 0x134].to_vec();
+    rom[0x40104 .. 0x40134].copy_from_slice(&logo);
+    let mut console = load_rom(&rom);
+    console.run_frame().unwrap();
+    assert_eq!(console.export_save()[0], 0x23);
+}
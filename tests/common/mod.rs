@@ -0,0 +1,63 @@
+// Rom builders and programs shared by the integration tests, each test uses a part of them
+#![allow(dead_code)]
+
+use std::fs;
+
+use rust_webpack_template::Console;
+
+// Counts in b and calls the store routine at $0200
+pub const COUNTER: [u8; 8] = [
+    0x06, 0x00,                   // ld b, 0
+    0x04,                         // inc b
+    0xCD, 0x00, 0x02,             // call $0200
+    0x18, 0xFA,                   // jr -6
+];
+
+// Stores b to $C000
+pub const STORE_ROUTINE: [u8; 5] = [
+    0x78,                         // ld a, b
+    0xEA, 0x00, 0xC0,             // ld [$C000], a
+    0xC9,                         // ret
+];
+
+// A 32KB rom without a mapper whose entry point runs the given program
+pub fn build_rom(program: &[u8]) -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    rom[0x100 .. 0x100 + program.len()].copy_from_slice(program);
+    return rom;
+}
+
+// Like build_rom, with a routine at $0200 for the program to call
+pub fn build_rom_with_routine(program: &[u8], routine: &[u8]) -> Vec<u8> {
+    let mut rom = build_rom(program);
+    rom[0x200 .. 0x200 + routine.len()].copy_from_slice(routine);
+    return rom;
+}
+
+// Like build_rom, with the given mapper and ram size codes in the header
+pub fn build_cartridge(cartridge_type: u8, ram_size_code: u8, program: &[u8]) -> Vec<u8> {
+    let mut rom = build_rom(program);
+    rom[0x147] = cartridge_type;
+    rom[0x149] = ram_size_code;
+    return rom;
+}
+
+pub fn load_rom(rom: &[u8]) -> Console {
+    let mut console = Console::new();
+    console.load(rom).unwrap();
+    console.reset();
+    return console;
+}
+
+pub fn load_program(program: &[u8]) -> Console {
+    return load_rom(&build_rom(program));
+}
+
+pub fn load_file(path: &str) -> Console {
+    return load_rom(&fs::read(path).unwrap());
+}
+
+// The counter with its store routine, the fixture of the debugging tools
+pub fn load_counter() -> Console {
+    return load_rom(&build_rom_with_routine(&COUNTER, &STORE_ROUTINE));
+}
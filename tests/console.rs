@@ -0,0 +1,188 @@
+use rust_webpack_template::{Console, EmulatorError, GameboyType, SCREEN_H, SCREEN_W};
+
+mod common;
+use common::{build_rom, load_file, load_rom};
+
+#[test]
+fn runs_frames_natively() {
+    let mut console = load_file("roms/opus5.gb");
+    assert_eq!(console.get_model(), GameboyType::CLASSIC);
+
+    let mut samples = 0;
+    for _i in 0 .. 60 {
+        console.run_frame().unwrap();
+        samples += console.take_sound().len();
+    }
+
+    assert_eq!(console.get_frame().len(), SCREEN_W * SCREEN_H * 4);
+
+    // A frame is 70224 cycles, at 44.1kHz that is ~738 stereo samples
+    assert!(samples > 60 * 1400 && samples < 60 * 1560, "unexpected sample count {}", samples);
+    assert!(console.take_sound().is_empty());
+}
+
+#[test]
+fn rejects_truncated_rom() {
+    let mut console = Console::new();
+    assert_eq!(console.load(&[0; 0x100]), Err(EmulatorError::TruncatedRom { expected: 0x150, actual: 0x100 }));
+
+    let mut rom = build_rom(&[]);
+    rom[0x148] = 0x01; // 64KB
+    assert_eq!(console.load(&rom), Err(EmulatorError::TruncatedRom { expected: 0x10000, actual: 0x8000 }));
+}
+
+#[test]
+fn reads_open_bus_past_short_roms() {
+    // 16KB with an unknown size code, jp $4000
+    let mut rom = build_rom(&[0xC3, 0x00, 0x40]);
+    rom.truncate(0x4000);
+    rom[0x148] = 0x09;
+
+    let mut console = load_rom(&rom);
+    assert_eq!(console.read_byte(0x4000), 0xFF);
+    console.run_frame().unwrap();
+}
+
+#[test]
+fn rejects_unsupported_mapper() {
+    let mut rom = build_rom(&[]);
+    rom[0x147] = 0xFC; // Pocket camera
+
+    let mut console = Console::new();
+    assert_eq!(console.load(&rom), Err(EmulatorError::UnsupportedMapper(0xFC)));
+}
+
+#[test]
+fn reports_illegal_opcode() {
+    let mut console = load_rom(&build_rom(&[0x00, 0xD3]));
+
+    assert_eq!(console.run_frame(), Err(EmulatorError::IllegalOpcode { opcode: 0xD3, address: 0x101 }));
+    assert_eq!(console.run_frame(), Ok(()));
+}
+
+#[test]
+fn reports_invalid_dma() {
+    // ld a, $E0; ldh [$51], a; xor a; ldh [$52], a; ldh [$55], a
+    let program = [0x3E, 0xE0, 0xE0, 0x51, 0xAF, 0xE0, 0x52, 0xE0, 0x55];
+
+    let mut console = load_rom(&build_rom(&program));
+
+    assert_eq!(console.run_frame(), Err(EmulatorError::InvalidDma { source: 0xE000 }));
+}
+
+#[test]
+fn restores_save_state() {
+    let mut console = load_file("roms/ttt.gb");
+    for _i in 0 .. 30 {
+        console.run_frame().unwrap();
+    }
+    let state = console.save_state();
+    assert_eq!(Console::state_rom_checksum(&state), Ok(console.rom_checksum()));
+
+    let mut expected_sound = Vec::new();
+    console.take_sound();
+    for _i in 0 .. 30 {
+        console.run_frame().unwrap();
+        expected_sound.extend(console.take_sound());
+    }
+    let expected_frame = console.get_frame().to_vec();
+
+    // Loading into a fresh console must give the same result as continuing
+    let mut restored = load_file("roms/ttt.gb");
+    restored.load_state(&state).unwrap();
+    let mut sound = Vec::new();
+    for _i in 0 .. 30 {
+        restored.run_frame().unwrap();
+        sound.extend(restored.take_sound());
+    }
+
+    assert!(restored.get_frame() == &expected_frame[..]);
+    assert_eq!(sound, expected_sound);
+}
+
+#[test]
+fn rejects_state_for_other_rom() {
+    let state = load_file("roms/ttt.gb").save_state();
+
+    let mut console = load_file("roms/opus5.gb");
+    let expected = console.rom_checksum();
+    let actual = Console::state_rom_checksum(&state).unwrap();
+    assert_eq!(console.load_state(&state), Err(EmulatorError::RomMismatch { expected, actual }));
+}
+
+#[test]
+fn rejects_corrupt_state() {
+    let mut console = load_file("roms/ttt.gb");
+    for _i in 0 .. 10 {
+        console.run_frame().unwrap();
+    }
+    let frame = console.get_frame().to_vec();
+
+    let mut state = console.save_state();
+    state[0] = b'X';
+    assert!(matches!(console.load_state(&state), Err(EmulatorError::InvalidState(_))));
+
+    // Cut off in the middle of the last section, the console must be left untouched
+    let state = load_file("roms/ttt.gb").save_state();
+    assert!(matches!(console.load_state(&state[.. state.len() - 4]), Err(EmulatorError::InvalidState(_))));
+    assert!(console.get_frame() == &frame[..]);
+
+    // A first section claiming 4GB, past the end of the address space on wasm32
+    let mut state = load_file("roms/ttt.gb").save_state();
+    state[14 .. 18].copy_from_slice(&[0xFF; 4]);
+    assert!(matches!(console.load_state(&state), Err(EmulatorError::InvalidState(_))));
+    assert!(console.get_frame() == &frame[..]);
+}
+
+// MBC1+RAM+BATTERY with 8KB of ram
+fn build_battery_rom(program: &[u8]) -> Vec<u8> {
+    return common::build_cartridge(0x03, 0x02, program);
+}
+
+#[test]
+fn exports_battery_ram() {
+    // ld a, $0A; ld [$0000], a; ld a, $42; ld [$A000], a; jr -2
+    let program = [0x3E, 0x0A, 0xEA, 0x00, 0x00, 0x3E, 0x42, 0xEA, 0x00, 0xA0, 0x18, 0xFE];
+
+    let mut console = load_rom(&build_battery_rom(&program));
+    assert!(console.has_battery());
+    assert!(!console.is_save_dirty());
+
+    console.run_frame().unwrap();
+    assert!(console.is_save_dirty());
+
+    let save = console.export_save();
+    assert_eq!(save.len(), 0x2000);
+    assert_eq!(save[0], 0x42);
+    assert!(!console.is_save_dirty());
+
+    let mut restored = load_rom(&build_battery_rom(&[0x18, 0xFE]));
+    restored.import_save(&save).unwrap();
+    assert!(!restored.is_save_dirty());
+    assert_eq!(restored.export_save(), save);
+}
+
+#[test]
+fn rejects_mismatched_save() {
+    let mut console = Console::new();
+    console.load(&build_battery_rom(&[])).unwrap();
+    assert_eq!(console.import_save(&[0; 0x800]), Err(EmulatorError::InvalidSave { expected: 0x2000, actual: 0x800 }));
+
+    // No battery, nothing to persist
+    console.load(&build_rom(&[])).unwrap();
+    assert!(!console.has_battery());
+    assert!(console.export_save().is_empty());
+    assert_eq!(console.import_save(&[0; 0x2000]), Err(EmulatorError::InvalidSave { expected: 0, actual: 0x2000 }));
+}
+
+#[test]
+fn ignores_saves_of_ram_without_battery() {
+    // MBC1+RAM, 8KB of ram that is lost at power off
+    let mut console = load_rom(&common::build_cartridge(0x02, 0x02, &[0x18, 0xFE]));
+    assert!(!console.has_battery());
+
+    let save = console.export_save();
+    assert!(save.is_empty());
+    assert_eq!(console.import_save(&save), Ok(()));
+    assert_eq!(console.import_save(&[0; 0x2000]), Err(EmulatorError::InvalidSave { expected: 0, actual: 0x2000 }));
+}
@@ -0,0 +1,281 @@
+use rust_webpack_template::{Button, CpuState, EmulatorError};
+
+mod common;
+use common::{build_rom, load_program, load_rom};
+
+// Sends A over the serial port and loops
+const SEND_A: [u8; 8] = [
+    0xE0, 0x01,                   // ldh [$01], a
+    0x3E, 0x81, 0xE0, 0x02,       // ld a, $81; ldh [$02], a
+    0x18, 0xFE,                   // jr -2
+];
+
+#[test]
+fn halt_bug_repeats_next_byte() {
+    let mut program = vec![
+        0xF3,                         // di
+        0x3E, 0x01, 0xE0, 0xFF,       // ld a, $01; ldh [$FF], a   IE = vblank
+        0xE0, 0x0F,                   // ldh [$0F], a               vblank pending
+        0xAF,                         // xor a
+        0x76,                         // halt
+        0x3C,                         // inc a, runs twice
+    ];
+    program.extend_from_slice(&SEND_A);
+
+    let mut console = load_program(&program);
+    console.run_frame().unwrap();
+    assert_eq!(console.take_serial_output(), vec![0x02]);
+}
+
+#[test]
+fn halt_waits_for_interrupt_without_ime() {
+    let mut program = vec![
+        0xF3,                         // di
+        0x3E, 0x01, 0xE0, 0xFF,       // ld a, $01; ldh [$FF], a   IE = vblank
+        0xAF, 0xE0, 0x0F,             // xor a; ldh [$0F], a
+        0x76,                         // halt
+        0x3C,                         // inc a
+    ];
+    program.extend_from_slice(&SEND_A);
+
+    let mut console = load_program(&program);
+    console.execute_ticks(20).unwrap();
+    assert_eq!(console.get_cpu_state(), CpuState::HALTED);
+
+    // Leaves halt on vblank without calling the handler
+    console.run_frame().unwrap();
+    console.run_frame().unwrap();
+    assert_eq!(console.get_cpu_state(), CpuState::RUNNING);
+    assert_eq!(console.take_serial_output(), vec![0x01]);
+}
+
+#[test]
+fn stop_waits_for_joypad() {
+    let mut program = vec![
+        0x3E, 0xE0, 0xE0, 0x00,       // ld a, $E0; ldh [$00], a   select the direction keys
+        0x10, 0x00,                   // stop
+        0x3E, 0x42,                   // ld a, $42
+    ];
+    program.extend_from_slice(&SEND_A);
+
+    let mut console = load_program(&program);
+    console.run_frame().unwrap();
+    assert_eq!(console.get_cpu_state(), CpuState::STOPPED);
+
+    // The system clock stands still, DIV does not count and the lcd is blank. Frames and samples
+    // keep coming for the host.
+    let div = console.read_byte(0xFF04);
+    console.take_sound();
+    console.run_frame().unwrap();
+    assert_eq!(console.read_byte(0xFF04), div);
+    assert!(console.get_frame().iter().all(|value| *value == 0xFF));
+    assert!(!console.take_sound().is_empty());
+
+    // Buttons on a row that is not selected don't pull a line low
+    console.press_button(Button::A);
+    console.run_frame().unwrap();
+    assert_eq!(console.get_cpu_state(), CpuState::STOPPED);
+    assert!(console.take_serial_output().is_empty());
+
+    console.press_button(Button::RIGHT);
+    console.run_frame().unwrap();
+    assert_eq!(console.get_cpu_state(), CpuState::RUNNING);
+    assert_eq!(console.take_serial_output(), vec![0x42]);
+}
+
+#[test]
+fn illegal_opcode_locks_up() {
+    let program = [
+        0x3E, 0x01, 0xE0, 0xFF,       // ld a, $01; ldh [$FF], a   IE = vblank
+        0xFB,                         // ei
+        0xD3,                         // illegal
+    ];
+
+    // Reported once, then the rest of the console keeps running and interrupts are not serviced
+    let mut console = load_program(&program);
+    assert_eq!(console.run_frame(), Err(EmulatorError::IllegalOpcode { opcode: 0xD3, address: 0x105 }));
+    for _i in 0 .. 3 {
+        console.run_frame().unwrap();
+    }
+    assert_eq!(console.get_cpu_state(), CpuState::LOCKED);
+
+    console.reset();
+    assert_eq!(console.get_cpu_state(), CpuState::RUNNING);
+}
+
+#[test]
+fn accesses_happen_at_their_m_cycle() {
+    // Too long to fit before the header, jr $0150 first
+    let mut program = vec![0x18, 0x4E];
+    program.resize(0x50, 0x00);
+    program.extend_from_slice(&[0xE0, 0x04]);       // ldh [$04], a    DIV is reset by the write in M3
+    program.extend_from_slice(&[0x00; 60]);
+    program.extend_from_slice(&[0xFA, 0x04, 0xFF]); // ld a, [$FF04]   read in M4
+    program.extend_from_slice(&SEND_A);
+
+    // 256 cycles pass between the write and the read, DIV has just ticked
+    let mut console = load_program(&program);
+    assert!(console.is_cycle_accurate());
+    console.run_frame().unwrap();
+    assert_eq!(console.take_serial_output(), vec![0x01]);
+
+    // Clocking after whole instructions moves both accesses to the start of their instruction
+    let mut console = load_program(&program);
+    console.set_cycle_accurate(false);
+    console.run_frame().unwrap();
+    assert_eq!(console.take_serial_output(), vec![0x00]);
+}
+
+#[test]
+fn calls_and_returns_access_the_stack_at_their_m_cycle() {
+    // The stack is DIV, call resets it with the low byte of the return address in M6
+    let program = [
+        0x31, 0x06, 0xFF,             // ld sp, $FF06
+        0xCD, 0x00, 0x02,             // call $0200
+    ];
+    let mut routine = vec![0x00; 59];
+    routine.extend_from_slice(&[0xFA, 0x04, 0xFF]); // ld a, [$FF04]   read 63 M-cycles later
+    routine.extend_from_slice(&SEND_A);
+
+    let mut console = load_rom(&common::build_rom_with_routine(&program, &routine));
+    console.run_frame().unwrap();
+    assert_eq!(console.take_serial_output(), vec![0x00]);
+
+    // ret z checks the condition in M2 and pops DIV in M3, TIMA is the high byte. Too long to
+    // fit before the header, jr $0150 first
+    let mut program = vec![0x18, 0x4E];
+    program.resize(0x50, 0x00);
+    program.extend_from_slice(&[
+        0x31, 0x04, 0xFF,             // ld sp, $FF04
+        0xAF,                         // xor a
+        0xE0, 0x07, 0xE0, 0x05,       // ldh [$07], a; ldh [$05], a   stop the timer at 0
+        0xE0, 0x04,                   // ldh [$04], a
+    ]);
+    program.extend_from_slice(&[0x00; 61]);
+    program.push(0xC8);               // ret z   pops DIV 64 M-cycles after the reset
+
+    // Returning to $0001 increments A once, $0000 twice
+    let mut rom = build_rom(&program);
+    rom[0x00 .. 0x02].copy_from_slice(&[0x3C, 0x3C]);
+    rom[0x02 .. 0x0A].copy_from_slice(&SEND_A);
+    let mut console = load_rom(&rom);
+    console.run_frame().unwrap();
+    assert_eq!(console.take_serial_output(), vec![0x01]);
+}
+
+// Requests vblank with IME=0, the vblank handler sends A
+fn build_interrupt_rom(program: &[u8]) -> Vec<u8> {
+    let mut setup = vec![
+        0xF3,                         // di
+        0x3E, 0x01, 0xE0, 0xFF,       // ld a, $01; ldh [$FF], a   IE = vblank
+        0xE0, 0x0F,                   // ldh [$0F], a               vblank pending
+    ];
+    setup.extend_from_slice(program);
+    let mut rom = build_rom(&setup);
+    rom[0x40 .. 0x48].copy_from_slice(&SEND_A);
+    return rom;
+}
+
+#[test]
+fn ei_takes_effect_after_next_instruction() {
+    let mut program = vec![
+        0xFB,                         // ei
+        0x3E, 0x55,                   // ld a, $55, runs before the interrupt
+        0x3E, 0x66,                   // ld a, $66
+    ];
+    program.extend_from_slice(&SEND_A);
+
+    let mut console = load_rom(&build_interrupt_rom(&program));
+    console.run_frame().unwrap();
+    assert_eq!(console.take_serial_output(), vec![0x55]);
+}
+
+#[test]
+fn di_takes_effect_immediately() {
+    let mut program = vec![
+        0xFB, 0xF3,                   // ei; di
+        0x3E, 0x55,                   // ld a, $55
+        0x3E, 0x66,                   // ld a, $66
+    ];
+    program.extend_from_slice(&SEND_A);
+
+    let mut console = load_rom(&build_interrupt_rom(&program));
+    console.run_frame().unwrap();
+    assert_eq!(console.take_serial_output(), vec![0x66]);
+}
+
+#[test]
+fn ei_before_halt_returns_to_halt() {
+    let mut program = vec![
+        0x06, 0x00,                   // ld b, 0
+        0xFB, 0x76,                   // ei; halt
+        0x78,                         // ld a, b
+    ];
+    program.extend_from_slice(&SEND_A);
+
+    // The pending vblank is serviced right away and returns to the HALT, which waits for the
+    // next vblank before continuing
+    let mut rom = build_interrupt_rom(&program);
+    rom[0x40 .. 0x42].copy_from_slice(&[0x04, 0xD9]); // inc b; reti
+    let mut console = load_rom(&rom);
+    console.run_frame().unwrap();
+    console.run_frame().unwrap();
+    assert_eq!(console.take_serial_output(), vec![0x02]);
+}
+
+#[test]
+fn ie_push_cancels_dispatch() {
+    // From $02xx the high byte of pc is pushed into IE at $FFFF and disables vblank
+    let mut rom = build_interrupt_rom(&[
+        0x31, 0x00, 0x00,             // ld sp, $0000
+        0xC3, 0x00, 0x02,             // jp $0200
+    ]);
+    rom[0x200 .. 0x203].copy_from_slice(&[0xFB, 0x00, 0x00]); // ei; nop; nop
+    rom[0x00 .. 0x02].copy_from_slice(&[0x3E, 0xCC]);         // ld a, $CC
+    rom[0x02 .. 0x0A].copy_from_slice(&SEND_A);
+
+    let mut console = load_rom(&rom);
+    console.run_frame().unwrap();
+    assert_eq!(console.take_serial_output(), vec![0xCC]);
+
+    // From $01xx IE stays $01 and the vblank handler runs
+    let rom = build_interrupt_rom(&[
+        0x31, 0x00, 0x00,             // ld sp, $0000
+        0x3E, 0x42,                   // ld a, $42
+        0xFB, 0x00,                   // ei; nop
+        0x18, 0xFE,                   // jr -2
+    ]);
+    let mut console = load_rom(&rom);
+    console.run_frame().unwrap();
+    assert_eq!(console.take_serial_output(), vec![0x42]);
+}
+
+#[test]
+fn dispatch_pushes_after_two_wait_states() {
+    // The stack is DIV, the dispatch resets it with the low byte of pc in M4
+    let mut rom = build_interrupt_rom(&[
+        0x31, 0x06, 0xFF,             // ld sp, $FF06
+        0xFB, 0x00,                   // ei; nop
+    ]);
+    let mut handler = vec![0x00; 58];
+    handler.extend_from_slice(&[0xFA, 0x04, 0xFF]); // ld a, [$FF04]   read 63 M-cycles later
+    handler.extend_from_slice(&SEND_A);
+    rom[0x40 .. 0x40 + handler.len()].copy_from_slice(&handler);
+
+    let mut console = load_rom(&rom);
+    console.run_frame().unwrap();
+    assert_eq!(console.take_serial_output(), vec![0x00]);
+}
+
+#[test]
+fn if_upper_bits_read_as_one() {
+    let mut program = vec![
+        0xAF, 0xE0, 0x0F,             // xor a; ldh [$0F], a
+        0xF0, 0x0F,                   // ldh a, [$0F]
+    ];
+    program.extend_from_slice(&SEND_A);
+
+    let mut console = load_program(&program);
+    console.run_frame().unwrap();
+    assert_eq!(console.take_serial_output(), vec![0xE0]);
+}
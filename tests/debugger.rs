@@ -0,0 +1,125 @@
+use rust_webpack_template::{Console, Debugger, Breakpoint, Watchpoint, Access, Condition, PauseReason, EmulatorError};
+
+mod common;
+use common::{load_counter, load_program};
+
+#[test]
+fn pauses_at_breakpoints() {
+    let mut console = load_counter();
+    let mut debugger = Debugger::new();
+
+    let condition = Condition::parse("B == $03").unwrap();
+    debugger.add_breakpoint(Breakpoint { address: 0x200, bank: None, condition: Some(condition) });
+    assert_eq!(debugger.run_frame(&mut console), Ok(Some(PauseReason::Breakpoint { address: 0x200, bank: 0 })));
+    assert_eq!((console.get_registers().pc, console.get_registers().b), (0x200, 3));
+    assert_eq!(debugger.execute_ticks(&mut console, 1000), Ok(None));
+    assert!(debugger.remove_breakpoint(0x200, None));
+
+    // Resuming runs the instruction it paused at
+    debugger.add_breakpoint(Breakpoint::new(0x102));
+    assert_eq!(debugger.execute_ticks(&mut console, 1000), Ok(Some(PauseReason::Breakpoint { address: 0x102, bank: 0 })));
+    let b = console.get_registers().b;
+    assert_eq!(debugger.execute_ticks(&mut console, 1000), Ok(Some(PauseReason::Breakpoint { address: 0x102, bank: 0 })));
+    assert_eq!(console.get_registers().b, b + 1);
+
+    // Bank 0 is always mapped at $0102
+    assert!(debugger.remove_breakpoint(0x102, None));
+    debugger.add_breakpoint(Breakpoint { address: 0x102, bank: Some(1), condition: None });
+    assert_eq!(debugger.execute_ticks(&mut console, 1000), Ok(None));
+
+    assert_eq!(Condition::parse("q == 1"), Err(EmulatorError::InvalidCondition(String::from("q == 1"))));
+}
+
+#[test]
+fn pauses_at_breakpoints_on_loops_to_themselves() {
+    // nop; jr -2
+    let mut console = load_program(&[0x00, 0x18, 0xFE]);
+    let mut debugger = Debugger::new();
+
+    debugger.add_breakpoint(Breakpoint::new(0x101));
+    for _i in 0 .. 3 {
+        assert_eq!(debugger.execute_ticks(&mut console, 10), Ok(Some(PauseReason::Breakpoint { address: 0x101, bank: 0 })));
+        assert_eq!(console.get_registers().pc, 0x101);
+    }
+}
+
+#[test]
+fn pauses_on_watchpoints() {
+    let mut console = load_counter();
+    let mut debugger = Debugger::new();
+
+    debugger.add_watchpoint(Watchpoint::new(0xC000, Access::READ));
+    debugger.add_watchpoint(Watchpoint::new(0xC000, Access::WRITE));
+    let reason = PauseReason::Watchpoint { address: 0xC000, access: Access::WRITE, value: 0x01 };
+    assert_eq!(debugger.execute_ticks(&mut console, 1000), Ok(Some(reason)));
+    assert_eq!(console.get_registers().pc, 0x204);
+
+    debugger.clear();
+    debugger.add_watchpoint(Watchpoint { start: 0x200, end: 0x204, access: Access::EXECUTE });
+    let reason = PauseReason::Watchpoint { address: 0x200, access: Access::EXECUTE, value: 0x78 };
+    assert_eq!(debugger.execute_ticks(&mut console, 1000), Ok(Some(reason)));
+    assert_eq!(console.get_registers().pc, 0x200);
+}
+
+#[test]
+fn steps_over_into_and_out_of_calls() {
+    let mut console = load_counter();
+    let mut debugger = Debugger::new();
+    let pc_after = |debugger: &mut Debugger, console: &mut Console| {
+        assert_eq!(debugger.execute_ticks(console, 1000), Ok(Some(PauseReason::Step)));
+        return console.get_registers().pc;
+    };
+
+    debugger.run_to(0x103, None);
+    assert_eq!(pc_after(&mut debugger, &mut console), 0x103);
+    debugger.step_over(&console);
+    assert_eq!(pc_after(&mut debugger, &mut console), 0x106);
+    assert_eq!(console.read_byte(0xC000), 0x01);
+
+    debugger.step_into();
+    assert_eq!(pc_after(&mut debugger, &mut console), 0x102);
+    debugger.step_into();
+    assert_eq!(pc_after(&mut debugger, &mut console), 0x103);
+    debugger.step_into();
+    assert_eq!(pc_after(&mut debugger, &mut console), 0x200);
+    debugger.step_out(&console);
+    assert_eq!(pc_after(&mut debugger, &mut console), 0x106);
+    assert_eq!(console.read_byte(0xC000), 0x02);
+}
+
+#[test]
+fn pauses_on_lockup() {
+    let mut console = load_program(&[0x00, 0xD3]);
+    let mut debugger = Debugger::new();
+    assert_eq!(debugger.run_frame(&mut console), Ok(Some(PauseReason::LockedUp)));
+    assert_eq!(console.get_registers().pc, 0x101);
+}
+
+const SYMBOLS: &str = "; File generated by rgblink
+00:0100 Start
+00:0102 Start.loop
+00:0200 Store
+00:c000 wCount
+";
+
+#[test]
+fn uses_symbols() {
+    let mut console = load_counter();
+    console.load_symbols(SYMBOLS).unwrap();
+    let mut debugger = Debugger::new();
+
+    debugger.add_symbol_breakpoint(&console, "Store", None).unwrap();
+    assert_eq!(debugger.add_symbol_breakpoint(&console, "Load", None), Err(EmulatorError::UnknownSymbol(String::from("Load"))));
+    assert_eq!(debugger.run_frame(&mut console), Ok(Some(PauseReason::Breakpoint { address: 0x200, bank: 0 })));
+
+    assert_eq!(debugger.backtrace(&console), vec!["#0 Store", "#1 Start.loop+$01"]);
+    assert!(console.trace_line().ends_with(" Store ld a, b"));
+    assert_eq!(console.disassemble_at(0x103).mnemonic, "call Store");
+    assert_eq!(console.disassemble_at(0x201).mnemonic, "ld [wCount], a");
+
+    // The frame is popped by the return
+    debugger.step_out(&console);
+    assert_eq!(debugger.execute_ticks(&mut console, 1000), Ok(Some(PauseReason::Step)));
+    assert!(debugger.call_stack().is_empty());
+    assert_eq!(debugger.backtrace(&console), vec!["#0 Start.loop+$04"]);
+}
@@ -0,0 +1,62 @@
+mod common;
+use common::load_rom;
+
+fn disassemble(program: &[u8]) -> Vec<String> {
+    let mut rom = vec![0; 0x8000];
+    rom[0x150 .. 0x150 + program.len()].copy_from_slice(program);
+    let console = load_rom(&rom);
+    return console.disassemble(0x150, 0x150 + program.len() as u16).into_iter().map(|i| i.mnemonic).collect();
+}
+
+#[test]
+fn decodes_operands() {
+    let program = [
+        0x00,                         // nop
+        0x01, 0x34, 0x12,             // ld bc, $1234
+        0x22, 0x3A,                   // ld [hl+], a; ld a, [hl-]
+        0x36, 0x7F,                   // ld [hl], $7F
+        0x20, 0xFE,                   // jr nz, -2
+        0xE0, 0x44, 0xF2,             // ldh [$FF44], a; ldh a, [c]
+        0x08, 0x00, 0xC0,             // ld [$C000], sp
+        0xE8, 0xFD, 0xF8, 0x05,       // add sp, -3; ld hl, sp+5
+        0x96, 0xFE, 0x90,             // sub a, [hl]; cp a, $90
+        0xC4, 0x00, 0x40, 0xFF,       // call nz, $4000; rst $38
+        0x10, 0x00, 0x76,             // stop; halt
+    ];
+    assert_eq!(disassemble(&program), vec![
+        "nop", "ld bc, $1234", "ld [hl+], a", "ld a, [hl-]", "ld [hl], $7F", "jr nz, $0158",
+        "ldh [$FF44], a", "ldh a, [c]", "ld [$C000], sp", "add sp, -$03", "ld hl, sp+$05",
+        "sub a, [hl]", "cp a, $90", "call nz, $4000", "rst $38", "stop", "halt",
+    ]);
+}
+
+#[test]
+fn decodes_prefixed_and_illegal_opcodes() {
+    let program = [
+        0xCB, 0x37, 0xCB, 0x7E,       // swap a; bit 7, [hl]
+        0xCB, 0x80, 0xCB, 0xFF,       // res 0, b; set 7, a
+        0xCB, 0x1A,                   // rr d
+        0xD3, 0xFD,                   // illegal
+    ];
+    assert_eq!(disassemble(&program), vec![
+        "swap a", "bit 7, [hl]", "res 0, b", "set 7, a", "rr d", "db $D3", "db $FD",
+    ]);
+}
+
+#[test]
+fn follows_the_mapped_rom_bank() {
+    // MBC1 with 4 banks, selects bank 2 and waits
+    let mut rom = vec![0; 0x10000];
+    rom[0x147] = 0x01;
+    rom[0x148] = 0x01;
+    rom[0x100 .. 0x107].copy_from_slice(&[0x3E, 0x02, 0xEA, 0x00, 0x20, 0x18, 0xFE]);
+    rom[0x8000 .. 0x8003].copy_from_slice(&[0xCD, 0x50, 0x01]);
+
+    let mut console = load_rom(&rom);
+    assert_eq!(console.disassemble_at(0x4000).to_string(), "01:4000  00        nop");
+
+    console.execute_ticks(100).unwrap();
+    let instruction = console.disassemble_at(0x4000);
+    assert_eq!((instruction.bank, instruction.bytes.clone()), (2, vec![0xCD, 0x50, 0x01]));
+    assert_eq!(instruction.to_string(), "02:4000  CD 50 01  call $0150");
+}
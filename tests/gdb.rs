@@ -0,0 +1,75 @@
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+use rust_webpack_template::GdbStub;
+
+mod common;
+
+// Sends a packet and returns the reply, the way gdb does
+fn request(stream: &mut TcpStream, data: &str) -> String {
+    let checksum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+    stream.write_all(format!("${}#{:02x}", data, checksum).as_bytes()).unwrap();
+
+    // The acknowledgement, then the reply
+    let mut byte = [0; 2];
+    stream.read_exact(&mut byte).unwrap();
+    assert_eq!(&byte, b"+$");
+
+    let mut reply = Vec::new();
+    let mut byte = [0];
+    loop {
+        stream.read_exact(&mut byte).unwrap();
+        match byte[0] {
+            b'#' => break,
+            byte => reply.push(byte),
+        }
+    }
+    let mut checksum = [0; 2];
+    stream.read_exact(&mut checksum).unwrap();
+    stream.write_all(b"+").unwrap();
+    return String::from_utf8(reply).unwrap();
+}
+
+#[test]
+fn serves_gdb_sessions() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+
+    let client = thread::spawn(move || {
+        let mut stream = TcpStream::connect(address).unwrap();
+        let mut replies = Vec::new();
+        for packet in ["qSupported:swbreak+", "?", "qXfer:features:read:target.xml:0,15", "g", "Z0,200,1", "c", "p5",
+                       "Z2,c000,1", "c", "m c000,1", "z2,c000,1", "M c001,2:abcd", "m c000,3", "P1=3412", "s", "g", "m 0,ffff", "D"].iter() {
+            replies.push(request(&mut stream, &packet.replace(' ', "")));
+        }
+        return replies;
+    });
+
+    let mut console = common::load_counter();
+
+    let mut stub = GdbStub::accept(&listener).unwrap();
+    stub.serve(&mut console).unwrap();
+
+    let replies = client.join().unwrap();
+    assert!(replies[0].contains("qXfer:features:read+"));
+    assert_eq!(replies[1], "S05");
+    assert_eq!(replies[2], "m<?xml version=\"1.0\"?>");
+    // af, bc, de, hl, sp, pc after the boot rom
+    assert_eq!(replies[3], "b0011300d8004d01feff0001");
+
+    // The breakpoint at the routine, then the write of b to $C000
+    assert_eq!(replies[4 .. 7], ["OK", "T05swbreak:;", "0002"]);
+    assert_eq!(replies[7 .. 10], ["OK", "T05watch:c000;", "01"]);
+    assert_eq!(replies[10 .. 13], ["OK", "OK", "01abcd"]);
+
+    // bc is written and the step runs ret
+    assert_eq!(replies[13 .. 15], ["OK", "S05"]);
+    assert_eq!(&replies[15][4 .. 8], "3412");
+    assert_eq!(&replies[15][20 .. 24], "0601");
+
+    // Reads are cut short to fit the packet size
+    assert!(replies[0].contains("PacketSize=1000;"));
+    assert_eq!(replies[16].len(), 0x7FF * 2);
+    assert_eq!(replies[17], "OK");
+}
@@ -0,0 +1,148 @@
+use std::net::TcpListener;
+use std::thread;
+
+use rust_webpack_template::{LinkedPair, TcpLink, DEFAULT_SYNC_WINDOW};
+
+mod common;
+use common::{build_rom, load_rom};
+
+fn load_pair(first: &[u8], second: &[u8]) -> LinkedPair {
+    let mut pair = LinkedPair::new();
+    pair.console_mut(0).load(first).unwrap();
+    pair.console_mut(1).load(second).unwrap();
+    pair.reset();
+    return pair;
+}
+
+// Sends $55 on the internal clock, echoes the byte it received and then sends the serial bit of IF
+const MASTER_PROGRAM: [u8; 46] = [
+    0x00, 0x00, 0x00, 0x00,       // nop, gives the peer time to start waiting
+    0x3E, 0x55, 0xE0, 0x01,       // ld a, $55; ldh [$01], a
+    0x3E, 0x81, 0xE0, 0x02,       // ld a, $81; ldh [$02], a
+    0xF0, 0x02, 0xCB, 0x7F,       // ldh a, [$02]; bit 7, a
+    0x20, 0xFA,                   // jr nz, -6
+    0xF0, 0x0F, 0xE6, 0x08, 0x47, // ldh a, [$0F]; and $08; ld b, a
+    0xF0, 0x01, 0xE0, 0x01,       // ldh a, [$01]; ldh [$01], a
+    0x3E, 0x81, 0xE0, 0x02,       // ld a, $81; ldh [$02], a
+    0xF0, 0x02, 0xCB, 0x7F,       // ldh a, [$02]; bit 7, a
+    0x20, 0xFA,                   // jr nz, -6
+    0x78, 0xE0, 0x01,             // ld a, b; ldh [$01], a
+    0x3E, 0x81, 0xE0, 0x02,       // ld a, $81; ldh [$02], a
+    0x18, 0xFE,                   // jr -2
+];
+
+// Waits with $66 on the external clock, then waits once more with the byte it received
+const SLAVE_PROGRAM: [u8; 24] = [
+    0x3E, 0x66, 0xE0, 0x01,       // ld a, $66; ldh [$01], a
+    0x3E, 0x80, 0xE0, 0x02,       // ld a, $80; ldh [$02], a
+    0xF0, 0x02, 0xCB, 0x7F,       // ldh a, [$02]; bit 7, a
+    0x20, 0xFA,                   // jr nz, -6
+    0xF0, 0x01, 0xE0, 0x01,       // ldh a, [$01]; ldh [$01], a
+    0x3E, 0x80, 0xE0, 0x02,       // ld a, $80; ldh [$02], a
+    0x18, 0xFE,                   // jr -2
+];
+
+fn master_rom() -> Vec<u8> {
+    return build_rom(&MASTER_PROGRAM);
+}
+
+#[test]
+fn exchanges_bytes_between_consoles() {
+    let mut pair = load_pair(&master_rom(), &build_rom(&SLAVE_PROGRAM));
+    pair.run_frame().unwrap();
+
+    // The third byte finds nobody waiting and reads 1s
+    assert_eq!(pair.console_mut(0).take_serial_output(), vec![0x55, 0x66, 0x08]);
+    assert_eq!(pair.console_mut(1).take_serial_output(), vec![0x66, 0x55]);
+}
+
+#[test]
+fn runs_in_lockstep() {
+    let mut pair = load_pair(&master_rom(), &build_rom(&SLAVE_PROGRAM));
+
+    // A transfer at 8192Hz takes 4096 cycles, the second one has not finished yet
+    pair.execute_cycles(6000).unwrap();
+    assert_eq!(pair.console_mut(0).take_serial_output(), vec![0x55, 0x66]);
+    assert_eq!(pair.console_mut(1).take_serial_output(), vec![0x66, 0x55]);
+
+    pair.reset();
+    pair.execute_cycles(100).unwrap();
+    assert_eq!(pair.console_mut(0).take_serial_output(), vec![0x55]);
+    assert_eq!(pair.console_mut(1).take_serial_output(), vec![0x66]);
+}
+
+#[test]
+fn exchanges_bytes_over_tcp() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+
+    // Consoles are not Send, each end builds its own
+    let slave = thread::spawn(move || {
+        let mut link = TcpLink::connect(address, 512).unwrap();
+        let mut console = load_rom(&build_rom(&SLAVE_PROGRAM));
+        link.attach(&mut console);
+        link.run_frame(&mut console).unwrap();
+        return (link.is_master(), link.sync_window(), console.take_serial_output());
+    });
+
+    let mut link = TcpLink::accept(&listener, DEFAULT_SYNC_WINDOW).unwrap();
+    let mut console = load_rom(&master_rom());
+    link.attach(&mut console);
+    link.run_frame(&mut console).unwrap();
+
+    let (slave_master, slave_window, slave_output) = slave.join().unwrap();
+    assert_ne!(link.is_master(), slave_master);
+    assert_eq!(link.sync_window(), slave_window);
+    assert_eq!(console.take_serial_output(), vec![0x55, 0x66, 0x08]);
+    assert_eq!(slave_output, vec![0x66, 0x55]);
+}
+
+#[test]
+fn queues_fast_bytes_over_tcp() {
+    // Three bytes on the CGB fast clock, 128 cycles each and 160 apart to let the peer get ready
+    // again. They all start in the first window, before either end has seen the fast clock.
+    let mut program = Vec::new();
+    for byte in 1 ..= 3 {
+        program.extend_from_slice(&[
+            0x3E, byte, 0xE0, 0x01,       // ld a, byte; ldh [$01], a
+            0x3E, 0x83, 0xE0, 0x02,       // ld a, $83; ldh [$02], a
+            0xF0, 0x02, 0xCB, 0x7F,       // ldh a, [$02]; bit 7, a
+            0x20, 0xFA,                   // jr nz, -6
+            0x06, 0x0A,                   // ld b, 10
+            0x05, 0x20, 0xFD,             // dec b; jr nz, -3
+        ]);
+    }
+    program.extend_from_slice(&[0x18, 0xFE]);
+    let mut master = build_rom(&program);
+    master[0x143] = 0x80;
+
+    // Keeps waiting on the external clock, sending back the byte it received last
+    let slave_program = [
+        0x3E, 0x66, 0xE0, 0x01,       // ld a, $66; ldh [$01], a
+        0x3E, 0x80, 0xE0, 0x02,       // ld a, $80; ldh [$02], a
+        0xF0, 0x02, 0xCB, 0x7F,       // ldh a, [$02]; bit 7, a
+        0x20, 0xFA,                   // jr nz, -6
+        0x18, 0xF4,                   // jr -12
+    ];
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let slave = thread::spawn(move || {
+        let mut link = TcpLink::connect(address, DEFAULT_SYNC_WINDOW).unwrap();
+        let mut console = load_rom(&build_rom(&slave_program));
+        link.attach(&mut console);
+        link.run_frame(&mut console).unwrap();
+        return (console.take_serial_output(), console.read_byte(0xFF01));
+    });
+
+    let mut link = TcpLink::accept(&listener, DEFAULT_SYNC_WINDOW).unwrap();
+    let mut console = load_rom(&master);
+    link.attach(&mut console);
+    link.run_frame(&mut console).unwrap();
+
+    assert_eq!(console.take_serial_output(), vec![0x01, 0x02, 0x03]);
+    // Each byte received is sent back with the next one, the last is left in SB
+    let (slave_output, slave_data) = slave.join().unwrap();
+    assert_eq!(slave_output, vec![0x66, 0x01, 0x02]);
+    assert_eq!(slave_data, 0x03);
+}
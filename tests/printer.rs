@@ -0,0 +1,114 @@
+use rust_webpack_template::{Printer, SerialDevice, PRINT_WIDTH};
+
+// Sends a packet the way a game does, returns the device id and status bytes the printer answered
+fn send_packet(printer: &mut Printer, command: u8, compression: u8, data: &[u8]) -> (u8, u8) {
+    let mut packet = vec![0x88, 0x33, command, compression, data.len() as u8, (data.len() >> 8) as u8];
+    packet.extend_from_slice(data);
+    let checksum = packet[2 ..].iter().fold(0u16, |sum, byte| sum.wrapping_add(*byte as u16));
+    packet.extend_from_slice(&checksum.to_le_bytes());
+
+    for byte in packet {
+        assert_eq!(printer.transfer(byte), 0x00);
+    }
+    return (printer.transfer(0x00), printer.transfer(0x00));
+}
+
+// Two rows of tiles, every pixel of the first row has color 1 and of the second row color 3
+fn band() -> Vec<u8> {
+    let mut data = Vec::new();
+    for _tile in 0 .. 20 {
+        data.extend_from_slice(&[0xFF, 0x00].repeat(8));
+    }
+    for _tile in 0 .. 20 {
+        data.extend_from_slice(&[0xFF, 0xFF].repeat(8));
+    }
+    return data;
+}
+
+#[test]
+fn prints_image_data() {
+    let mut printer = Printer::new();
+    let queue = printer.queue();
+
+    assert_eq!(send_packet(&mut printer, 0x01, 0, &[]), (0x81, 0x00));
+    assert_eq!(send_packet(&mut printer, 0x04, 0, &band()), (0x81, 0x08));
+    assert_eq!(send_packet(&mut printer, 0x04, 0, &band()), (0x81, 0x08));
+    assert_eq!(send_packet(&mut printer, 0x04, 0, &[]), (0x81, 0x08));
+    assert!(queue.is_empty());
+
+    // One sheet, margins of 1 and 3, palette mapping color 1 to dark and 3 to black
+    assert_eq!(send_packet(&mut printer, 0x02, 0, &[0x01, 0x13, 0xE8, 0x40]), (0x81, 0x02));
+    let job = queue.take_job().unwrap();
+    assert!(queue.take_job().is_none());
+
+    assert_eq!((job.width, job.height), (PRINT_WIDTH, 32));
+    assert_eq!((job.sheets, job.margin_before, job.margin_after), (1, 1, 3));
+    assert_eq!(job.pixels.len(), PRINT_WIDTH * 32 * 4);
+    assert_eq!(job.pixels[0 .. 4], [96, 96, 96, 0xFF]);
+    assert_eq!(job.pixels[(8 * PRINT_WIDTH + 159) * 4 .. (8 * PRINT_WIDTH + 160) * 4], [0, 0, 0, 0xFF]);
+
+    let png = job.to_png().unwrap();
+    assert_eq!(png[1 .. 4], *b"PNG");
+
+    // The busy flag clears after a few status inquiries
+    let statuses = (0 .. 5).map(|_i| send_packet(&mut printer, 0x0F, 0, &[]).1).collect::<Vec<u8>>();
+    assert_eq!(statuses, vec![0x02, 0x02, 0x02, 0x00, 0x00]);
+}
+
+#[test]
+fn decompresses_data() {
+    let mut printer = Printer::new();
+    let queue = printer.queue();
+
+    // 320 bytes of $FF, $00 pairs as literals and 320 bytes of $FF as repeats
+    let mut data = Vec::new();
+    for _i in 0 .. 5 {
+        data.push(0x3F);
+        data.extend_from_slice(&[0xFF, 0x00].repeat(32));
+    }
+    for _i in 0 .. 2 {
+        data.extend_from_slice(&[0xFF, 0xFF]);
+    }
+    data.extend_from_slice(&[0xBC, 0xFF]);
+
+    send_packet(&mut printer, 0x01, 0, &[]);
+    send_packet(&mut printer, 0x04, 1, &data);
+    send_packet(&mut printer, 0x02, 0, &[0x01, 0x00, 0x00, 0x40]);
+
+    // A palette of 0 prints with the default $E4
+    let job = queue.take_job().unwrap();
+    assert_eq!(job.palette, 0xE4);
+    assert_eq!(job.height, 16);
+    assert_eq!(job.pixels[0 .. 4], [192, 192, 192, 0xFF]);
+    assert_eq!(job.pixels[(15 * PRINT_WIDTH) * 4 .. (15 * PRINT_WIDTH + 1) * 4], [0, 0, 0, 0xFF]);
+}
+
+#[test]
+fn reports_checksum_errors() {
+    let mut printer = Printer::new();
+    let queue = printer.queue();
+
+    let packet = [0x88, 0x33, 0x02, 0x00, 0x04, 0x00, 0x01, 0x00, 0xE4, 0x40, 0x00, 0x00];
+    for byte in packet.iter() {
+        printer.transfer(*byte);
+    }
+    assert_eq!((printer.transfer(0x00), printer.transfer(0x00)), (0x81, 0x01));
+    assert!(queue.is_empty());
+
+    // Garbage between packets is skipped and the next valid packet clears the error
+    printer.transfer(0x12);
+    assert_eq!(send_packet(&mut printer, 0x0F, 0, &[]), (0x81, 0x00));
+}
+
+#[test]
+fn skips_empty_prints() {
+    let mut printer = Printer::new();
+    let queue = printer.queue();
+
+    // Less than a row of tiles doesn't make a job and the printer does not turn busy
+    send_packet(&mut printer, 0x01, 0, &[]);
+    assert_eq!(send_packet(&mut printer, 0x02, 0, &[0x01, 0x00, 0xE4, 0x40]), (0x81, 0x00));
+    send_packet(&mut printer, 0x04, 0, &[0xFF; 16]);
+    assert_eq!(send_packet(&mut printer, 0x02, 0, &[0x01, 0x00, 0xE4, 0x40]), (0x81, 0x08));
+    assert!(queue.is_empty());
+}
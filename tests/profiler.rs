@@ -0,0 +1,51 @@
+use rust_webpack_template::{Hotspot, COVERAGE_CODE, COVERAGE_DATA};
+
+mod common;
+
+// The counter with a read of a byte of data before the call
+const PROGRAM: [u8; 11] = [
+    0x06, 0x00,                   // ld b, 0
+    0x04,                         // inc b
+    0xFA, 0x00, 0x03,             // ld a, [$0300]
+    0xCD, 0x00, 0x02,             // call $0200
+    0x18, 0xF7,                   // jr -9
+];
+
+const SYMBOLS: &str = "00:0100 Start
+00:0200 Store
+";
+
+#[test]
+fn profiles_hotspots_and_coverage() {
+    let rom = common::build_rom_with_routine(&PROGRAM, &common::STORE_ROUTINE);
+    let mut console = common::load_rom(&rom);
+    console.load_symbols(SYMBOLS).unwrap();
+
+    // ld b, 0 and 10 times the loop
+    console.start_profiling();
+    console.execute_ticks(71).unwrap();
+    let profiler = console.profiler().unwrap();
+    assert_eq!(profiler.total_cycles(), 8 + 10 * (4 + 16 + 24 + 4 + 16 + 16 + 12));
+
+    // The call is the slowest, ties are in address order
+    let hotspots = profiler.hotspots();
+    assert_eq!(hotspots[0], Hotspot { bank: 0, address: 0x106, instructions: 10, cycles: 240 });
+    let addresses: Vec<u16> = hotspots.iter().map(|hotspot| hotspot.address).collect();
+    assert_eq!(addresses, vec![0x106, 0x103, 0x201, 0x204, 0x109, 0x102, 0x200, 0x100]);
+
+    let coverage = profiler.coverage();
+    assert_eq!(coverage.len(), rom.len());
+    assert!(coverage[0x100 .. 0x10B].iter().all(|flags| *flags == COVERAGE_CODE));
+    assert!(coverage[0x200 .. 0x205].iter().all(|flags| *flags == COVERAGE_CODE));
+    assert_eq!(coverage[0x300], COVERAGE_DATA);
+    assert_eq!(coverage.iter().filter(|flags| **flags != 0).count(), 11 + 5 + 1);
+
+    let report = console.profile_report(2);
+    let lines: Vec<&str> = report.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[1], format!("00:0106  {:>12}  {:>12}  Start+$06", 240, 10));
+    assert_eq!(lines[2], format!("00:0103  {:>12}  {:>12}  Start+$03", 160, 10));
+
+    assert!(console.stop_profiling().is_some());
+    assert_eq!(console.profile_report(2), "");
+}
@@ -0,0 +1,158 @@
+use rust_webpack_template::Console;
+
+mod common;
+
+const NR10: u16 = 0xFF10;
+const NR12: u16 = 0xFF12;
+const NR13: u16 = 0xFF13;
+const NR14: u16 = 0xFF14;
+const NR21: u16 = 0xFF16;
+const NR22: u16 = 0xFF17;
+const NR23: u16 = 0xFF18;
+const NR24: u16 = 0xFF19;
+const NR30: u16 = 0xFF1A;
+const NR34: u16 = 0xFF1E;
+const NR50: u16 = 0xFF24;
+const NR51: u16 = 0xFF25;
+const NR52: u16 = 0xFF26;
+
+// Waits in a loop with the apu powered off and on again, every channel starts stopped
+fn load_console() -> Console {
+    return power_cycle(common::load_program(&[0x18, 0xFE]));
+}
+
+fn load_color_console() -> Console {
+    let mut rom = common::build_rom(&[0x18, 0xFE]);
+    rom[0x143] = 0x80;
+    return power_cycle(common::load_rom(&rom));
+}
+
+fn power_cycle(mut console: Console) -> Console {
+    console.write_byte(NR52, 0x00);
+    console.write_byte(NR52, 0x80);
+    return console;
+}
+
+fn loudest_sample(console: &mut Console) -> f32 {
+    return console.take_sound().iter().fold(0.0, |loudest: f32, sample| loudest.max(sample.abs()));
+}
+
+#[test]
+fn length_counter_stops_channels() {
+    let mut console = load_console();
+    assert_eq!(console.read_byte(NR52), 0xF0);
+
+    // 16 steps of the 256Hz length clock, about 3.7 frames
+    console.write_byte(NR21, 0x30);
+    console.write_byte(NR22, 0xF0);
+    console.write_byte(NR24, 0xC0);
+    assert_eq!(console.read_byte(NR52), 0xF2);
+
+    console.run_frame().unwrap();
+    assert_eq!(console.read_byte(NR52), 0xF2);
+
+    for _i in 0 .. 4 {
+        console.run_frame().unwrap();
+    }
+    assert_eq!(console.read_byte(NR52), 0xF0);
+
+    // Without the length enabled it plays on
+    console.write_byte(NR24, 0x80);
+    for _i in 0 .. 5 {
+        console.run_frame().unwrap();
+    }
+    assert_eq!(console.read_byte(NR52), 0xF2);
+}
+
+#[test]
+fn sweep_overflow_stops_channel_1() {
+    let mut console = load_console();
+    console.write_byte(NR12, 0xF0);
+
+    // The trigger calculates the first sweep right away, $7FF + $3FF overflows
+    console.write_byte(NR10, 0x11);
+    console.write_byte(NR13, 0xFF);
+    console.write_byte(NR14, 0x87);
+    assert_eq!(console.read_byte(NR52), 0xF0);
+
+    // $400 + $200 fits, the next sweep step writes it back and $600 + $300 overflows
+    console.write_byte(NR13, 0x00);
+    console.write_byte(NR14, 0x84);
+    assert_eq!(console.read_byte(NR52), 0xF1);
+    console.run_frame().unwrap();
+    assert_eq!(console.read_byte(NR52), 0xF0);
+}
+
+#[test]
+fn power_off_clears_registers() {
+    let mut console = load_console();
+    console.write_byte(NR50, 0x77);
+    console.write_byte(NR22, 0xF0);
+    console.write_byte(NR24, 0x80);
+    console.write_byte(0xFF30, 0x12);
+
+    console.write_byte(NR52, 0x00);
+    assert_eq!(console.read_byte(NR52), 0x70);
+    assert_eq!(console.read_byte(NR50), 0x00);
+    assert_eq!(console.read_byte(NR22), 0x00);
+
+    // Registers ignore writes while powered off, wave ram is kept
+    console.write_byte(NR50, 0x77);
+    assert_eq!(console.read_byte(NR50), 0x00);
+    assert_eq!(console.read_byte(0xFF30), 0x12);
+
+    console.write_byte(NR52, 0x80);
+    assert_eq!(console.read_byte(NR52), 0xF0);
+    console.write_byte(NR50, 0x77);
+    assert_eq!(console.read_byte(NR50), 0x77);
+}
+
+#[test]
+fn mixes_enabled_channels() {
+    let mut console = load_console();
+    console.write_byte(NR50, 0x77);
+    console.write_byte(NR51, 0xFF);
+    console.run_frame().unwrap();
+    assert_eq!(loudest_sample(&mut console), 0.0);
+
+    // A 50% duty square wave at about 1kHz on both sides
+    console.write_byte(NR21, 0x80);
+    console.write_byte(NR22, 0xF0);
+    console.write_byte(NR23, 0x83);
+    console.write_byte(NR24, 0x87);
+    console.run_frame().unwrap();
+    assert!(loudest_sample(&mut console) > 0.1);
+}
+
+// Fills wave ram with $00, $11, ... and starts the wave channel
+fn play_wave(console: &mut Console) {
+    for index in 0 .. 16 {
+        console.write_byte(0xFF30 + index, index as u8 * 0x11);
+    }
+    console.write_byte(NR30, 0x80);
+    console.write_byte(NR34, 0x80);
+    console.execute_ticks(100).unwrap();
+}
+
+#[test]
+fn wave_ram_is_blocked_while_playing() {
+    // The classic model reads $FF and drops writes
+    let mut console = load_console();
+    play_wave(&mut console);
+    assert_eq!(console.read_byte(0xFF32), 0xFF);
+    console.write_byte(0xFF32, 0x99);
+
+    console.write_byte(NR30, 0x00);
+    assert_eq!(console.read_byte(0xFF32), 0x22);
+
+    // The color model reads and writes the byte being played, whatever the address
+    let mut console = load_color_console();
+    play_wave(&mut console);
+    let playing = console.read_byte(0xFF30);
+    assert_eq!(console.read_byte(0xFF3F), playing);
+    console.write_byte(0xFF3F, 0x99);
+    assert_eq!(console.read_byte(0xFF30), 0x99);
+
+    console.write_byte(NR30, 0x00);
+    assert_eq!(console.read_byte(0xFF30 + (playing / 0x11) as u16), 0x99);
+}
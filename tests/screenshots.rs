@@ -0,0 +1,139 @@
+use std::env;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use rust_webpack_template::{SCREEN_H, SCREEN_W};
+
+mod common;
+
+fn render(rom_path: &str, frames: u32) -> Vec<u8> {
+    let mut console = common::load_file(rom_path);
+    for _i in 0 .. frames {
+        console.run_frame().unwrap();
+    }
+    return console.get_frame().to_vec();
+}
+
+// Renders a rom for a fixed number of frames and compares the last frame pixel by pixel against
+// tests/screenshots/<name>.png. On a mismatch a diff image is written next to the test binaries,
+// run with UPDATE_SCREENSHOTS=1 to replace the references after an intended rendering change.
+fn check_screenshot(rom_path: &str, name: &str, frames: u32) {
+    let frame = render(rom_path, frames);
+    let reference_path = Path::new("tests/screenshots").join(format!("{}.png", name));
+    if env::var("UPDATE_SCREENSHOTS").is_ok() {
+        write_png(&reference_path, &frame);
+        return;
+    }
+    compare(rom_path, name, &frame, &reference_path, |pixel| [pixel[0], pixel[1], pixel[2]]);
+}
+
+// Compares against the image published with the test rom in tests/screenshots/published, which
+// is never replaced. Only what the hardware can tell apart is compared, emulators and the
+// published images turn the shades and colors into different 8 bit values.
+fn check_published(rom_path: &str, name: &str, frames: u32, color: fn(&[u8]) -> [u8; 3]) {
+    let frame = render(rom_path, frames);
+    let reference_path = Path::new("tests/screenshots/published").join(format!("{}.png", name));
+    compare(rom_path, name, &frame, &reference_path, color);
+}
+
+fn compare(rom_path: &str, name: &str, frame: &[u8], reference_path: &Path, color: fn(&[u8]) -> [u8; 3]) {
+    let reference = read_png(reference_path);
+    let matches = |i: usize| color(&frame[i .. i + 3]) == color(&reference[i .. i + 3]);
+    let mismatches = (0 .. SCREEN_W * SCREEN_H).filter(|pixel| !matches(pixel * 4)).count();
+    if mismatches == 0 {
+        return;
+    }
+
+    let diff_path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(format!("{}-diff.png", name));
+    write_png(&diff_path, &diff_image(frame, matches));
+    panic!("{} differs from {} in {} pixels, see {}", rom_path, reference_path.display(), mismatches, diff_path.display());
+}
+
+// The 4 shades of the DMG, the published image uses $FF, $AA, $55 and $00
+fn dmg_shade(pixel: &[u8]) -> [u8; 3] {
+    let shade = match pixel[1] {
+        213 ..= 255 => 0,
+        128 ..= 212 => 1,
+        40 ..= 127 => 2,
+        _ => 3,
+    };
+    return [shade; 3];
+}
+
+// The 5 bits per channel of the CGB palettes
+fn cgb_color(pixel: &[u8]) -> [u8; 3] {
+    return [pixel[0] >> 3, pixel[1] >> 3, pixel[2] >> 3];
+}
+
+// Matching pixels are dimmed, mismatches are drawn in red
+fn diff_image(frame: &[u8], matches: impl Fn(usize) -> bool) -> Vec<u8> {
+    let mut diff = vec![0; SCREEN_W * SCREEN_H * 4];
+    for pixel in 0 .. SCREEN_W * SCREEN_H {
+        let i = pixel * 4;
+        if matches(i) {
+            let gray = ((frame[i] as u32 + frame[i + 1] as u32 + frame[i + 2] as u32) / 12) as u8;
+            diff[i .. i + 4].copy_from_slice(&[gray, gray, gray, 0xFF]);
+        } else {
+            diff[i .. i + 4].copy_from_slice(&[0xFF, 0x00, 0x00, 0xFF]);
+        }
+    }
+    return diff;
+}
+
+// Any 8 bit png as RGBA, the published images are not stored as RGBA
+fn read_png(path: &Path) -> Vec<u8> {
+    let file = File::open(path).unwrap_or_else(|error| panic!("unable to open {}: {}", path.display(), error));
+    let mut decoder = png::Decoder::new(file);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().unwrap();
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).unwrap();
+    assert_eq!((info.width as usize, info.height as usize), (SCREEN_W, SCREEN_H));
+
+    let channels = info.color_type.samples();
+    let mut pixels = Vec::with_capacity(SCREEN_W * SCREEN_H * 4);
+    for pixel in buffer[.. info.buffer_size()].chunks(channels) {
+        match info.color_type {
+            png::ColorType::Grayscale | png::ColorType::GrayscaleAlpha => pixels.extend_from_slice(&[pixel[0], pixel[0], pixel[0]]),
+            _ => pixels.extend_from_slice(&pixel[0 .. 3]),
+        }
+        pixels.push(0xFF);
+    }
+    return pixels;
+}
+
+fn write_png(path: &Path, pixels: &[u8]) {
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path).unwrap()), SCREEN_W as u32, SCREEN_H as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header().unwrap().write_image_data(pixels).unwrap();
+}
+
+// The acid2 tests, against reference-dmg.png from github.com/mattcurrie/dmg-acid2 and
+// reference.png from github.com/mattcurrie/cgb-acid2, saved as dmg-acid2.png and cgb-acid2.png
+// in tests/screenshots/published. The eyes, nose, mouth and hair are still missing.
+#[test]
+#[ignore = "ppu does not pass acid2 yet"]
+fn dmg_acid2() {
+    check_published("roms/dmg-acid2.gb", "dmg-acid2", 60, dmg_shade);
+}
+
+#[test]
+#[ignore = "ppu does not pass acid2 yet"]
+fn cgb_acid2() {
+    check_published("roms/cgb-acid2.gbc", "cgb-acid2", 60, cgb_color);
+}
+
+// Not the acid2 tests. These references are snapshots of the emulator's own, wrong, output and
+// only flag rendering changes nobody meant to make. A ppu fix is expected to change them, check
+// it against the acid2 tests above and then replace the snapshots with UPDATE_SCREENSHOTS=1.
+#[test]
+fn dmg_acid2_regression() {
+    check_screenshot("roms/dmg-acid2.gb", "dmg-acid2-regression", 60);
+}
+
+#[test]
+fn cgb_acid2_regression() {
+    check_screenshot("roms/cgb-acid2.gbc", "cgb-acid2-regression", 60);
+}
@@ -0,0 +1,102 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use rust_webpack_template::SerialDevice;
+
+mod common;
+
+// Replies with a fixed byte and remembers everything it received
+struct Recorder {
+    received: Rc<RefCell<Vec<u8>>>,
+    reply: u8,
+}
+
+impl SerialDevice for Recorder {
+    fn transfer(&mut self, byte: u8) -> u8 {
+        self.received.borrow_mut().push(byte);
+        return self.reply;
+    }
+}
+
+// Sends $55, echoes the byte it received and then sends the serial bit of IF
+fn build_rom(control: u8, color: bool) -> Vec<u8> {
+    let program = [
+        0x3E, 0x55, 0xE0, 0x01,       // ld a, $55; ldh [$01], a
+        0x3E, control, 0xE0, 0x02,    // ld a, control; ldh [$02], a
+        0xF0, 0x02, 0xCB, 0x7F,       // ldh a, [$02]; bit 7, a
+        0x20, 0xFA,                   // jr nz, -6
+        0xF0, 0x0F, 0xE6, 0x08, 0x47, // ldh a, [$0F]; and $08; ld b, a
+        0xF0, 0x01, 0xE0, 0x01,       // ldh a, [$01]; ldh [$01], a
+        0x3E, control, 0xE0, 0x02,    // ld a, control; ldh [$02], a
+        0xF0, 0x02, 0xCB, 0x7F,       // ldh a, [$02]; bit 7, a
+        0x20, 0xFA,                   // jr nz, -6
+        0x78, 0xE0, 0x01,             // ld a, b; ldh [$01], a
+        0x3E, control, 0xE0, 0x02,    // ld a, control; ldh [$02], a
+        0x18, 0xFE,                   // jr -2
+    ];
+
+    let mut rom = common::build_rom(&program);
+    rom[0x143] = if color { 0x80 } else { 0x00 };
+    return rom;
+}
+
+#[test]
+fn exchanges_bytes_with_device() {
+    let received = Rc::new(RefCell::new(Vec::new()));
+    let mut console = common::load_rom(&build_rom(0x81, false));
+    console.attach_serial_device(Box::new(Recorder { received: received.clone(), reply: 0x42 }));
+    console.run_frame().unwrap();
+
+    assert_eq!(*received.borrow(), vec![0x55, 0x42, 0x08]);
+    assert_eq!(console.take_serial_output(), vec![0x55, 0x42, 0x08]);
+    assert!(console.detach_serial_device().is_some());
+}
+
+#[test]
+fn shifts_in_ones_without_device() {
+    let mut console = common::load_rom(&build_rom(0x81, false));
+    console.run_frame().unwrap();
+    assert_eq!(console.take_serial_output(), vec![0x55, 0xFF, 0x08]);
+}
+
+#[test]
+fn transfer_takes_eight_bit_periods() {
+    // 8 bits at 8192Hz are 4096 cycles, the wait loop takes 32 cycles for 3 instructions
+    let mut console = common::load_rom(&build_rom(0x81, false));
+    console.execute_ticks(300).unwrap();
+    assert_eq!(console.take_serial_output(), vec![0x55]);
+    console.execute_ticks(200).unwrap();
+    assert_eq!(console.take_serial_output(), vec![0xFF]);
+
+    // The fast clock is ignored on DMG, on CGB it is 32 times faster
+    let mut console = common::load_rom(&build_rom(0x83, false));
+    console.execute_ticks(300).unwrap();
+    assert_eq!(console.take_serial_output(), vec![0x55]);
+
+    let mut console = common::load_rom(&build_rom(0x83, true));
+    console.execute_ticks(60).unwrap();
+    assert_eq!(console.take_serial_output(), vec![0x55, 0xFF, 0x08]);
+}
+
+#[test]
+fn external_clock_waits_for_peer() {
+    let program = [
+        0x3E, 0x66, 0xE0, 0x01,       // ld a, $66; ldh [$01], a
+        0x3E, 0x80, 0xE0, 0x02,       // ld a, $80; ldh [$02], a   external clock
+        0xF0, 0x02, 0xCB, 0x7F,       // ldh a, [$02]; bit 7, a
+        0x20, 0xFA,                   // jr nz, -6
+        0xF0, 0x01, 0xE0, 0x01,       // ldh a, [$01]; ldh [$01], a
+        0x3E, 0x80, 0xE0, 0x02,       // ld a, $80; ldh [$02], a
+        0x18, 0xFE,                   // jr -2
+    ];
+    let mut console = common::load_program(&program);
+
+    assert_eq!(console.external_serial_transfer(0x12, false), None);
+    console.run_frame().unwrap();
+    assert!(console.take_serial_output().is_empty());
+
+    assert_eq!(console.external_serial_transfer(0x12, false), Some(0x66));
+    assert_eq!(console.external_serial_transfer(0x34, false), None);
+    console.run_frame().unwrap();
+    assert_eq!(console.external_serial_transfer(0x34, false), Some(0x12));
+}
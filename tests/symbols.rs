@@ -0,0 +1,26 @@
+use rust_webpack_template::{SymbolTable, EmulatorError};
+
+#[test]
+fn parses_sym_files() {
+    let symbols = SymbolTable::parse("; File generated by rgblink
+
+00:0150 Main
+00:0158 Main.loop ; comment
+01:4000 Banked
+02:4000 OtherBank
+00:ff80 hFlag
+").unwrap();
+
+    assert_eq!(symbols.len(), 5);
+    assert_eq!(symbols.address("Banked"), Some((Some(1), 0x4000)));
+    assert_eq!(symbols.address("hFlag"), Some((None, 0xFF80)));
+    assert_eq!(symbols.label(2, 0x4000), Some("OtherBank"));
+    assert_eq!(symbols.label(3, 0x4000), None);
+
+    assert_eq!(symbols.locate(0, 0x015A), Some(("Main.loop", 2)));
+    assert_eq!(symbols.locate(1, 0x4010), Some(("Banked", 0x10)));
+    // Labels of another area are not used
+    assert_eq!(symbols.locate(0, 0xC000), None);
+
+    assert_eq!(SymbolTable::parse("00:0150 Main\n0150 Broken\n").unwrap_err(), EmulatorError::InvalidSymbols { line: 2 });
+}
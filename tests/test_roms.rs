@@ -0,0 +1,26 @@
+mod common;
+
+// Runs a test rom until it reports a result over the serial port or the frame budget runs out.
+// blargg's roms print their name, a line per sub test and finally a "Passed" or "Failed" line.
+fn run_serial_test(path: &str, frame_budget: u32) -> String {
+    let mut console = common::load_file(path);
+
+    let mut output = String::new();
+    for _i in 0 .. frame_budget {
+        console.run_frame().unwrap();
+        output.extend(console.take_serial_output().iter().map(|byte| *byte as char));
+        // The result is complete once its line is terminated
+        let result = output.rfind("Passed").or_else(|| output.rfind("Failed"));
+        if result.is_some_and(|index| output[index ..].contains('\n')) {
+            break;
+        }
+    }
+    return output;
+}
+
+#[test]
+fn blargg_cpu_instrs() {
+    // About a minute of emulated time
+    let output = run_serial_test("roms/cpu_instrs.gb", 4000);
+    assert!(output.contains("Passed all tests"), "cpu_instrs did not pass:\n{}", output);
+}
@@ -0,0 +1,50 @@
+use std::env;
+use std::fs;
+
+use rust_webpack_template::{FileTrace, TraceBuffer, TraceFilter};
+
+mod common;
+
+#[test]
+fn traces_in_gameboy_doctor_format() {
+    let mut console = common::load_counter();
+    let buffer = TraceBuffer::new(100);
+    console.start_trace(Box::new(buffer.clone()), None);
+    console.execute_ticks(3).unwrap();
+    assert_eq!(buffer.take_lines(), vec![
+        "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:06,00,04,CD",
+        "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0102 PCMEM:04,CD,00,02",
+        "A:01 F:10 B:01 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0103 PCMEM:CD,00,02,18",
+    ]);
+
+    // Only the routine, and only the last lines are kept
+    let buffer = TraceBuffer::new(2);
+    console.start_trace(Box::new(buffer.clone()), Some(TraceFilter::new(0x200, 0x2FF)));
+    console.execute_ticks(6).unwrap();
+    let lines = buffer.take_lines();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].contains("PC:0201 PCMEM:EA,00,C0,C9"));
+    assert!(lines[1].contains("PC:0204 PCMEM:C9"));
+
+    // Bank 0 is always mapped at $0200
+    console.start_trace(Box::new(buffer.clone()), Some(TraceFilter { start: 0x200, end: 0x2FF, bank: Some(1) }));
+    console.execute_ticks(20).unwrap();
+    assert!(buffer.is_empty());
+
+    console.stop_trace().unwrap();
+    assert!(!console.is_tracing());
+}
+
+#[test]
+fn traces_to_files() {
+    let path = env::temp_dir().join(format!("rustyboy-trace-{}.log", std::process::id()));
+    let mut console = common::load_counter();
+    console.start_trace(Box::new(FileTrace::create(&path).unwrap()), None);
+    console.execute_ticks(10).unwrap();
+    console.stop_trace().unwrap();
+
+    let text = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(text.lines().count(), 10);
+    assert_eq!(text.lines().next(), Some("A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:06,00,04,CD"));
+}